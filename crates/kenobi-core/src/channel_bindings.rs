@@ -1,5 +1,9 @@
 #[cfg(feature = "rustls")]
 use std::convert::Infallible;
+use std::{
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
 
 /// Defines a channel a Security Context can bind to using `bind_to_channel`
 ///
@@ -15,46 +19,314 @@ pub trait Channel {
     ///
     /// If you want to avoid defining an error, use `Infallible`
     fn channel_bindings(&self) -> Result<Option<Vec<u8>>, Self::Error>;
+    /// Channel bindings of the given type, including its prefix, or `None` if the channel does not offer them
+    ///
+    /// Defaults to `channel_bindings` for `tls-server-end-point` and `None` for other types
+    ///
+    /// # Errors
+    /// Like `channel_bindings`
+    fn channel_bindings_of(&self, kind: ChannelBindingType) -> Result<Option<Vec<u8>>, Self::Error> {
+        match kind {
+            ChannelBindingType::TlsServerEndPoint => self.channel_bindings(),
+            ChannelBindingType::TlsExporter | ChannelBindingType::TlsUnique => Ok(None),
+        }
+    }
+}
+
+/// Failure to bind a context to a channel
+#[derive(Debug)]
+pub enum BindError<E> {
+    Channel(E),
+    /// The channel offers no bindings of the requested type
+    Unavailable(ChannelBindingType),
+}
+impl<E: std::error::Error + 'static> std::error::Error for BindError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Channel(e) => Some(e),
+            Self::Unavailable(_) => None,
+        }
+    }
+}
+impl<E: Display> Display for BindError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Channel(e) => write!(f, "{e}"),
+            Self::Unavailable(kind) => write!(f, "the channel offers no {kind:?} bindings"),
+        }
+    }
+}
+
+/// The kinds of TLS channel bindings
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ChannelBindingType {
+    /// Hash of the server certificate (RFC 5929), the only type SSPI and older GSSAPI peers support
+    #[default]
+    TlsServerEndPoint,
+    /// Keying material exported from the TLS session (RFC 9266), the type TLS 1.3 expects
+    TlsExporter,
+    /// The first `Finished` message of a TLS 1.2 handshake (RFC 5929)
+    TlsUnique,
+}
+impl ChannelBindingType {
+    /// The prefix of the application data, identifying the type to the peer
+    #[must_use]
+    pub fn prefix(self) -> &'static [u8] {
+        match self {
+            Self::TlsServerEndPoint => b"tls-server-end-point:",
+            Self::TlsExporter => b"tls-exporter:",
+            Self::TlsUnique => b"tls-unique:",
+        }
+    }
+    /// Prepends the prefix to the raw binding `data`
+    #[must_use]
+    pub fn with_prefix(self, data: &[u8]) -> Vec<u8> {
+        [self.prefix(), data].concat()
+    }
+}
+
+/// How an acceptor treats the channel bindings of clients
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ChannelBindingPolicy {
+    /// Rejects clients that did not bind to the channel
+    Required,
+    /// Verifies the bindings of clients that sent them, and accepts clients that did not
+    #[default]
+    IfPresent,
+    /// Accepts clients regardless of their bindings
+    Ignore,
+}
+
+/// A network address of one side of the context, as bound by legacy peers
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Address {
+    Ipv4(Ipv4Addr),
+    Ipv6(Ipv6Addr),
+    /// An address of another family, identified by its `GSS_C_AF_*` type (RFC 2744)
+    Other {
+        address_type: u32,
+        address: Vec<u8>,
+    },
+}
+impl Address {
+    /// The `GSS_C_AF_*` type of the address, which SSPI uses as well
+    #[must_use]
+    pub fn address_type(&self) -> u32 {
+        match self {
+            Self::Ipv4(_) => 2,
+            Self::Ipv6(_) => 24,
+            Self::Other { address_type, .. } => *address_type,
+        }
+    }
+    /// The address in network byte order
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::Ipv4(address) => address.octets().to_vec(),
+            Self::Ipv6(address) => address.octets().to_vec(),
+            Self::Other { address, .. } => address.clone(),
+        }
+    }
+}
+impl From<IpAddr> for Address {
+    fn from(value: IpAddr) -> Self {
+        match value {
+            IpAddr::V4(address) => Self::Ipv4(address),
+            IpAddr::V6(address) => Self::Ipv6(address),
+        }
+    }
 }
 
-#[cfg(any(feature = "native-tls", feature = "rustls"))]
-const PREFIX: &[u8] = b"tls-server-end-point:";
+/// The complete channel bindings of a context (`gss_channel_bindings_struct`)
+///
+/// Both peers have to bind to the same addresses and application data
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ChannelBindings {
+    pub initiator_address: Option<Address>,
+    pub acceptor_address: Option<Address>,
+    /// The bindings of a [`Channel`], including their prefix
+    pub application_data: Option<Vec<u8>>,
+}
+impl ChannelBindings {
+    /// Whether the context is bound to nothing at all
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.initiator_address.is_none() && self.acceptor_address.is_none() && self.application_data.is_none()
+    }
+}
 
+/// Label of the keying material exported for `tls-exporter` bindings
+pub const TLS_EXPORTER_LABEL: &[u8] = b"EXPORTER-Channel-Binding";
+/// Length of the keying material exported for `tls-exporter` bindings
+pub const TLS_EXPORTER_LEN: usize = 32;
+
+/// Works on both sides of the connection, as native-tls hashes the certificate of the server in either case
 #[cfg(feature = "native-tls")]
 impl<S: std::io::Read + std::io::Write> Channel for native_tls::TlsStream<S> {
     type Error = native_tls::Error;
     fn channel_bindings(&self) -> Result<Option<Vec<u8>>, Self::Error> {
-        match self.tls_server_end_point() {
-            Ok(Some(v)) => {
-                let mut vec = Vec::with_capacity(PREFIX.len() + v.len());
-                vec.extend_from_slice(PREFIX);
-                vec.extend_from_slice(&v);
-                Ok(Some(vec))
-            }
-            other => other,
-        }
+        Ok(self
+            .tls_server_end_point()?
+            .map(|hash| ChannelBindingType::TlsServerEndPoint.with_prefix(&hash)))
     }
 }
 #[cfg(feature = "rustls")]
 impl Channel for rustls::ClientConnection {
-    type Error = Infallible;
+    type Error = rustls::Error;
     fn channel_bindings(&self) -> Result<Option<Vec<u8>>, Self::Error> {
         Ok(self
             .peer_certificates()
             .and_then(|peers| peers.first())
-            .and_then(|p| p.channel_bindings().unwrap()))
+            .map(|p| tls_server_end_point(p)))
+    }
+    fn channel_bindings_of(&self, kind: ChannelBindingType) -> Result<Option<Vec<u8>>, Self::Error> {
+        match kind {
+            ChannelBindingType::TlsServerEndPoint => self.channel_bindings(),
+            ChannelBindingType::TlsExporter => tls_exporter(self),
+            ChannelBindingType::TlsUnique => Ok(None),
+        }
+    }
+}
+/// Offers no `tls-server-end-point` bindings, since rustls does not expose the certificate the server presented.
+/// Bind servers through a [`ServerChannel`] instead, which only takes `tls-exporter` bindings from the connection
+#[cfg(feature = "rustls")]
+impl Channel for rustls::ServerConnection {
+    type Error = rustls::Error;
+    fn channel_bindings(&self) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(None)
+    }
+    fn channel_bindings_of(&self, kind: ChannelBindingType) -> Result<Option<Vec<u8>>, Self::Error> {
+        match kind {
+            ChannelBindingType::TlsServerEndPoint => self.channel_bindings(),
+            ChannelBindingType::TlsExporter => tls_exporter(self),
+            ChannelBindingType::TlsUnique => Ok(None),
+        }
+    }
+}
+#[cfg(feature = "rustls")]
+/// Only TLS 1.3 exporters are fit for bindings (RFC 9266), like for openssl
+fn tls_exporter<Data>(connection: &rustls::ConnectionCommon<Data>) -> Result<Option<Vec<u8>>, rustls::Error> {
+    if connection.protocol_version() != Some(rustls::ProtocolVersion::TLSv1_3) {
+        return Ok(None);
+    }
+    let material = connection.export_keying_material([0; TLS_EXPORTER_LEN], TLS_EXPORTER_LABEL, None)?;
+    Ok(Some(ChannelBindingType::TlsExporter.with_prefix(&material)))
+}
+
+/// A server side connection together with the DER encoded certificate it presents to clients
+///
+/// The way to bind rustls servers, whose connections do not know their own certificate.
+/// `tls-server-end-point` bindings are computed from the certificate, all other types are taken from the connection
+#[cfg(feature = "rustls")]
+#[derive(Clone, Copy, Debug)]
+pub struct ServerChannel<'a, C> {
+    connection: &'a C,
+    certificate: &'a [u8],
+}
+#[cfg(feature = "rustls")]
+impl<'a, C> ServerChannel<'a, C> {
+    #[must_use]
+    pub fn new(connection: &'a C, certificate: &'a [u8]) -> Self {
+        Self {
+            connection,
+            certificate,
+        }
+    }
+}
+#[cfg(feature = "rustls")]
+impl<C: Channel> Channel for ServerChannel<'_, C> {
+    type Error = C::Error;
+    fn channel_bindings(&self) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(Some(tls_server_end_point(self.certificate)))
+    }
+    fn channel_bindings_of(&self, kind: ChannelBindingType) -> Result<Option<Vec<u8>>, Self::Error> {
+        match kind {
+            ChannelBindingType::TlsServerEndPoint => self.channel_bindings(),
+            kind => self.connection.channel_bindings_of(kind),
+        }
+    }
+}
+
+#[cfg(feature = "tokio-rustls")]
+impl<IO> Channel for tokio_rustls::client::TlsStream<IO> {
+    type Error = rustls::Error;
+    fn channel_bindings(&self) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.get_ref().1.channel_bindings()
+    }
+    fn channel_bindings_of(&self, kind: ChannelBindingType) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.get_ref().1.channel_bindings_of(kind)
+    }
+}
+/// Like [`rustls::ServerConnection`], use a [`ServerChannel`] for `tls-server-end-point`
+#[cfg(feature = "tokio-rustls")]
+impl<IO> Channel for tokio_rustls::server::TlsStream<IO> {
+    type Error = rustls::Error;
+    fn channel_bindings(&self) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.get_ref().1.channel_bindings()
+    }
+    fn channel_bindings_of(&self, kind: ChannelBindingType) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.get_ref().1.channel_bindings_of(kind)
+    }
+}
+#[cfg(feature = "tokio-native-tls")]
+impl<S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin> Channel for tokio_native_tls::TlsStream<S> {
+    type Error = native_tls::Error;
+    fn channel_bindings(&self) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.get_ref().channel_bindings()
     }
 }
 #[cfg(feature = "rustls")]
 impl Channel for rustls::pki_types::CertificateDer<'_> {
     type Error = Infallible;
     fn channel_bindings(&self) -> Result<Option<Vec<u8>>, Self::Error> {
-        let hash = tls_server_end_point_digest(self);
-        Ok::<_, std::convert::Infallible>(Some([PREFIX, hash.as_ref()].concat()))
+        Ok(Some(tls_server_end_point(self)))
     }
 }
 
-#[cfg(feature = "rustls")]
+/// Uses the certificate of the server on both sides of the connection, and offers `tls-exporter` for TLS 1.3
+#[cfg(feature = "openssl")]
+impl Channel for openssl::ssl::SslRef {
+    type Error = openssl::error::ErrorStack;
+    fn channel_bindings(&self) -> Result<Option<Vec<u8>>, Self::Error> {
+        let certificate = if self.is_server() {
+            self.certificate().map(openssl::x509::X509Ref::to_der)
+        } else {
+            self.peer_certificate().map(|certificate| certificate.to_der())
+        };
+        Ok(certificate.transpose()?.map(|der| tls_server_end_point(&der)))
+    }
+    fn channel_bindings_of(&self, kind: ChannelBindingType) -> Result<Option<Vec<u8>>, Self::Error> {
+        match kind {
+            ChannelBindingType::TlsServerEndPoint => self.channel_bindings(),
+            ChannelBindingType::TlsExporter if self.version2() == Some(openssl::ssl::SslVersion::TLS1_3) => {
+                let mut material = [0; TLS_EXPORTER_LEN];
+                let label = std::str::from_utf8(TLS_EXPORTER_LABEL).expect("label is ASCII");
+                self.export_keying_material(&mut material, label, None)?;
+                Ok(Some(kind.with_prefix(&material)))
+            }
+            ChannelBindingType::TlsExporter | ChannelBindingType::TlsUnique => Ok(None),
+        }
+    }
+}
+#[cfg(feature = "openssl")]
+impl<S> Channel for openssl::ssl::SslStream<S> {
+    type Error = openssl::error::ErrorStack;
+    fn channel_bindings(&self) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.ssl().channel_bindings()
+    }
+    fn channel_bindings_of(&self, kind: ChannelBindingType) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.ssl().channel_bindings_of(kind)
+    }
+}
+
+/// Computes the `tls-server-end-point` channel bindings for the DER encoded certificate of the server
+#[cfg(any(feature = "rustls", feature = "openssl"))]
+#[must_use]
+pub fn tls_server_end_point(cert_der: &[u8]) -> Vec<u8> {
+    ChannelBindingType::TlsServerEndPoint.with_prefix(&tls_server_end_point_digest(cert_der))
+}
+
+#[cfg(any(feature = "rustls", feature = "openssl"))]
 fn tls_server_end_point_digest(cert_der: &[u8]) -> Vec<u8> {
     use sha2::{Digest, Sha256, Sha384, Sha512};
     use x509_parser::{
@@ -0,0 +1,149 @@
+//! Minimal reader for the DER subset used by GSSAPI tokens
+
+use std::fmt::Display;
+
+pub(crate) const INTEGER: u8 = 0x02;
+pub(crate) const BIT_STRING: u8 = 0x03;
+pub(crate) const OCTET_STRING: u8 = 0x04;
+pub(crate) const OID: u8 = 0x06;
+pub(crate) const ENUMERATED: u8 = 0x0a;
+pub(crate) const GENERALIZED_TIME: u8 = 0x18;
+pub(crate) const GENERAL_STRING: u8 = 0x1b;
+pub(crate) const SEQUENCE: u8 = 0x30;
+
+/// Tag of the constructed, context specific field `[number]`
+pub(crate) const fn context(number: u8) -> u8 {
+    0xa0 | number
+}
+/// Tag of the constructed, application specific type `[APPLICATION number]`
+pub(crate) const fn application(number: u8) -> u8 {
+    0x60 | number
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The token ended in the middle of an element
+    Truncated,
+    /// An element had a different type than expected
+    UnexpectedTag { expected: u8, found: u8 },
+    /// Indefinite lengths or multi-byte tags, which GSSAPI tokens never use
+    Unsupported,
+    /// An element had a value outside of its definition
+    InvalidValue,
+    /// Bytes followed the last element
+    TrailingData,
+}
+impl std::error::Error for DecodeError {}
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "token ended unexpectedly"),
+            Self::UnexpectedTag { expected, found } => {
+                write!(f, "expected element with tag {expected:#04x}, found {found:#04x}")
+            }
+            Self::Unsupported => write!(f, "encoding is not valid DER"),
+            Self::InvalidValue => write!(f, "element has an invalid value"),
+            Self::TrailingData => write!(f, "unexpected data after the last element"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    pub(crate) fn rest(self) -> &'a [u8] {
+        self.data
+    }
+    pub(crate) fn peek_tag(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+    /// Reads any element, returning its tag and contents
+    pub(crate) fn read_any(&mut self) -> Result<(u8, &'a [u8]), DecodeError> {
+        let [tag, length, rest @ ..] = self.data else {
+            return Err(DecodeError::Truncated);
+        };
+        if tag & 0x1f == 0x1f {
+            return Err(DecodeError::Unsupported);
+        }
+        let (length, rest) = match *length {
+            short @ 0..=0x7f => (usize::from(short), rest),
+            0x81..=0x84 => {
+                let count = usize::from(length & 0x7f);
+                let bytes = rest.get(..count).ok_or(DecodeError::Truncated)?;
+                let length = bytes.iter().fold(0, |length, b| length << 8 | usize::from(*b));
+                (length, &rest[count..])
+            }
+            _ => return Err(DecodeError::Unsupported),
+        };
+        let contents = rest.get(..length).ok_or(DecodeError::Truncated)?;
+        self.data = &rest[length..];
+        Ok((*tag, contents))
+    }
+    pub(crate) fn read(&mut self, tag: u8) -> Result<&'a [u8], DecodeError> {
+        let (found, contents) = self.read_any()?;
+        if found != tag {
+            return Err(DecodeError::UnexpectedTag { expected: tag, found });
+        }
+        Ok(contents)
+    }
+    /// Reads the element if it has the given tag
+    pub(crate) fn read_optional(&mut self, tag: u8) -> Result<Option<&'a [u8]>, DecodeError> {
+        if self.peek_tag() != Some(tag) {
+            return Ok(None);
+        }
+        self.read(tag).map(Some)
+    }
+    /// Reads an explicitly tagged field and the single element inside of it
+    pub(crate) fn read_field(&mut self, number: u8, tag: u8) -> Result<&'a [u8], DecodeError> {
+        Reader::new(self.read(context(number))?).read_single(tag)
+    }
+    pub(crate) fn read_optional_field(&mut self, number: u8, tag: u8) -> Result<Option<&'a [u8]>, DecodeError> {
+        self.read_optional(context(number))?
+            .map(|field| Reader::new(field).read_single(tag))
+            .transpose()
+    }
+    /// Reads the only remaining element
+    pub(crate) fn read_single(mut self, tag: u8) -> Result<&'a [u8], DecodeError> {
+        let contents = self.read(tag)?;
+        self.finish()?;
+        Ok(contents)
+    }
+    pub(crate) fn finish(self) -> Result<(), DecodeError> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(DecodeError::TrailingData)
+        }
+    }
+}
+
+/// Decodes the contents of a non-negative INTEGER or ENUMERATED that fits into a `u32`
+pub(crate) fn unsigned(contents: &[u8]) -> Result<u32, DecodeError> {
+    let contents = match contents {
+        [] => return Err(DecodeError::InvalidValue),
+        [0, rest @ ..] if !rest.is_empty() => rest,
+        [first, ..] if first & 0x80 != 0 => return Err(DecodeError::InvalidValue),
+        contents => contents,
+    };
+    if contents.len() > 4 {
+        return Err(DecodeError::InvalidValue);
+    }
+    Ok(contents.iter().fold(0, |value, b| value << 8 | u32::from(*b)))
+}
+
+/// Decodes the contents of an INTEGER that fits into an `i32`
+pub(crate) fn signed(contents: &[u8]) -> Result<i32, DecodeError> {
+    if contents.is_empty() || contents.len() > 4 {
+        return Err(DecodeError::InvalidValue);
+    }
+    let sign = if contents[0] & 0x80 == 0 { 0 } else { -1 };
+    Ok(contents.iter().fold(sign, |value, b| value << 8 | i32::from(*b)))
+}
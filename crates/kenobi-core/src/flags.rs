@@ -8,6 +8,10 @@ pub struct CapabilityFlags(u32);
 impl CapabilityFlags {
     pub const DELEGATE: Self = Self(0x1);
     pub const MUTUAL_AUTH: Self = Self(0x02);
+    /// Detect messages and signatures that were received before
+    pub const REPLAY_DETECT: Self = Self(0x04);
+    /// Delegate only if the KDC marked the target service as OK-as-delegate
+    pub const DELEGATE_IF_OK_AS_DELEGATE: Self = Self(0x8000);
     pub const CONFIDENTIALITY: Self = Self(0x10);
     pub const INTEGRITY: Self = Self(0x20000);
     pub const fn contains_all(self, other: Self) -> bool {
@@ -29,9 +33,15 @@ impl Display for CapabilityFlags {
         if self.contains_all(Self::DELEGATE) {
             strings.push("DELEGATE");
         }
+        if self.contains_all(Self::DELEGATE_IF_OK_AS_DELEGATE) {
+            strings.push("DELEGATE_IF_OK_AS_DELEGATE");
+        }
         if self.contains_all(Self::MUTUAL_AUTH) {
             strings.push("MUTUAL_AUTH");
         }
+        if self.contains_all(Self::REPLAY_DETECT) {
+            strings.push("REPLAY_DETECT");
+        }
         if self.contains_all(Self::INTEGRITY) {
             strings.push("INTEGRITY");
         }
@@ -0,0 +1,309 @@
+//! Decoding of the unencrypted parts of Kerberos context tokens (RFC 4120, RFC 4121)
+//!
+//! Shows which service principal, key version and encryption type a client presented,
+//! or why the peer refused the context, without needing any keys.
+
+use std::fmt::Display;
+
+pub use crate::der::DecodeError;
+use crate::{
+    der::{self, BIT_STRING, GENERAL_STRING, GENERALIZED_TIME, INTEGER, OCTET_STRING, Reader, SEQUENCE, application},
+    spnego::InitialContextToken,
+};
+
+const PVNO: i32 = 5;
+const TOK_AP_REQ: [u8; 2] = [0x01, 0x00];
+const TOK_AP_REP: [u8; 2] = [0x02, 0x00];
+const TOK_ERROR: [u8; 2] = [0x03, 0x00];
+
+/// A Kerberos encryption type number
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EncryptionType(pub i32);
+impl EncryptionType {
+    #[must_use]
+    pub fn name(self) -> Option<&'static str> {
+        Some(match self.0 {
+            1 => "des-cbc-crc",
+            3 => "des-cbc-md5",
+            16 => "des3-cbc-sha1",
+            17 => "aes128-cts-hmac-sha1-96",
+            18 => "aes256-cts-hmac-sha1-96",
+            19 => "aes128-cts-hmac-sha256-128",
+            20 => "aes256-cts-hmac-sha384-192",
+            23 => "rc4-hmac",
+            24 => "rc4-hmac-exp",
+            25 => "camellia128-cts-cmac",
+            26 => "camellia256-cts-cmac",
+            _ => return None,
+        })
+    }
+}
+impl Display for EncryptionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{name} ({})", self.0),
+            None => write!(f, "unknown encryption type ({})", self.0),
+        }
+    }
+}
+
+/// A `KRB-ERROR` error code
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ErrorCode(pub i32);
+impl ErrorCode {
+    #[must_use]
+    pub fn name(self) -> Option<&'static str> {
+        Some(match self.0 {
+            6 => "KDC_ERR_C_PRINCIPAL_UNKNOWN",
+            7 => "KDC_ERR_S_PRINCIPAL_UNKNOWN",
+            12 => "KDC_ERR_POLICY",
+            13 => "KDC_ERR_BADOPTION",
+            14 => "KDC_ERR_ETYPE_NOSUPP",
+            18 => "KDC_ERR_CLIENT_REVOKED",
+            23 => "KDC_ERR_KEY_EXPIRED",
+            24 => "KDC_ERR_PREAUTH_FAILED",
+            25 => "KDC_ERR_PREAUTH_REQUIRED",
+            31 => "KRB_AP_ERR_BAD_INTEGRITY",
+            32 => "KRB_AP_ERR_TKT_EXPIRED",
+            33 => "KRB_AP_ERR_TKT_NYV",
+            34 => "KRB_AP_ERR_REPEAT",
+            35 => "KRB_AP_ERR_NOT_US",
+            36 => "KRB_AP_ERR_BADMATCH",
+            37 => "KRB_AP_ERR_SKEW",
+            41 => "KRB_AP_ERR_MODIFIED",
+            44 => "KRB_AP_ERR_BADKEYVER",
+            45 => "KRB_AP_ERR_NOKEY",
+            52 => "KRB_ERR_RESPONSE_TOO_BIG",
+            60 => "KRB_ERR_GENERIC",
+            68 => "KDC_ERR_WRONG_REALM",
+            _ => return None,
+        })
+    }
+}
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{name} ({})", self.0),
+            None => write!(f, "unknown error ({})", self.0),
+        }
+    }
+}
+
+/// Options of an `AP-REQ`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ApOptions(u32);
+impl ApOptions {
+    pub const USE_SESSION_KEY: Self = Self(0x4000_0000);
+    pub const MUTUAL_REQUIRED: Self = Self(0x2000_0000);
+    #[must_use]
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+    #[must_use]
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+    fn decode(bits: &[u8]) -> Result<Self, DecodeError> {
+        let [unused, bytes @ ..] = bits else {
+            return Err(DecodeError::InvalidValue);
+        };
+        if *unused > 7 {
+            return Err(DecodeError::InvalidValue);
+        }
+        let mut value = [0; 4];
+        let len = bytes.len().min(4);
+        value[..len].copy_from_slice(&bytes[..len]);
+        Ok(Self(u32::from_be_bytes(value)))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrincipalName<'a> {
+    pub name_type: i32,
+    pub components: Vec<&'a str>,
+}
+impl<'a> PrincipalName<'a> {
+    fn decode(contents: &'a [u8]) -> Result<Self, DecodeError> {
+        let mut fields = Reader::new(contents);
+        let name_type = der::signed(fields.read_field(0, INTEGER)?)?;
+        let mut strings = Reader::new(fields.read_field(1, SEQUENCE)?);
+        fields.finish()?;
+        let mut components = Vec::new();
+        while !strings.is_empty() {
+            components.push(string(strings.read(GENERAL_STRING)?)?);
+        }
+        Ok(Self { name_type, components })
+    }
+}
+impl Display for PrincipalName<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.components.join("/"))
+    }
+}
+
+/// Encrypted part of a message, of which only the key it was encrypted with is known
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EncryptedData<'a> {
+    pub etype: EncryptionType,
+    /// Version of the key, absent for session keys
+    pub kvno: Option<u32>,
+    pub cipher: &'a [u8],
+}
+impl<'a> EncryptedData<'a> {
+    fn decode(contents: &'a [u8]) -> Result<Self, DecodeError> {
+        let mut fields = Reader::new(contents);
+        let etype = EncryptionType(der::signed(fields.read_field(0, INTEGER)?)?);
+        let kvno = fields.read_optional_field(1, INTEGER)?.map(der::unsigned).transpose()?;
+        let cipher = fields.read_field(2, OCTET_STRING)?;
+        fields.finish()?;
+        Ok(Self { etype, kvno, cipher })
+    }
+}
+
+/// The service ticket presented in an `AP-REQ`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ticket<'a> {
+    pub realm: &'a str,
+    pub sname: PrincipalName<'a>,
+    /// Encrypted with the long-term key of `sname`
+    pub enc_part: EncryptedData<'a>,
+}
+impl<'a> Ticket<'a> {
+    fn decode(contents: &'a [u8]) -> Result<Self, DecodeError> {
+        let mut fields = Reader::new(Reader::new(contents).read_single(SEQUENCE)?);
+        if der::signed(fields.read_field(0, INTEGER)?)? != PVNO {
+            return Err(DecodeError::InvalidValue);
+        }
+        let realm = string(fields.read_field(1, GENERAL_STRING)?)?;
+        let sname = PrincipalName::decode(fields.read_field(2, SEQUENCE)?)?;
+        let enc_part = EncryptedData::decode(fields.read_field(3, SEQUENCE)?)?;
+        fields.finish()?;
+        Ok(Self { realm, sname, enc_part })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApReq<'a> {
+    pub ap_options: ApOptions,
+    pub ticket: Ticket<'a>,
+    /// Encrypted with the session key of the ticket
+    pub authenticator: EncryptedData<'a>,
+}
+impl<'a> ApReq<'a> {
+    fn decode(fields: &mut Reader<'a>) -> Result<Self, DecodeError> {
+        let ap_options = ApOptions::decode(fields.read_field(2, BIT_STRING)?)?;
+        let ticket = Ticket::decode(fields.read_field(3, application(1))?)?;
+        let authenticator = EncryptedData::decode(fields.read_field(4, SEQUENCE)?)?;
+        Ok(Self {
+            ap_options,
+            ticket,
+            authenticator,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ApRep<'a> {
+    /// Encrypted with the session key of the ticket
+    pub enc_part: EncryptedData<'a>,
+}
+impl<'a> ApRep<'a> {
+    fn decode(fields: &mut Reader<'a>) -> Result<Self, DecodeError> {
+        let enc_part = EncryptedData::decode(fields.read_field(2, SEQUENCE)?)?;
+        Ok(Self { enc_part })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KrbError<'a> {
+    pub error_code: ErrorCode,
+    /// The time of the peer as `YYYYMMDDHHMMSSZ`, useful for diagnosing clock skew
+    pub stime: &'a str,
+    pub crealm: Option<&'a str>,
+    pub cname: Option<PrincipalName<'a>>,
+    pub realm: &'a str,
+    pub sname: PrincipalName<'a>,
+    pub e_text: Option<&'a str>,
+    pub e_data: Option<&'a [u8]>,
+}
+impl<'a> KrbError<'a> {
+    fn decode(fields: &mut Reader<'a>) -> Result<Self, DecodeError> {
+        fields.read_optional_field(2, GENERALIZED_TIME)?;
+        fields.read_optional_field(3, INTEGER)?;
+        let stime = string(fields.read_field(4, GENERALIZED_TIME)?)?;
+        fields.read_field(5, INTEGER)?;
+        let error_code = ErrorCode(der::signed(fields.read_field(6, INTEGER)?)?);
+        let crealm = fields.read_optional_field(7, GENERAL_STRING)?.map(string).transpose()?;
+        let cname = fields
+            .read_optional_field(8, SEQUENCE)?
+            .map(PrincipalName::decode)
+            .transpose()?;
+        let realm = string(fields.read_field(9, GENERAL_STRING)?)?;
+        let sname = PrincipalName::decode(fields.read_field(10, SEQUENCE)?)?;
+        let e_text = fields
+            .read_optional_field(11, GENERAL_STRING)?
+            .map(string)
+            .transpose()?;
+        let e_data = fields.read_optional_field(12, OCTET_STRING)?;
+        Ok(Self {
+            error_code,
+            stime,
+            crealm,
+            cname,
+            realm,
+            sname,
+            e_text,
+            e_data,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KrbMessage<'a> {
+    ApReq(ApReq<'a>),
+    ApRep(ApRep<'a>),
+    Error(KrbError<'a>),
+}
+impl<'a> KrbMessage<'a> {
+    /// Decodes a Kerberos message, either inside the GSSAPI framing of the krb5 mechanism or bare
+    ///
+    /// # Errors
+    /// Fails if `token` is no `AP-REQ`, `AP-REP` or `KRB-ERROR`
+    pub fn decode(token: &'a [u8]) -> Result<Self, DecodeError> {
+        let message = match InitialContextToken::decode(token) {
+            Ok(initial) if initial.this_mech.is_kerberos() => {
+                let (tok_id, message) = initial
+                    .inner_token
+                    .split_first_chunk::<2>()
+                    .ok_or(DecodeError::Truncated)?;
+                if ![TOK_AP_REQ, TOK_AP_REP, TOK_ERROR].contains(tok_id) {
+                    return Err(DecodeError::InvalidValue);
+                }
+                message
+            }
+            // Tokens of other mechanisms
+            Ok(_) => return Err(DecodeError::InvalidValue),
+            Err(_) => token,
+        };
+        let mut reader = Reader::new(message);
+        let (tag, contents) = reader.read_any()?;
+        reader.finish()?;
+        let mut fields = Reader::new(Reader::new(contents).read_single(SEQUENCE)?);
+        if der::signed(fields.read_field(0, INTEGER)?)? != PVNO {
+            return Err(DecodeError::InvalidValue);
+        }
+        let msg_type = der::signed(fields.read_field(1, INTEGER)?)?;
+        let decoded = match tag {
+            t if t == application(14) && msg_type == 14 => Self::ApReq(ApReq::decode(&mut fields)?),
+            t if t == application(15) && msg_type == 15 => Self::ApRep(ApRep::decode(&mut fields)?),
+            t if t == application(30) && msg_type == 30 => Self::Error(KrbError::decode(&mut fields)?),
+            _ => return Err(DecodeError::InvalidValue),
+        };
+        fields.finish()?;
+        Ok(decoded)
+    }
+}
+
+fn string(contents: &[u8]) -> Result<&str, DecodeError> {
+    std::str::from_utf8(contents).map_err(|_| DecodeError::InvalidValue)
+}
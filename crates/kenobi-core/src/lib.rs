@@ -1,5 +1,10 @@
 pub mod channel_bindings;
 pub mod cred;
+mod der;
 pub mod flags;
+pub mod krb5;
 pub mod mech;
+pub mod prf;
+pub mod qop;
+pub mod spnego;
 pub mod typestate;
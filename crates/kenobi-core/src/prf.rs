@@ -0,0 +1,8 @@
+/// Selects the key `gss_pseudo_random` derives its output from (RFC 4401)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrfKey {
+    /// The initiator's subkey if negotiated, the session key otherwise
+    Partial,
+    /// The acceptor's subkey if negotiated, falling back like `Partial` otherwise
+    Full,
+}
@@ -0,0 +1,21 @@
+/// Quality of protection, selecting among the algorithms a mechanism offers for a message
+///
+/// The values are mechanism specific, most mechanisms (including Kerberos) only support [`Qop::DEFAULT`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Qop(u32);
+impl Qop {
+    pub const DEFAULT: Self = Self(0);
+    #[must_use]
+    pub const fn new(value: u32) -> Self {
+        Self(value)
+    }
+    #[must_use]
+    pub const fn value(self) -> u32 {
+        self.0
+    }
+}
+impl std::fmt::Display for Qop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#x}", self.0)
+    }
+}
@@ -0,0 +1,307 @@
+//! Decoding of SPNEGO tokens (RFC 4178) and the GSSAPI token framing (RFC 2743), without calling into GSSAPI/SSPI
+//!
+//! Meant for inspecting what a peer sent, e.g. to log the offered mechanisms or to reject NTLM
+//! before handing the token to `gss_accept_sec_context`/`AcceptSecurityContext`.
+//! The tokens are not verified in any way.
+
+use std::fmt::Display;
+
+pub use crate::der::DecodeError;
+use crate::der::{
+    self, BIT_STRING, ENUMERATED, GENERAL_STRING, OCTET_STRING, OID, Reader, SEQUENCE, application, context,
+};
+
+/// An object identifier, kept in its DER encoding
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Oid<'a>(&'a [u8]);
+impl<'a> Oid<'a> {
+    /// 1.3.6.1.5.5.2
+    pub const SPNEGO: Oid<'static> = Oid(&[0x2b, 0x06, 0x01, 0x05, 0x05, 0x02]);
+    /// 1.2.840.113554.1.2.2
+    pub const KERBEROS_V5: Oid<'static> = Oid(&[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x12, 0x01, 0x02, 0x02]);
+    /// 1.2.840.48018.1.2.2, the OID older Windows versions use for Kerberos
+    pub const KERBEROS_V5_LEGACY: Oid<'static> = Oid(&[0x2a, 0x86, 0x48, 0x82, 0xf7, 0x12, 0x01, 0x02, 0x02]);
+    /// 1.2.840.113554.1.2.2.3
+    pub const KERBEROS_V5_USER_TO_USER: Oid<'static> =
+        Oid(&[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x12, 0x01, 0x02, 0x02, 0x03]);
+    /// 1.3.6.1.4.1.311.2.2.10
+    pub const NTLM: Oid<'static> = Oid(&[0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x02, 0x0a]);
+    /// 1.3.6.1.4.1.311.2.2.30
+    pub const NEGOEX: Oid<'static> = Oid(&[0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x02, 0x1e]);
+
+    /// Wraps the contents of a DER encoded OID, without tag and length
+    #[must_use]
+    pub const fn from_der(contents: &'a [u8]) -> Self {
+        Self(contents)
+    }
+    #[must_use]
+    pub fn as_der(&self) -> &'a [u8] {
+        self.0
+    }
+    #[must_use]
+    pub fn is_kerberos(&self) -> bool {
+        [Oid::KERBEROS_V5, Oid::KERBEROS_V5_LEGACY, Oid::KERBEROS_V5_USER_TO_USER].contains(self)
+    }
+    fn arcs(&self) -> impl Iterator<Item = u64> {
+        let mut bytes = self.0.iter();
+        std::iter::from_fn(move || {
+            let mut arc = 0u64;
+            for byte in bytes.by_ref() {
+                arc = arc << 7 | u64::from(byte & 0x7f);
+                if byte & 0x80 == 0 {
+                    return Some(arc);
+                }
+            }
+            None
+        })
+    }
+}
+impl Display for Oid<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut arcs = self.arcs();
+        let Some(first) = arcs.next() else {
+            return Ok(());
+        };
+        match first {
+            0..40 => write!(f, "0.{first}")?,
+            40..80 => write!(f, "1.{}", first - 40)?,
+            _ => write!(f, "2.{}", first - 80)?,
+        }
+        arcs.try_for_each(|arc| write!(f, ".{arc}"))
+    }
+}
+
+/// The framing of the first token of every mechanism (RFC 2743 3.1)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InitialContextToken<'a> {
+    pub this_mech: Oid<'a>,
+    pub inner_token: &'a [u8],
+}
+impl<'a> InitialContextToken<'a> {
+    /// # Errors
+    /// Fails if `token` is not framed as an initial context token
+    pub fn decode(token: &'a [u8]) -> Result<Self, DecodeError> {
+        let mut outer = Reader::new(token);
+        let mut contents = Reader::new(outer.read(application(0))?);
+        outer.finish()?;
+        let this_mech = Oid(contents.read(OID)?);
+        Ok(Self {
+            this_mech,
+            inner_token: contents.rest(),
+        })
+    }
+}
+
+/// Flags the initiator requested, as sent in `reqFlags`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ContextFlags(u8);
+impl ContextFlags {
+    pub const DELEGATION: Self = Self(0x80);
+    pub const MUTUAL: Self = Self(0x40);
+    pub const REPLAY: Self = Self(0x20);
+    pub const SEQUENCE: Self = Self(0x10);
+    pub const ANONYMOUS: Self = Self(0x08);
+    pub const CONFIDENTIALITY: Self = Self(0x04);
+    pub const INTEGRITY: Self = Self(0x02);
+    #[must_use]
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+    fn decode(bits: &[u8]) -> Result<Self, DecodeError> {
+        match bits {
+            [unused, ..] if *unused > 7 => Err(DecodeError::InvalidValue),
+            [_] => Ok(Self::default()),
+            [_, flags, ..] => Ok(Self(*flags)),
+            [] => Err(DecodeError::InvalidValue),
+        }
+    }
+}
+
+/// Hints of `NegTokenInit2`, the variant of `NegTokenInit` Windows servers send
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NegHints<'a> {
+    pub hint_name: Option<&'a [u8]>,
+    pub hint_address: Option<&'a [u8]>,
+}
+
+/// The first SPNEGO token, offering mechanisms in order of preference
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NegTokenInit<'a> {
+    pub mech_types: Vec<Oid<'a>>,
+    pub req_flags: Option<ContextFlags>,
+    /// The optimistic token for the first mechanism in `mech_types`
+    pub mech_token: Option<&'a [u8]>,
+    /// Only present in `NegTokenInit2`
+    pub neg_hints: Option<NegHints<'a>>,
+    pub mech_list_mic: Option<&'a [u8]>,
+}
+impl<'a> NegTokenInit<'a> {
+    fn decode(contents: &'a [u8]) -> Result<Self, DecodeError> {
+        let mut fields = Reader::new(Reader::new(contents).read_single(SEQUENCE)?);
+        let mut mech_types = Vec::new();
+        let mut list = Reader::new(fields.read_field(0, SEQUENCE)?);
+        while !list.is_empty() {
+            mech_types.push(Oid(list.read(OID)?));
+        }
+        let req_flags = fields
+            .read_optional_field(1, BIT_STRING)?
+            .map(ContextFlags::decode)
+            .transpose()?;
+        let mech_token = fields.read_optional_field(2, OCTET_STRING)?;
+        // [3] is the MIC in NegTokenInit, but the hints in NegTokenInit2
+        let mut neg_hints = None;
+        let mut mech_list_mic = None;
+        if let Some(field) = fields.read_optional(context(3))? {
+            let field = Reader::new(field);
+            match field.peek_tag() {
+                Some(SEQUENCE) => neg_hints = Some(NegHints::decode(field.read_single(SEQUENCE)?)?),
+                _ => mech_list_mic = Some(field.read_single(OCTET_STRING)?),
+            }
+        }
+        if let Some(mic) = fields.read_optional_field(4, OCTET_STRING)? {
+            mech_list_mic = Some(mic);
+        }
+        fields.finish()?;
+        Ok(Self {
+            mech_types,
+            req_flags,
+            mech_token,
+            neg_hints,
+            mech_list_mic,
+        })
+    }
+    /// The mechanism the initiator prefers, which `mech_token` belongs to
+    #[must_use]
+    pub fn preferred_mech(&self) -> Option<Oid<'a>> {
+        self.mech_types.first().copied()
+    }
+}
+impl<'a> NegHints<'a> {
+    fn decode(contents: &'a [u8]) -> Result<Self, DecodeError> {
+        let mut fields = Reader::new(contents);
+        let hint_name = fields.read_optional_field(0, GENERAL_STRING)?;
+        let hint_address = fields.read_optional_field(1, OCTET_STRING)?;
+        fields.finish()?;
+        Ok(Self {
+            hint_name,
+            hint_address,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NegState {
+    AcceptCompleted,
+    AcceptIncomplete,
+    Reject,
+    RequestMic,
+}
+
+/// Every SPNEGO token after the first one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NegTokenResp<'a> {
+    pub neg_state: Option<NegState>,
+    pub supported_mech: Option<Oid<'a>>,
+    pub response_token: Option<&'a [u8]>,
+    pub mech_list_mic: Option<&'a [u8]>,
+}
+impl<'a> NegTokenResp<'a> {
+    fn decode(contents: &'a [u8]) -> Result<Self, DecodeError> {
+        let mut fields = Reader::new(Reader::new(contents).read_single(SEQUENCE)?);
+        let neg_state = fields
+            .read_optional_field(0, ENUMERATED)?
+            .map(|state| match der::unsigned(state)? {
+                0 => Ok(NegState::AcceptCompleted),
+                1 => Ok(NegState::AcceptIncomplete),
+                2 => Ok(NegState::Reject),
+                3 => Ok(NegState::RequestMic),
+                _ => Err(DecodeError::InvalidValue),
+            })
+            .transpose()?;
+        let supported_mech = fields.read_optional_field(1, OID)?.map(Oid);
+        let response_token = fields.read_optional_field(2, OCTET_STRING)?;
+        let mech_list_mic = fields.read_optional_field(3, OCTET_STRING)?;
+        fields.finish()?;
+        Ok(Self {
+            neg_state,
+            supported_mech,
+            response_token,
+            mech_list_mic,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NegotiationToken<'a> {
+    Init(NegTokenInit<'a>),
+    Resp(NegTokenResp<'a>),
+}
+impl<'a> NegotiationToken<'a> {
+    /// Decodes a SPNEGO token, either framed as initial context token or as bare `NegotiationToken`
+    ///
+    /// # Errors
+    /// Fails if `token` is no SPNEGO token
+    pub fn decode(token: &'a [u8]) -> Result<Self, DecodeError> {
+        let token = if token.first() == Some(&application(0)) {
+            let initial = InitialContextToken::decode(token)?;
+            if initial.this_mech != Oid::SPNEGO {
+                return Err(DecodeError::InvalidValue);
+            }
+            initial.inner_token
+        } else {
+            token
+        };
+        let mut reader = Reader::new(token);
+        let (tag, contents) = reader.read_any()?;
+        reader.finish()?;
+        match tag {
+            t if t == context(0) => NegTokenInit::decode(contents).map(Self::Init),
+            t if t == context(1) => NegTokenResp::decode(contents).map(Self::Resp),
+            found => Err(DecodeError::UnexpectedTag {
+                expected: context(0),
+                found,
+            }),
+        }
+    }
+    /// The token of the negotiated mechanism carried inside
+    #[must_use]
+    pub fn mech_token(&self) -> Option<&'a [u8]> {
+        match self {
+            Self::Init(init) => init.mech_token,
+            Self::Resp(resp) => resp.response_token,
+        }
+    }
+}
+
+/// The kind of a token, as far as it can be told from its framing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    Kerberos,
+    Ntlm,
+    Spnego,
+    Unknown,
+}
+
+const NTLM_SIGNATURE: &[u8] = b"NTLMSSP\0";
+
+/// Tells which mechanism produced `token`
+///
+/// For SPNEGO, the token inside can be classified again using [`NegotiationToken::mech_token`]
+#[must_use]
+pub fn classify(token: &[u8]) -> TokenKind {
+    if token.starts_with(NTLM_SIGNATURE) {
+        return TokenKind::Ntlm;
+    }
+    if let Ok(initial) = InitialContextToken::decode(token) {
+        return match initial.this_mech {
+            mech if mech == Oid::SPNEGO => TokenKind::Spnego,
+            mech if mech == Oid::NTLM => TokenKind::Ntlm,
+            mech if mech.is_kerberos() => TokenKind::Kerberos,
+            _ => TokenKind::Unknown,
+        };
+    }
+    if token.first() == Some(&context(1)) && NegotiationToken::decode(token).is_ok() {
+        return TokenKind::Spnego;
+    }
+    TokenKind::Unknown
+}
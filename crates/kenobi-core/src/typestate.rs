@@ -11,3 +11,10 @@ pub enum Encryption {}
 pub enum NoDelegation {}
 pub enum MaybeDelegation {}
 pub enum Delegation {}
+
+/// The context is not bound to a channel
+#[derive(Debug)]
+pub enum Unbound {}
+/// The context is bound to a channel, which the peer has to match
+#[derive(Debug)]
+pub enum Bound {}
@@ -0,0 +1,159 @@
+use std::{
+    convert::Infallible,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+use kenobi_core::channel_bindings::{Address, Channel, ChannelBindingPolicy, ChannelBindingType, ChannelBindings};
+
+struct EndPointOnly;
+impl Channel for EndPointOnly {
+    type Error = Infallible;
+    fn channel_bindings(&self) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(Some(ChannelBindingType::TlsServerEndPoint.with_prefix(&[1, 2, 3])))
+    }
+}
+
+#[test]
+fn prefix_follows_type() {
+    assert_eq!(
+        ChannelBindingType::TlsExporter.with_prefix(&[0xff]),
+        b"tls-exporter:\xff"
+    );
+    assert_eq!(ChannelBindingType::TlsUnique.prefix(), b"tls-unique:");
+    assert_eq!(ChannelBindingType::default(), ChannelBindingType::TlsServerEndPoint);
+}
+
+#[test]
+fn acceptors_verify_bindings_if_present_by_default() {
+    assert_eq!(ChannelBindingPolicy::default(), ChannelBindingPolicy::IfPresent);
+}
+
+#[test]
+fn unsupported_types_are_absent() {
+    let channel = EndPointOnly;
+    let Ok(end_point) = channel.channel_bindings_of(ChannelBindingType::TlsServerEndPoint);
+    assert_eq!(end_point.unwrap(), b"tls-server-end-point:\x01\x02\x03");
+    let Ok(exporter) = channel.channel_bindings_of(ChannelBindingType::TlsExporter);
+    assert_eq!(exporter, None);
+}
+
+#[test]
+fn addresses_use_gss_types() {
+    let v4 = Address::from(IpAddr::from([192, 0, 2, 1]));
+    assert_eq!((v4.address_type(), v4.to_bytes()), (2, vec![192, 0, 2, 1]));
+    let v6 = Address::from(IpAddr::from(Ipv6Addr::LOCALHOST));
+    assert_eq!(v6.address_type(), 24);
+    assert_eq!(v6.to_bytes().len(), 16);
+}
+
+#[test]
+fn bindings_without_addresses_or_data_are_empty() {
+    let mut bindings = ChannelBindings::default();
+    assert!(bindings.is_empty());
+    bindings.acceptor_address = Some(Address::Ipv4(Ipv4Addr::LOCALHOST));
+    assert!(!bindings.is_empty());
+}
+
+#[cfg(feature = "rustls")]
+#[test]
+fn server_channel_hashes_own_certificate() {
+    use kenobi_core::channel_bindings::{ServerChannel, tls_server_end_point};
+
+    let certificate = b"not a certificate";
+    let channel = ServerChannel::new(&EndPointOnly, certificate);
+    let Ok(end_point) = channel.channel_bindings();
+    assert_eq!(end_point.unwrap(), tls_server_end_point(certificate));
+    let Ok(exporter) = channel.channel_bindings_of(ChannelBindingType::TlsExporter);
+    assert_eq!(exporter, None);
+}
+
+#[cfg(feature = "rustls")]
+#[test]
+fn rustls_exports_only_after_tls_1_3_handshakes() {
+    use std::sync::Arc;
+
+    use rustls::{ClientConfig, ClientConnection, RootCertStore, crypto::ring::default_provider};
+
+    let config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(RootCertStore::empty())
+        .with_no_client_auth();
+    let connection = ClientConnection::new(Arc::new(config), "localhost".try_into().unwrap()).unwrap();
+    assert_eq!(
+        connection.channel_bindings_of(ChannelBindingType::TlsExporter),
+        Ok(None)
+    );
+}
+
+#[cfg(feature = "rustls")]
+#[test]
+fn rustls_servers_bind_through_server_channel() {
+    use std::sync::Arc;
+
+    use kenobi_core::channel_bindings::{ServerChannel, tls_server_end_point};
+    use rustls::{
+        ServerConfig, ServerConnection,
+        crypto::ring::default_provider,
+        server::{ClientHello, ResolvesServerCert},
+        sign::CertifiedKey,
+    };
+
+    #[derive(Debug)]
+    struct NoCertificate;
+    impl ResolvesServerCert for NoCertificate {
+        fn resolve(&self, _: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+            None
+        }
+    }
+
+    let config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(NoCertificate));
+    let connection = ServerConnection::new(Arc::new(config)).unwrap();
+    assert_eq!(connection.channel_bindings(), Ok(None));
+
+    let certificate = b"not a certificate";
+    let channel = ServerChannel::new(&connection, certificate);
+    assert_eq!(channel.channel_bindings(), Ok(Some(tls_server_end_point(certificate))));
+    assert_eq!(channel.channel_bindings_of(ChannelBindingType::TlsExporter), Ok(None));
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn openssl_server_hashes_own_certificate() {
+    use kenobi_core::channel_bindings::tls_server_end_point;
+    use openssl::{
+        asn1::Asn1Time,
+        ec::{EcGroup, EcKey},
+        hash::MessageDigest,
+        nid::Nid,
+        pkey::PKey,
+        ssl::{Ssl, SslContext, SslMethod},
+        x509::X509,
+    };
+
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+    let mut certificate = X509::builder().unwrap();
+    certificate.set_pubkey(&key).unwrap();
+    certificate
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    certificate.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+    certificate.sign(&key, MessageDigest::sha384()).unwrap();
+    let certificate = certificate.build();
+
+    let mut context = SslContext::builder(SslMethod::tls_server()).unwrap();
+    context.set_certificate(&certificate).unwrap();
+    context.set_private_key(&key).unwrap();
+    let mut ssl = Ssl::new(&context.build()).unwrap();
+    ssl.set_accept_state();
+
+    let end_point = ssl.channel_bindings().unwrap().unwrap();
+    assert_eq!(end_point, tls_server_end_point(&certificate.to_der().unwrap()));
+    // SHA-384 signatures are hashed with SHA-384
+    assert_eq!(end_point.len(), b"tls-server-end-point:".len() + 48);
+}
@@ -0,0 +1,127 @@
+use kenobi_core::{
+    krb5::{ApOptions, EncryptionType, ErrorCode, KrbMessage},
+    spnego::Oid,
+};
+
+fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    match contents.len() {
+        len @ 0..0x80 => out.push(len as u8),
+        len @ 0x80..0x100 => out.extend([0x81, len as u8]),
+        len => out.extend([0x82, (len >> 8) as u8, len as u8]),
+    }
+    out.extend_from_slice(contents);
+    out
+}
+fn field(number: u8, contents: Vec<u8>) -> Vec<u8> {
+    tlv(0xa0 | number, &contents)
+}
+fn int(value: u8) -> Vec<u8> {
+    tlv(0x02, &[value])
+}
+fn string(value: &str) -> Vec<u8> {
+    tlv(0x1b, value.as_bytes())
+}
+fn principal(components: &[&str]) -> Vec<u8> {
+    let names: Vec<u8> = components.iter().flat_map(|c| string(c)).collect();
+    tlv(0x30, &[field(0, int(2)), field(1, tlv(0x30, &names))].concat())
+}
+fn encrypted(etype: u8, kvno: Option<u8>) -> Vec<u8> {
+    let mut fields = field(0, int(etype));
+    if let Some(kvno) = kvno {
+        fields.extend(field(1, int(kvno)));
+    }
+    fields.extend(field(2, tlv(0x04, &[0x55; 150])));
+    tlv(0x30, &fields)
+}
+fn message(application: u8, fields: &[Vec<u8>]) -> Vec<u8> {
+    let header = [field(0, int(5)), field(1, int(application))];
+    tlv(0x60 | application, &tlv(0x30, &[&header[..], fields].concat().concat()))
+}
+fn gss_framed(tok_id: u8, message: &[u8]) -> Vec<u8> {
+    let contents = [&tlv(0x06, Oid::KERBEROS_V5.as_der())[..], &[tok_id, 0], message].concat();
+    tlv(0x60, &contents)
+}
+
+fn ap_req() -> Vec<u8> {
+    let ticket = tlv(
+        0x61,
+        &tlv(
+            0x30,
+            &[
+                field(0, int(5)),
+                field(1, string("EXAMPLE.COM")),
+                field(2, principal(&["HTTP", "www.example.com"])),
+                field(3, encrypted(18, Some(3))),
+            ]
+            .concat(),
+        ),
+    );
+    message(
+        14,
+        &[
+            field(2, tlv(0x03, &[0x00, 0x20, 0x00, 0x00, 0x00])),
+            field(3, ticket),
+            field(4, encrypted(18, None)),
+        ],
+    )
+}
+
+#[test]
+fn decodes_framed_ap_req() {
+    let token = gss_framed(1, &ap_req());
+    let KrbMessage::ApReq(req) = KrbMessage::decode(&token).unwrap() else {
+        panic!("expected AP-REQ");
+    };
+    assert!(req.ap_options.contains(ApOptions::MUTUAL_REQUIRED));
+    assert!(!req.ap_options.contains(ApOptions::USE_SESSION_KEY));
+    assert_eq!(req.ticket.realm, "EXAMPLE.COM");
+    assert_eq!(req.ticket.sname.to_string(), "HTTP/www.example.com");
+    assert_eq!(req.ticket.enc_part.etype, EncryptionType(18));
+    assert_eq!(req.ticket.enc_part.kvno, Some(3));
+    assert_eq!(req.ticket.enc_part.cipher.len(), 150);
+    assert_eq!(req.authenticator.kvno, None);
+    assert_eq!(EncryptionType(18).to_string(), "aes256-cts-hmac-sha1-96 (18)");
+}
+
+#[test]
+fn decodes_bare_ap_rep() {
+    let token = message(15, &[field(2, encrypted(23, None))]);
+    let KrbMessage::ApRep(rep) = KrbMessage::decode(&token).unwrap() else {
+        panic!("expected AP-REP");
+    };
+    assert_eq!(rep.enc_part.etype.name(), Some("rc4-hmac"));
+}
+
+#[test]
+fn decodes_krb_error() {
+    let error = message(
+        30,
+        &[
+            field(4, tlv(0x18, b"20260101120000Z")),
+            field(5, int(0)),
+            field(6, int(44)),
+            field(9, string("EXAMPLE.COM")),
+            field(10, principal(&["HTTP", "www.example.com"])),
+            field(11, string("no key for kvno 3")),
+        ],
+    );
+    let token = gss_framed(3, &error);
+    let KrbMessage::Error(error) = KrbMessage::decode(&token).unwrap() else {
+        panic!("expected KRB-ERROR");
+    };
+    assert_eq!(error.error_code, ErrorCode(44));
+    assert_eq!(error.error_code.to_string(), "KRB_AP_ERR_BADKEYVER (44)");
+    assert_eq!(error.stime, "20260101120000Z");
+    assert_eq!(error.crealm, None);
+    assert_eq!(error.e_text, Some("no key for kvno 3"));
+}
+
+#[test]
+fn rejects_other_messages() {
+    let token = ap_req();
+    assert!(KrbMessage::decode(&token[..token.len() - 3]).is_err());
+    let wrong_type = message(15, &[field(2, tlv(0x03, &[0]))]);
+    assert!(KrbMessage::decode(&wrong_type).is_err());
+    assert!(KrbMessage::decode(b"NTLMSSP\0").is_err());
+}
@@ -0,0 +1,99 @@
+use kenobi_core::spnego::{ContextFlags, InitialContextToken, NegState, NegotiationToken, Oid, TokenKind, classify};
+
+fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    match contents.len() {
+        len @ 0..0x80 => out.push(len as u8),
+        len @ 0x80..0x100 => out.extend([0x81, len as u8]),
+        len => out.extend([0x82, (len >> 8) as u8, len as u8]),
+    }
+    out.extend_from_slice(contents);
+    out
+}
+fn oid(oid: Oid<'_>) -> Vec<u8> {
+    tlv(0x06, oid.as_der())
+}
+
+fn kerberos_token() -> Vec<u8> {
+    let mut contents = oid(Oid::KERBEROS_V5);
+    contents.extend([0x01, 0x00]);
+    contents.extend(tlv(0x6e, &[0x30, 0x00]));
+    tlv(0x60, &contents)
+}
+
+fn spnego_init(hints: bool) -> Vec<u8> {
+    let mech_types = [oid(Oid::KERBEROS_V5), oid(Oid::NTLM)].concat();
+    let mut fields = tlv(0xa0, &tlv(0x30, &mech_types));
+    fields.extend(tlv(0xa1, &tlv(0x03, &[0x01, 0x40 | 0x02])));
+    fields.extend(tlv(0xa2, &tlv(0x04, &kerberos_token())));
+    if hints {
+        let name = tlv(0xa0, &tlv(0x1b, b"not_defined_in_RFC4178@please_ignore"));
+        fields.extend(tlv(0xa3, &tlv(0x30, &name)));
+    } else {
+        fields.extend(tlv(0xa3, &tlv(0x04, b"mic")));
+    }
+    let token = tlv(0xa0, &tlv(0x30, &fields));
+    tlv(0x60, &[oid(Oid::SPNEGO), token].concat())
+}
+
+#[test]
+fn decodes_neg_token_init() {
+    let token = spnego_init(false);
+    assert_eq!(classify(&token), TokenKind::Spnego);
+    let NegotiationToken::Init(init) = NegotiationToken::decode(&token).unwrap() else {
+        panic!("expected NegTokenInit");
+    };
+    assert_eq!(init.mech_types, [Oid::KERBEROS_V5, Oid::NTLM]);
+    assert_eq!(init.preferred_mech(), Some(Oid::KERBEROS_V5));
+    let flags = init.req_flags.unwrap();
+    assert!(flags.contains(ContextFlags::MUTUAL) && flags.contains(ContextFlags::INTEGRITY));
+    assert!(!flags.contains(ContextFlags::DELEGATION));
+    assert_eq!(init.mech_list_mic, Some(&b"mic"[..]));
+    assert_eq!(init.neg_hints, None);
+    assert_eq!(classify(init.mech_token.unwrap()), TokenKind::Kerberos);
+}
+
+#[test]
+fn decodes_neg_token_init2() {
+    let token = spnego_init(true);
+    let NegotiationToken::Init(init) = NegotiationToken::decode(&token).unwrap() else {
+        panic!("expected NegTokenInit2");
+    };
+    assert_eq!(init.mech_list_mic, None);
+    let hints = init.neg_hints.unwrap();
+    assert_eq!(hints.hint_name, Some(&b"not_defined_in_RFC4178@please_ignore"[..]));
+}
+
+#[test]
+fn decodes_neg_token_resp() {
+    let mut fields = tlv(0xa0, &tlv(0x0a, &[0x00]));
+    fields.extend(tlv(0xa1, &oid(Oid::KERBEROS_V5)));
+    fields.extend(tlv(0xa2, &tlv(0x04, &[0xab; 200])));
+    let token = tlv(0xa1, &tlv(0x30, &fields));
+    assert_eq!(classify(&token), TokenKind::Spnego);
+    let NegotiationToken::Resp(resp) = NegotiationToken::decode(&token).unwrap() else {
+        panic!("expected NegTokenResp");
+    };
+    assert_eq!(resp.neg_state, Some(NegState::AcceptCompleted));
+    assert_eq!(resp.supported_mech, Some(Oid::KERBEROS_V5));
+    assert_eq!(resp.response_token.unwrap().len(), 200);
+    assert_eq!(resp.mech_list_mic, None);
+}
+
+#[test]
+fn classifies_other_mechanisms() {
+    assert_eq!(classify(b"NTLMSSP\0\x01\0\0\0"), TokenKind::Ntlm);
+    assert_eq!(classify(&kerberos_token()), TokenKind::Kerberos);
+    assert_eq!(classify(b"garbage"), TokenKind::Unknown);
+    let kerberos = kerberos_token();
+    let initial = InitialContextToken::decode(&kerberos).unwrap();
+    assert_eq!(initial.this_mech.to_string(), "1.2.840.113554.1.2.2");
+    assert_eq!(&initial.inner_token[..2], [0x01, 0x00]);
+}
+
+#[test]
+fn rejects_truncated_tokens() {
+    let token = spnego_init(false);
+    assert!(NegotiationToken::decode(&token[..token.len() - 1]).is_err());
+    assert!(NegotiationToken::decode(&kerberos_token()).is_err());
+}
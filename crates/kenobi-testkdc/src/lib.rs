@@ -0,0 +1,320 @@
+//! Throwaway MIT Kerberos realm for end-to-end tests
+//!
+//! [`TestKdc::start`] provisions a realm in a temporary directory and runs `krb5kdc` on a free localhost port.
+//! The KDC is stopped and the directory removed when the [`TestKdc`] is dropped.
+//!
+//! The MIT KDC is rarely installed, so tests using it should be `#[ignore]`d and run with `--ignored` where it is,
+//! failing on [`Error::MissingBinary`] instead of passing without having run.
+//! Tests that only reach `HTTP/localhost` can call [`offline_environment`] instead, which needs no KDC
+
+use std::{
+    ffi::OsString,
+    fmt::Display,
+    fs,
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use tempfile::TempDir;
+
+mod offline;
+pub use offline::{OfflineAcceptor, OfflineInitiator, offline_environment};
+
+/// Realm of every test KDC
+pub const REALM: &str = "KENOBI.TEST";
+
+const MASTER_PASSWORD: &str = "kenobi-test-master";
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+/// Searched after `PATH`, as distributions install the KDC programs for root only
+const SBIN_DIRS: &[&str] = &["/usr/sbin", "/usr/local/sbin", "/sbin", "/usr/lib/mit/sbin"];
+
+const KDB5_UTIL: &str = "kdb5_util";
+const KADMIN_LOCAL: &str = "kadmin.local";
+const KRB5KDC: &str = "krb5kdc";
+const KINIT: &str = "kinit";
+
+struct Binaries {
+    kdb5_util: PathBuf,
+    kadmin_local: PathBuf,
+    kinit: PathBuf,
+}
+
+/// A running KDC serving [`REALM`]
+pub struct TestKdc {
+    dir: TempDir,
+    binaries: Binaries,
+    port: u16,
+    kdc: Child,
+}
+impl TestKdc {
+    /// Creates the realm database and starts `krb5kdc`
+    ///
+    /// # Errors
+    /// Fails if a program of the MIT KDC is missing, provisioning failed or the KDC did not start listening
+    pub fn start() -> Result<Self, Error> {
+        let binaries = Binaries {
+            kdb5_util: find_binary(KDB5_UTIL)?,
+            kadmin_local: find_binary(KADMIN_LOCAL)?,
+            kinit: find_binary(KINIT)?,
+        };
+        let krb5kdc = find_binary(KRB5KDC)?;
+        let dir = tempfile::Builder::new().prefix("kenobi-testkdc").tempdir()?;
+        let port = free_port()?;
+        write_config(dir.path(), port)?;
+        let environment = environment(dir.path());
+        run(
+            KDB5_UTIL,
+            &binaries.kdb5_util,
+            &environment,
+            &["create", "-s", "-r", REALM, "-P", MASTER_PASSWORD],
+        )?;
+        let kdc = Command::new(krb5kdc)
+            .args(["-n", "-r", REALM])
+            .envs(environment)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let mut test_kdc = Self {
+            dir,
+            binaries,
+            port,
+            kdc,
+        };
+        test_kdc.wait_until_listening()?;
+        Ok(test_kdc)
+    }
+    #[must_use]
+    pub fn realm(&self) -> &'static str {
+        REALM
+    }
+    /// The localhost port the KDC listens on, for UDP and TCP
+    #[must_use]
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+    /// The directory holding the configuration, database, keytab and credential cache
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+    #[must_use]
+    pub fn keytab(&self) -> PathBuf {
+        self.path().join("kenobi.keytab")
+    }
+    #[must_use]
+    pub fn ccache(&self) -> PathBuf {
+        self.path().join("ccache")
+    }
+    /// Creates `name` with a random key and adds the key to [`keytab`](Self::keytab)
+    ///
+    /// Returns the principal including the realm
+    ///
+    /// # Errors
+    /// Fails if `kadmin.local` fails
+    pub fn add_principal(&self, name: &str) -> Result<String, Error> {
+        let principal = format!("{name}@{REALM}");
+        let keytab = self.keytab();
+        self.kadmin(&format!("addprinc -randkey {principal}"))?;
+        self.kadmin(&format!("ktadd -k {} {principal}", keytab.display()))?;
+        Ok(principal)
+    }
+    /// Obtains a forwardable ticket granting ticket for `principal` into [`ccache`](Self::ccache),
+    /// using its key from the keytab
+    ///
+    /// # Errors
+    /// Fails if `kinit` fails
+    pub fn kinit(&self, principal: &str) -> Result<(), Error> {
+        let keytab = self.keytab();
+        let ccache = self.ccache();
+        let args = [
+            "-f".as_ref(),
+            "-k".as_ref(),
+            "-t".as_ref(),
+            keytab.as_os_str(),
+            "-c".as_ref(),
+            ccache.as_os_str(),
+            principal.as_ref(),
+        ];
+        run(KINIT, &self.binaries.kinit, &self.environment(), &args)
+    }
+    /// The variables pointing GSSAPI at this realm, its keytab and its credential cache
+    #[must_use]
+    pub fn environment(&self) -> Vec<(&'static str, OsString)> {
+        environment(self.path())
+    }
+    /// Points the environment of the current process at this realm
+    ///
+    /// # Safety
+    /// No other thread may read or write the environment at the same time, see [`std::env::set_var`]
+    pub unsafe fn set_environment(&self) {
+        for (key, value) in self.environment() {
+            unsafe { std::env::set_var(key, value) };
+        }
+    }
+
+    fn kadmin(&self, query: &str) -> Result<(), Error> {
+        run(
+            KADMIN_LOCAL,
+            &self.binaries.kadmin_local,
+            &self.environment(),
+            &["-r", REALM, "-q", query],
+        )
+    }
+    fn wait_until_listening(&mut self) -> Result<(), Error> {
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, self.port));
+        let started = Instant::now();
+        while started.elapsed() < STARTUP_TIMEOUT {
+            if self.kdc.try_wait()?.is_some() {
+                return Err(Error::KdcNotListening);
+            }
+            if TcpStream::connect_timeout(&address, Duration::from_millis(100)).is_ok() {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        Err(Error::KdcNotListening)
+    }
+}
+impl Drop for TestKdc {
+    fn drop(&mut self) {
+        let _ = self.kdc.kill();
+        let _ = self.kdc.wait();
+    }
+}
+
+fn find_binary(name: &'static str) -> Result<PathBuf, Error> {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path)
+        .chain(SBIN_DIRS.iter().map(PathBuf::from))
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+        .ok_or(Error::MissingBinary(name))
+}
+
+/// A port that was free a moment ago, `krb5kdc` can only be told a port and not take over a socket
+fn free_port() -> Result<u16, Error> {
+    Ok(TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?.local_addr()?.port())
+}
+
+fn environment(dir: &Path) -> Vec<(&'static str, OsString)> {
+    let file = |name: &str| {
+        let mut value = OsString::from("FILE:");
+        value.push(dir.join(name));
+        value
+    };
+    vec![
+        ("KRB5_CONFIG", dir.join("krb5.conf").into()),
+        ("KRB5_KDC_PROFILE", dir.join("kdc.conf").into()),
+        ("KRB5_KTNAME", file("kenobi.keytab")),
+        ("KRB5_CLIENT_KTNAME", file("kenobi.keytab")),
+        ("KRB5CCNAME", file("ccache")),
+    ]
+}
+
+fn write_config(dir: &Path, port: u16) -> Result<(), Error> {
+    let krb5_conf = format!(
+        "[libdefaults]
+    default_realm = {REALM}
+    dns_lookup_kdc = false
+    dns_lookup_realm = false
+    rdns = false
+    forwardable = true
+    udp_preference_limit = 1
+
+[realms]
+    {REALM} = {{
+        kdc = 127.0.0.1:{port}
+    }}
+
+[domain_realm]
+    localhost = {REALM}
+"
+    );
+    let dir_display = dir.display();
+    let kdc_conf = format!(
+        "[kdcdefaults]
+    kdc_ports = {port}
+    kdc_tcp_ports = {port}
+
+[realms]
+    {REALM} = {{
+        database_name = {dir_display}/principal
+        key_stash_file = {dir_display}/stash
+        acl_file = {dir_display}/kadm5.acl
+        kdc_ports = {port}
+        kdc_tcp_ports = {port}
+        max_life = 1h
+        max_renewable_life = 1h
+    }}
+
+[logging]
+    kdc = FILE:{dir_display}/kdc.log
+"
+    );
+    fs::write(dir.join("krb5.conf"), krb5_conf)?;
+    fs::write(dir.join("kdc.conf"), kdc_conf)?;
+    fs::write(dir.join("kadm5.acl"), "")?;
+    Ok(())
+}
+
+fn run<S: AsRef<std::ffi::OsStr>>(
+    name: &'static str,
+    program: &Path,
+    environment: &[(&'static str, OsString)],
+    args: &[S],
+) -> Result<(), Error> {
+    let output = Command::new(program)
+        .args(args)
+        .envs(environment.iter().map(|(key, value)| (key, value)))
+        .stdin(Stdio::null())
+        .output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(Error::Command {
+            program: name,
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// A program of the MIT KDC is not installed
+    MissingBinary(&'static str),
+    Io(std::io::Error),
+    /// A provisioning command failed
+    Command {
+        program: &'static str,
+        stderr: String,
+    },
+    /// `krb5kdc` exited or did not listen within the startup timeout
+    KdcNotListening,
+}
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingBinary(name) => write!(f, "`{name}` is not installed"),
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Command { program, stderr } => write!(f, "`{program}` failed: {}", stderr.trim()),
+            Self::KdcNotListening => write!(f, "krb5kdc did not start listening within {STARTUP_TIMEOUT:?}"),
+        }
+    }
+}
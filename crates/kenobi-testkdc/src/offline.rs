@@ -0,0 +1,359 @@
+//! Credentials written by hand, for tests that can not start a KDC
+//!
+//! The cached ticket of [`OfflineInitiator`] is encrypted in the key of the [`OfflineAcceptor`] keytab,
+//! so a client and a server using both complete a Kerberos exchange without ever contacting a KDC.
+//! Only `HTTP/localhost` can be reached this way, and nothing can be delegated.
+
+use std::{ffi::OsString, fs, path::Path, sync::OnceLock};
+
+use openssl::{
+    hash::MessageDigest,
+    pkey::PKey,
+    sign::Signer,
+    symm::{Cipher, Crypter, Mode},
+};
+use tempfile::TempDir;
+
+use crate::{Error, REALM};
+
+const KRB5_NT_PRINCIPAL: u32 = 1;
+const KRB5_NT_SRV_INST: u32 = 2;
+const KRB5_NT_SRV_HST: u32 = 3;
+const AES256_CTS_HMAC_SHA1_96: u16 = 18;
+/// Key of `HTTP/localhost` in the keytab, which encrypts the ticket
+const SERVICE_KEY: [u8; 32] = [0x4b; 32];
+/// Key the ticket shares between client and service
+const SESSION_KEY: [u8; 32] = [0x6b; 32];
+/// Key usage of the encrypted part of a ticket (RFC 4120, section 7.5.1)
+const TICKET_KEY_USAGE: u32 = 2;
+const AUTH_TIME: (u32, &str) = (946_684_800, "20000101000000Z");
+const END_TIME: (u32, &str) = (2_145_830_400, "20371231000000Z");
+
+/// A keytab for `HTTP/localhost@KENOBI.TEST` with a fixed key, written without any KDC
+///
+/// Enough to acquire acceptor credentials, and to accept the ticket of an [`OfflineInitiator`]
+pub struct OfflineAcceptor {
+    dir: TempDir,
+}
+impl OfflineAcceptor {
+    /// Writes the keytab and a minimal krb5.conf into a temporary directory
+    ///
+    /// # Errors
+    /// Fails if the files could not be written
+    pub fn new() -> Result<Self, Error> {
+        let dir = tempfile::Builder::new().prefix("kenobi-offline").tempdir()?;
+        write_config(dir.path())?;
+        fs::write(dir.path().join("kenobi.keytab"), keytab())?;
+        Ok(Self { dir })
+    }
+    /// The variables pointing GSSAPI at the keytab
+    ///
+    /// The replay cache is disabled, as it would be shared with every other test on the machine
+    #[must_use]
+    pub fn environment(&self) -> Vec<(&'static str, OsString)> {
+        let mut keytab = OsString::from("FILE:");
+        keytab.push(self.dir.path().join("kenobi.keytab"));
+        vec![
+            ("KRB5_CONFIG", self.dir.path().join("krb5.conf").into()),
+            ("KRB5_KTNAME", keytab),
+            ("KRB5RCACHETYPE", "none".into()),
+        ]
+    }
+    /// Points the environment of the current process at the keytab
+    ///
+    /// # Safety
+    /// No other thread may read or write the environment at the same time, see [`std::env::set_var`]
+    pub unsafe fn set_environment(&self) {
+        for (key, value) in self.environment() {
+            unsafe { std::env::set_var(key, value) };
+        }
+    }
+}
+
+/// A credential cache holding a ticket for `HTTP/localhost@KENOBI.TEST`, written without any KDC
+///
+/// Clients can authenticate to an [`OfflineAcceptor`] with it.
+/// Other services fail, as there is no KDC to ask for their tickets
+pub struct OfflineInitiator {
+    dir: TempDir,
+}
+impl OfflineInitiator {
+    /// The client the ticket was issued to
+    pub const CLIENT: &str = "alice@KENOBI.TEST";
+    /// The only service a ticket is cached for
+    pub const SERVICE: &str = "HTTP/localhost";
+
+    /// Writes the credential cache and a krb5.conf without any KDC into a temporary directory
+    ///
+    /// # Errors
+    /// Fails if the files could not be written
+    pub fn new() -> Result<Self, Error> {
+        let dir = tempfile::Builder::new().prefix("kenobi-offline").tempdir()?;
+        write_config(dir.path())?;
+        fs::write(dir.path().join("ccache"), ccache())?;
+        Ok(Self { dir })
+    }
+    /// The variables pointing GSSAPI at the credential cache
+    #[must_use]
+    pub fn environment(&self) -> Vec<(&'static str, OsString)> {
+        let mut ccache = OsString::from("FILE:");
+        ccache.push(self.dir.path().join("ccache"));
+        vec![
+            ("KRB5_CONFIG", self.dir.path().join("krb5.conf").into()),
+            ("KRB5CCNAME", ccache),
+        ]
+    }
+    /// Points the environment of the current process at the credential cache
+    ///
+    /// # Safety
+    /// No other thread may read or write the environment at the same time, see [`std::env::set_var`]
+    pub unsafe fn set_environment(&self) {
+        for (key, value) in self.environment() {
+            unsafe { std::env::set_var(key, value) };
+        }
+    }
+}
+
+/// Points the environment of the test process at an [`OfflineInitiator`] and an [`OfflineAcceptor`]
+///
+/// The files are written on the first call and kept until the process exits.
+/// As the environment must not change while GSSAPI reads it,
+/// every test of a binary using this has to call it before touching GSSAPI
+///
+/// # Panics
+/// Panics if the files could not be written
+pub fn offline_environment() {
+    static FIXTURES: OnceLock<(OfflineInitiator, OfflineAcceptor)> = OnceLock::new();
+    FIXTURES.get_or_init(|| {
+        let initiator = OfflineInitiator::new().expect("could not write the credential cache");
+        let acceptor = OfflineAcceptor::new().expect("could not write the keytab");
+        // SAFETY: concurrent callers wait for the lock, and every test calls this before touching GSSAPI
+        unsafe {
+            initiator.set_environment();
+            acceptor.set_environment();
+        }
+        (initiator, acceptor)
+    });
+}
+
+/// Both sides write the same configuration, so either may set `KRB5_CONFIG` last
+fn write_config(dir: &Path) -> Result<(), Error> {
+    fs::write(
+        dir.join("krb5.conf"),
+        format!("[libdefaults]\n    default_realm = {REALM}\n    dns_lookup_kdc = false\n"),
+    )?;
+    Ok(())
+}
+
+/// A version 0x502 keytab with a single aes256-cts-hmac-sha1-96 key
+fn keytab() -> Vec<u8> {
+    fn counted(out: &mut Vec<u8>, data: &[u8]) {
+        out.extend(u16::try_from(data.len()).expect("short keytab field").to_be_bytes());
+        out.extend(data);
+    }
+    let mut entry = Vec::new();
+    entry.extend(2u16.to_be_bytes());
+    counted(&mut entry, REALM.as_bytes());
+    counted(&mut entry, b"HTTP");
+    counted(&mut entry, b"localhost");
+    entry.extend(KRB5_NT_SRV_HST.to_be_bytes());
+    // timestamp and key version
+    entry.extend(0u32.to_be_bytes());
+    entry.push(1);
+    entry.extend(AES256_CTS_HMAC_SHA1_96.to_be_bytes());
+    counted(&mut entry, &SERVICE_KEY);
+    let mut keytab = vec![0x05, 0x02];
+    keytab.extend(i32::try_from(entry.len()).expect("short keytab entry").to_be_bytes());
+    keytab.extend(entry);
+    keytab
+}
+
+/// A version 0x504 credential cache with a single service ticket valid until the end of 2037
+fn ccache() -> Vec<u8> {
+    fn counted(out: &mut Vec<u8>, data: &[u8]) {
+        out.extend(u32::try_from(data.len()).expect("short ccache field").to_be_bytes());
+        out.extend(data);
+    }
+    fn principal(out: &mut Vec<u8>, name_type: u32, components: &[&str]) {
+        out.extend(name_type.to_be_bytes());
+        out.extend(u32::try_from(components.len()).expect("few components").to_be_bytes());
+        counted(out, REALM.as_bytes());
+        for component in components {
+            counted(out, component.as_bytes());
+        }
+    }
+    // version, then an empty header
+    let mut ccache = vec![0x05, 0x04, 0, 0];
+    principal(&mut ccache, KRB5_NT_PRINCIPAL, &["alice"]);
+    principal(&mut ccache, KRB5_NT_PRINCIPAL, &["alice"]);
+    principal(&mut ccache, KRB5_NT_SRV_INST, &["HTTP", "localhost"]);
+    ccache.extend(AES256_CTS_HMAC_SHA1_96.to_be_bytes());
+    counted(&mut ccache, &SESSION_KEY);
+    // auth, start, end and renew-till times
+    for time in [AUTH_TIME.0, AUTH_TIME.0, END_TIME.0, 0] {
+        ccache.extend(time.to_be_bytes());
+    }
+    // not encrypted in a session key, no flags, addresses or authorization data
+    ccache.push(0);
+    ccache.extend([0; 12]);
+    counted(&mut ccache, &ticket());
+    counted(&mut ccache, &[]);
+    ccache
+}
+
+/// The DER encoded ticket (RFC 4120, section 5.3), encrypted in [`SERVICE_KEY`]
+fn ticket() -> Vec<u8> {
+    let integer = |value: u8| der(0x02, &[value]);
+    let string = |value: &str| der(0x1b, value.as_bytes());
+    let time = |value: &str| der(0x18, value.as_bytes());
+    let name = |name_type: u8, components: &[&str]| {
+        let components: Vec<u8> = components.iter().flat_map(|component| string(component)).collect();
+        der(
+            0x30,
+            &[der(0xa0, &integer(name_type)), der(0xa1, &der(0x30, &components))].concat(),
+        )
+    };
+    let session_key = der(
+        0x30,
+        &[der(0xa0, &integer(18)), der(0xa1, &der(0x04, &SESSION_KEY))].concat(),
+    );
+    let transited = der(0x30, &[der(0xa0, &integer(1)), der(0xa1, &der(0x04, &[]))].concat());
+    let enc_part = der(
+        0x63,
+        &der(
+            0x30,
+            &[
+                // no flags
+                der(0xa0, &der(0x03, &[0; 5])),
+                der(0xa1, &session_key),
+                der(0xa2, &string(REALM)),
+                der(0xa3, &name(1, &["alice"])),
+                der(0xa4, &transited),
+                der(0xa5, &time(AUTH_TIME.1)),
+                der(0xa7, &time(END_TIME.1)),
+            ]
+            .concat(),
+        ),
+    );
+    let encrypted = der(
+        0x30,
+        &[
+            der(0xa0, &integer(18)),
+            der(0xa1, &integer(1)),
+            der(0xa2, &der(0x04, &encrypt(&SERVICE_KEY, TICKET_KEY_USAGE, &enc_part))),
+        ]
+        .concat(),
+    );
+    let ticket = der(
+        0x30,
+        &[
+            der(0xa0, &integer(5)),
+            der(0xa1, &string(REALM)),
+            der(0xa2, &name(2, &["HTTP", "localhost"])),
+            der(0xa3, &encrypted),
+        ]
+        .concat(),
+    );
+    der(0x61, &ticket)
+}
+
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    match u8::try_from(content.len()) {
+        Ok(len @ 0..0x80) => out.push(len),
+        Ok(len) => out.extend([0x81, len]),
+        Err(_) => {
+            out.push(0x82);
+            out.extend(u16::try_from(content.len()).expect("short DER value").to_be_bytes());
+        }
+    }
+    out.extend(content);
+    out
+}
+
+/// aes256-cts-hmac-sha1-96 encryption (RFC 3962), with a fixed confounder
+fn encrypt(key: &[u8; 32], usage: u32, plaintext: &[u8]) -> Vec<u8> {
+    let derive = |purpose: u8| {
+        let mut constant = usage.to_be_bytes().to_vec();
+        constant.push(purpose);
+        derive_key(key, &constant)
+    };
+    let confounded = [&[0x2a; 16], plaintext].concat();
+    let mut ciphertext = aes_cts(&derive(0xaa), &confounded);
+    let integrity = PKey::hmac(&derive(0x55)).expect("HMAC accepts any key");
+    let mut signer = Signer::new(MessageDigest::sha1(), &integrity).expect("SHA-1 is available");
+    let checksum = signer.sign_oneshot_to_vec(&confounded).expect("HMAC never fails");
+    ciphertext.extend(&checksum[..12]);
+    ciphertext
+}
+
+/// DK of the simplified profile (RFC 3961, section 5.1), the AES random-to-key is the identity
+fn derive_key(key: &[u8; 32], constant: &[u8]) -> [u8; 32] {
+    let first = aes_block(key, &nfold(constant, 16));
+    let second = aes_block(key, &first);
+    let mut derived = [0; 32];
+    derived[..16].copy_from_slice(&first);
+    derived[16..].copy_from_slice(&second);
+    derived
+}
+
+fn aes_block(key: &[u8; 32], block: &[u8]) -> [u8; 16] {
+    let mut crypter = Crypter::new(Cipher::aes_256_ecb(), Mode::Encrypt, key, None).expect("AES-256 takes 32 bytes");
+    crypter.pad(false);
+    let mut out = [0; 32];
+    let written = crypter.update(block, &mut out).expect("one whole block");
+    assert_eq!(written, 16);
+    out[..16].try_into().expect("one block")
+}
+
+/// CBC with ciphertext stealing, swapping the last two blocks even if the input fills them (RFC 3962, section 5)
+fn aes_cts(key: &[u8; 32], plaintext: &[u8]) -> Vec<u8> {
+    assert!(plaintext.len() > 16, "the confounder alone fills a block");
+    let mut blocks = Vec::new();
+    let mut previous = [0; 16];
+    for chunk in plaintext.chunks(16) {
+        let mut block = [0; 16];
+        block[..chunk.len()].copy_from_slice(chunk);
+        for (byte, chained) in block.iter_mut().zip(previous) {
+            *byte ^= chained;
+        }
+        previous = aes_block(key, &block);
+        blocks.push(previous);
+    }
+    let last = blocks.pop().expect("more than one block");
+    let second_last = blocks.pop().expect("more than one block");
+    let mut ciphertext: Vec<u8> = blocks.concat();
+    ciphertext.extend(last);
+    ciphertext.extend(&second_last[..plaintext.len() - ciphertext.len()]);
+    ciphertext
+}
+
+/// Stretches `input` to `len` bytes (RFC 3961, section 5.1), ported from MIT krb5
+fn nfold(input: &[u8], len: usize) -> Vec<u8> {
+    let in_len = input.len();
+    let (mut a, mut b) = (len, in_len);
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    let lcm = len * in_len / a;
+    let in_bits = in_len << 3;
+    let mut out = vec![0u8; len];
+    let mut carry = 0usize;
+    for i in (0..lcm).rev() {
+        let msbit = ((in_bits - 1) + ((in_bits + 13) * (i / in_len)) + ((in_len - (i % in_len)) << 3)) % in_bits;
+        let high = usize::from(input[((in_len - 1) - (msbit >> 3)) % in_len]);
+        let low = usize::from(input[(in_len - (msbit >> 3)) % in_len]);
+        carry += (((high << 8) | low) >> ((msbit & 7) + 1)) & 0xff;
+        carry += usize::from(out[i % len]);
+        out[i % len] = (carry & 0xff) as u8;
+        carry >>= 8;
+    }
+    if carry != 0 {
+        for byte in out.iter_mut().rev() {
+            carry += usize::from(*byte);
+            *byte = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+    }
+    out
+}
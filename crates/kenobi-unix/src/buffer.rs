@@ -1,4 +1,6 @@
-use std::{ffi::c_void, ptr};
+use std::{ffi::c_void, marker::PhantomData, ptr};
+
+use kenobi_core::channel_bindings::{Address, ChannelBindings};
 
 use libgssapi_sys::{gss_buffer_desc, gss_buffer_desc_struct, gss_channel_bindings_struct, gss_release_buffer};
 
@@ -29,21 +31,47 @@ pub(crate) fn empty_token() -> gss_buffer_desc {
     }
 }
 
-pub(crate) fn as_channel_bindings(arr: &[u8]) -> gss_channel_bindings_struct {
-    gss_channel_bindings_struct {
-        initiator_addrtype: 0,
-        initiator_address: gss_buffer_desc_struct {
-            length: 0,
-            value: ptr::null_mut(),
-        },
-        acceptor_addrtype: 0,
-        acceptor_address: gss_buffer_desc_struct {
-            length: 0,
-            value: ptr::null_mut(),
-        },
-        application_data: gss_buffer_desc_struct {
-            length: arr.len(),
-            value: arr.as_ptr() as *mut c_void,
-        },
+fn borrowed_buffer(bytes: &[u8]) -> gss_buffer_desc_struct {
+    if bytes.is_empty() {
+        return empty_token();
+    }
+    gss_buffer_desc_struct {
+        length: bytes.len(),
+        value: bytes.as_ptr() as *mut c_void,
+    }
+}
+
+/// `gss_channel_bindings_struct` borrowing the application data of the `ChannelBindings` it was created from
+///
+/// The encoded addresses are owned, the borrow keeps the application data in place while the struct lives
+pub(crate) struct RawChannelBindings<'a> {
+    raw: gss_channel_bindings_struct,
+    _addresses: [Vec<u8>; 2],
+    _bindings: PhantomData<&'a ChannelBindings>,
+}
+impl<'a> RawChannelBindings<'a> {
+    /// `None` if there is nothing to bind to
+    pub(crate) fn new(bindings: &'a ChannelBindings) -> Option<Self> {
+        if bindings.is_empty() {
+            return None;
+        }
+        let encode = |address: Option<&Address>| address.map_or((0, Vec::new()), |a| (a.address_type(), a.to_bytes()));
+        let (initiator_addrtype, initiator) = encode(bindings.initiator_address.as_ref());
+        let (acceptor_addrtype, acceptor) = encode(bindings.acceptor_address.as_ref());
+        let raw = gss_channel_bindings_struct {
+            initiator_addrtype,
+            initiator_address: borrowed_buffer(&initiator),
+            acceptor_addrtype,
+            acceptor_address: borrowed_buffer(&acceptor),
+            application_data: borrowed_buffer(bindings.application_data.as_deref().unwrap_or_default()),
+        };
+        Some(Self {
+            raw,
+            _addresses: [initiator, acceptor],
+            _bindings: PhantomData,
+        })
+    }
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut gss_channel_bindings_struct {
+        &raw mut self.raw
     }
 }
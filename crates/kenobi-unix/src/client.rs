@@ -6,21 +6,28 @@ use std::{
     time::{Duration, Instant},
 };
 
-use kenobi_core::{cred::usage::OutboundUsable, flags::CapabilityFlags};
+use kenobi_core::{
+    channel_bindings::ChannelBindings, cred::usage::OutboundUsable, flags::CapabilityFlags, prf::PrfKey, qop::Qop,
+};
 use libgssapi_sys::{
-    _GSS_C_INDEFINITE, GSS_C_CONF_FLAG, GSS_C_DELEG_FLAG, GSS_C_INTEG_FLAG, GSS_C_MUTUAL_FLAG, GSS_S_COMPLETE,
-    GSS_S_CONTINUE_NEEDED, gss_buffer_desc_struct, gss_delete_sec_context, gss_init_sec_context,
+    _GSS_C_INDEFINITE, GSS_C_CONF_FLAG, GSS_C_DELEG_FLAG, GSS_C_DELEG_POLICY_FLAG, GSS_C_INTEG_FLAG, GSS_C_MUTUAL_FLAG,
+    GSS_C_REPLAY_FLAG, GSS_S_COMPLETE, GSS_S_CONTINUE_NEEDED, gss_buffer_desc_struct, gss_delete_sec_context,
+    gss_init_sec_context,
 };
 
 use crate::{
     Error,
-    buffer::{Token, as_channel_bindings, empty_token},
+    buffer::{RawChannelBindings, Token, empty_token},
     context::{ContextHandle, SessionKey},
     cred::Credentials,
     error::{GssErrorCode, MechanismErrorCode},
-    mech_kerberos,
+    mech_oid,
     name::NameHandle,
-    sign_encrypt,
+    sign_encrypt::{
+        self,
+        iov::{self, InPlacePlaintext, IovBuffer, IovLayout},
+    },
+    split::{self, Opener, Sealer},
 };
 mod builder;
 mod typestate;
@@ -80,6 +87,9 @@ impl<CU, S, E> ClientContext<CU, S, E, MaybeDelegation> {
     #[allow(clippy::type_complexity)]
     /// Statically ensures the `ClientContext` is set up to forwarding the TGT
     ///
+    /// When delegation was requested using `delegate_if_ok_as_delegate`, this only succeeds if the target was actually
+    /// trusted for delegation and the TGT got forwarded
+    ///
     /// # Errors
     /// an error is `GSS_C_DELEG_FLAG` not being set on the finished context, but gives back the context without Delegation operations enabled
     pub fn check_delegation(
@@ -111,13 +121,26 @@ impl<CU, S1, E1, D1> ClientContext<CU, S1, E1, D1> {
     pub fn session_key(&self) -> Result<SessionKey, Error> {
         self.context.session_key()
     }
+    /// Derives `output_len` bytes from `input` and the keys of the context, identically on both sides (RFC 4401)
+    ///
+    /// # Errors
+    /// - `GSS_S_FAILURE` if `output_len` does not fit the length type of GSSAPI
+    /// - Forwards the failure from `gss_pseudo_random`
+    pub fn prf(&self, key: PrfKey, input: &[u8], output_len: usize) -> Result<Vec<u8>, Error> {
+        self.context.pseudo_random(key, input, output_len)
+    }
 }
 
 impl<CU, E, D> ClientContext<CU, Signing, E, D> {
     /// # Errors
     /// - Error from the underlying `gss_wrap`
     pub fn sign(&mut self, message: &[u8]) -> Result<sign_encrypt::Signed, Error> {
-        sign_encrypt::sign(&mut self.context, message)
+        sign_encrypt::sign(&mut self.context, Qop::DEFAULT, message)
+    }
+    /// # Errors
+    /// - Error from the underlying `gss_wrap`, e.g. if the mechanism does not support `qop`
+    pub fn sign_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<sign_encrypt::Signed, Error> {
+        sign_encrypt::sign(&mut self.context, qop, message)
     }
 
     /// # Errors
@@ -125,12 +148,183 @@ impl<CU, E, D> ClientContext<CU, Signing, E, D> {
     pub fn unwrap(&mut self, message: &[u8]) -> Result<sign_encrypt::Plaintext, Error> {
         sign_encrypt::unwrap_raw(&mut self.context, message)
     }
+    /// Creates a signature over `message`, which is sent separately from the message itself
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_get_mic`
+    pub fn get_mic(&mut self, message: &[u8]) -> Result<sign_encrypt::Mic, Error> {
+        sign_encrypt::get_mic(&mut self.context, Qop::DEFAULT, message)
+    }
+    /// # Errors
+    /// - Error from the underlying `gss_get_mic`, e.g. if the mechanism does not support `qop`
+    pub fn get_mic_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<sign_encrypt::Mic, Error> {
+        sign_encrypt::get_mic(&mut self.context, qop, message)
+    }
+
+    /// Returns the QOP the signature was created with
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_verify_mic`, see [`GssErrorCode::kind_verify`](crate::error::GssErrorCode::kind_verify)
+    pub fn verify_mic(&mut self, message: &[u8], mic: &[u8]) -> Result<Qop, Error> {
+        sign_encrypt::verify_mic(&mut self.context, message, mic)
+    }
+
+    /// Lengths of the regions needed around `data_len` bytes of data for [`sign_iov`](Self::sign_iov)
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov_length`
+    pub fn sign_iov_layout(&mut self, data_len: usize) -> Result<IovLayout, Error> {
+        iov::layout(&mut self.context, false, Qop::DEFAULT, data_len)
+    }
+    /// Like [`sign_iov_layout`](Self::sign_iov_layout), for [`sign_iov_with_qop`](Self::sign_iov_with_qop)
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov_length`, e.g. if the mechanism does not support `qop`
+    pub fn sign_iov_layout_with_qop(&mut self, data_len: usize, qop: Qop) -> Result<IovLayout, Error> {
+        iov::layout(&mut self.context, false, qop, data_len)
+    }
+    /// Signs the message in place, writing into the caller-provided header, padding and trailer regions
+    ///
+    /// Returns the lengths of the regions actually used
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov`
+    pub fn sign_iov(&mut self, buffers: &mut [IovBuffer<'_>]) -> Result<IovLayout, Error> {
+        iov::wrap(&mut self.context, false, Qop::DEFAULT, buffers)
+    }
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov`, e.g. if the mechanism does not support `qop`
+    pub fn sign_iov_with_qop(&mut self, buffers: &mut [IovBuffer<'_>], qop: Qop) -> Result<IovLayout, Error> {
+        iov::wrap(&mut self.context, false, qop, buffers)
+    }
+    /// Turns `message` into a signed token, reusing its allocation
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov`, leaving `message` unspecified
+    pub fn sign_in_place(&mut self, message: &mut Vec<u8>, associated: &[&[u8]]) -> Result<(), Error> {
+        iov::wrap_vec(&mut self.context, false, Qop::DEFAULT, message, associated)
+    }
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov`, e.g. if the mechanism does not support `qop`, leaving `message` unspecified
+    pub fn sign_in_place_with_qop(
+        &mut self,
+        message: &mut Vec<u8>,
+        associated: &[&[u8]],
+        qop: Qop,
+    ) -> Result<(), Error> {
+        iov::wrap_vec(&mut self.context, false, qop, message, associated)
+    }
+    /// Verifies and decrypts the message in place, returning whether it was encrypted
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_unwrap_iov`
+    pub fn unwrap_iov(&mut self, buffers: &mut [IovBuffer<'_>]) -> Result<bool, Error> {
+        iov::unwrap(&mut self.context, buffers)
+    }
+    /// Verifies and decrypts a whole token in place, returning the part of `token` holding the plaintext
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_unwrap_iov`
+    pub fn unwrap_in_place<'a>(
+        &mut self,
+        token: &'a mut [u8],
+        associated: &[&[u8]],
+    ) -> Result<InPlacePlaintext<'a>, Error> {
+        iov::unwrap_in_place(&mut self.context, token, associated)
+    }
+    /// Splits the context into halves for sending and receiving, which may be used from different threads
+    pub fn split(self) -> (Sealer<E>, Opener<E>) {
+        split::split(self.context)
+    }
+
+    /// Largest message whose wrap token is at most `max_token_len` bytes long
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_size_limit`
+    pub fn max_plaintext_len(&mut self, max_token_len: usize, encrypt: bool) -> Result<usize, Error> {
+        sign_encrypt::max_plaintext_len(&mut self.context, encrypt, Qop::DEFAULT, max_token_len)
+    }
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_size_limit`, e.g. if the mechanism does not support `qop`
+    pub fn max_plaintext_len_with_qop(
+        &mut self,
+        max_token_len: usize,
+        encrypt: bool,
+        qop: Qop,
+    ) -> Result<usize, Error> {
+        sign_encrypt::max_plaintext_len(&mut self.context, encrypt, qop, max_token_len)
+    }
+    /// Number of bytes wrapping adds to a message of `data_len` bytes
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov_length`
+    pub fn wrap_overhead(&mut self, data_len: usize, encrypt: bool) -> Result<usize, Error> {
+        sign_encrypt::wrap_overhead(&mut self.context, encrypt, Qop::DEFAULT, data_len)
+    }
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov_length`, e.g. if the mechanism does not support `qop`
+    pub fn wrap_overhead_with_qop(&mut self, data_len: usize, encrypt: bool, qop: Qop) -> Result<usize, Error> {
+        sign_encrypt::wrap_overhead(&mut self.context, encrypt, qop, data_len)
+    }
 }
 impl<CU, S, D> ClientContext<CU, S, Encryption, D> {
     /// # Errors
     /// - Error from the underlying `gss_wrap`
     pub fn encrypt(&mut self, message: &[u8]) -> Result<sign_encrypt::Encrypted, Error> {
-        sign_encrypt::encrypt(&mut self.context, message)
+        sign_encrypt::encrypt(&mut self.context, Qop::DEFAULT, message)
+    }
+    /// # Errors
+    /// - Error from the underlying `gss_wrap`, e.g. if the mechanism does not support `qop`
+    pub fn encrypt_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<sign_encrypt::Encrypted, Error> {
+        sign_encrypt::encrypt(&mut self.context, qop, message)
+    }
+
+    /// Lengths of the regions needed around `data_len` bytes of data for [`encrypt_iov`](Self::encrypt_iov)
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov_length`
+    pub fn encrypt_iov_layout(&mut self, data_len: usize) -> Result<IovLayout, Error> {
+        iov::layout(&mut self.context, true, Qop::DEFAULT, data_len)
+    }
+    /// Like [`encrypt_iov_layout`](Self::encrypt_iov_layout), for [`encrypt_iov_with_qop`](Self::encrypt_iov_with_qop)
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov_length`, e.g. if the mechanism does not support `qop`
+    pub fn encrypt_iov_layout_with_qop(&mut self, data_len: usize, qop: Qop) -> Result<IovLayout, Error> {
+        iov::layout(&mut self.context, true, qop, data_len)
+    }
+    /// Encrypts the data regions in place, writing into the caller-provided header, padding and trailer regions
+    ///
+    /// Returns the lengths of the regions actually used
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov`
+    /// - `GSS_S_FAILURE` if the mechanism did not encrypt the data
+    pub fn encrypt_iov(&mut self, buffers: &mut [IovBuffer<'_>]) -> Result<IovLayout, Error> {
+        iov::wrap(&mut self.context, true, Qop::DEFAULT, buffers)
+    }
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov`, e.g. if the mechanism does not support `qop`
+    pub fn encrypt_iov_with_qop(&mut self, buffers: &mut [IovBuffer<'_>], qop: Qop) -> Result<IovLayout, Error> {
+        iov::wrap(&mut self.context, true, qop, buffers)
+    }
+    /// Turns `message` into an encrypted token, reusing its allocation
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov`, leaving `message` unspecified
+    /// - `GSS_S_FAILURE` if the mechanism did not encrypt the data
+    pub fn encrypt_in_place(&mut self, message: &mut Vec<u8>, associated: &[&[u8]]) -> Result<(), Error> {
+        iov::wrap_vec(&mut self.context, true, Qop::DEFAULT, message, associated)
+    }
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov`, e.g. if the mechanism does not support `qop`, leaving `message` unspecified
+    pub fn encrypt_in_place_with_qop(
+        &mut self,
+        message: &mut Vec<u8>,
+        associated: &[&[u8]],
+        qop: Qop,
+    ) -> Result<(), Error> {
+        iov::wrap_vec(&mut self.context, true, qop, message, associated)
     }
 }
 
@@ -141,7 +335,7 @@ pub struct PendingClientContext<CU> {
     flags: CapabilityFlags,
     target_principal: Option<NameHandle>,
     requested_duration: Option<Duration>,
-    channel_bindings: Option<Box<[u8]>>,
+    channel_bindings: ChannelBindings,
     #[expect(dead_code)]
     valid_until: Instant,
 }
@@ -172,7 +366,7 @@ fn step<CU: OutboundUsable>(
     mut target_principal: Option<NameHandle>,
     token: Option<&[u8]>,
     requested_duration: Option<Duration>,
-    channel_bindings: Option<Box<[u8]>>,
+    channel_bindings: ChannelBindings,
 ) -> Result<StepOut<CU>, Error> {
     let mut ctx_ptr = ctx.as_mut().map(ContextHandle::as_mut).unwrap_or_default();
     let mut minor_status = 0;
@@ -190,26 +384,28 @@ fn step<CU: OutboundUsable>(
             value: slice.as_ptr() as *mut c_void,
         },
     );
-    let mut channel_application_buffer = channel_bindings.as_deref().map(as_channel_bindings);
-    match unsafe {
+    let mut channel_bindings_buffer = RawChannelBindings::new(&channel_bindings);
+    let status = unsafe {
         gss_init_sec_context(
             &raw mut minor_status,
             cred.as_raw().as_ptr(),
             &raw mut ctx_ptr,
             target_principal.as_mut().map_or(ptr::null_mut(), NameHandle::as_mut),
-            &mut mech_kerberos(),
+            &mut mech_oid(cred.mechanism()),
             convert_flags(flags),
             requested_duration.map_or(_GSS_C_INDEFINITE, |d| d.as_secs().min(u32::MAX.into()) as u32),
-            channel_application_buffer
+            channel_bindings_buffer
                 .as_mut()
-                .map_or(ptr::null_mut(), ptr::from_mut),
+                .map_or(ptr::null_mut(), RawChannelBindings::as_mut_ptr),
             &raw mut input_token,
             &raw mut mech_type,
             &raw mut next_token,
             &raw mut attributes,
             &raw mut remaining_seconds,
         )
-    } {
+    };
+    drop(channel_bindings_buffer);
+    match status {
         GSS_S_COMPLETE => Ok(StepOut::Finished(ClientContext {
             attributes,
             cred,
@@ -253,6 +449,9 @@ fn convert_flags(flags: CapabilityFlags) -> u32 {
     if flags.contains_all(CapabilityFlags::MUTUAL_AUTH) {
         out |= GSS_C_MUTUAL_FLAG;
     }
+    if flags.contains_all(CapabilityFlags::REPLAY_DETECT) {
+        out |= GSS_C_REPLAY_FLAG;
+    }
     if flags.contains_all(CapabilityFlags::INTEGRITY) {
         out |= GSS_C_INTEG_FLAG;
     }
@@ -262,5 +461,8 @@ fn convert_flags(flags: CapabilityFlags) -> u32 {
     if flags.contains_all(CapabilityFlags::DELEGATE) {
         out |= GSS_C_DELEG_FLAG;
     }
+    if flags.contains_all(CapabilityFlags::DELEGATE_IF_OK_AS_DELEGATE) {
+        out |= GSS_C_DELEG_POLICY_FLAG;
+    }
     out
 }
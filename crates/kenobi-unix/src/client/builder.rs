@@ -1,12 +1,17 @@
 use std::{sync::Arc, time::Duration};
 
-use kenobi_core::{channel_bindings::Channel, cred::usage::OutboundUsable, flags::CapabilityFlags};
+use kenobi_core::{
+    channel_bindings::{Address, Channel, ChannelBindingType, ChannelBindings},
+    cred::usage::OutboundUsable,
+    flags::CapabilityFlags,
+};
 use libgssapi_sys::GSS_C_NT_USER_NAME;
 
 use crate::{
     Error,
     client::{StepOut, step},
     cred::Credentials,
+    mech::Mechanism,
     name::NameHandle,
 };
 
@@ -16,7 +21,7 @@ pub struct ClientBuilder<CU> {
     target_principal: Option<NameHandle>,
     flags: CapabilityFlags,
     requested_duration: Option<Duration>,
-    channel_bindings: Option<Box<[u8]>>,
+    channel_bindings: ChannelBindings,
 }
 impl<CU: OutboundUsable> ClientBuilder<CU> {
     /// # Errors
@@ -30,11 +35,16 @@ impl<CU: OutboundUsable> ClientBuilder<CU> {
             target_principal,
             flags: CapabilityFlags::default(),
             requested_duration: None,
-            channel_bindings: None,
+            channel_bindings: ChannelBindings::default(),
         })
     }
 }
 impl<CU> ClientBuilder<CU> {
+    /// The mechanism of the credentials, which the context is established with
+    #[must_use]
+    pub fn mechanism(&self) -> Mechanism {
+        self.cred.mechanism()
+    }
     #[must_use]
     pub fn with_flag(mut self, flags: CapabilityFlags) -> Self {
         self.flags.add_flag(flags);
@@ -45,6 +55,10 @@ impl<CU> ClientBuilder<CU> {
         self.with_flag(CapabilityFlags::MUTUAL_AUTH)
     }
     #[must_use]
+    pub fn request_replay_detection(self) -> Self {
+        self.with_flag(CapabilityFlags::REPLAY_DETECT)
+    }
+    #[must_use]
     pub fn request_signing(self) -> Self {
         self.with_flag(CapabilityFlags::INTEGRITY)
     }
@@ -56,6 +70,13 @@ impl<CU> ClientBuilder<CU> {
     pub fn allow_delegation(self) -> Self {
         self.with_flag(CapabilityFlags::DELEGATE)
     }
+    /// Only forwards the TGT if the KDC marked the target service as OK-as-delegate (`GSS_C_DELEG_POLICY_FLAG`)
+    ///
+    /// Has no effect if unconstrained delegation was already allowed using `allow_delegation`
+    #[must_use]
+    pub fn delegate_if_ok_as_delegate(self) -> Self {
+        self.with_flag(CapabilityFlags::DELEGATE_IF_OK_AS_DELEGATE)
+    }
     #[must_use]
     pub fn request_duration(self, duration: Duration) -> Self {
         Self {
@@ -68,12 +89,27 @@ impl<CU> ClientBuilder<CU> {
     pub fn bind_to_channel<C: Channel>(self, channel: &C) -> Result<Self, C::Error> {
         match channel.channel_bindings() {
             Err(e) => Err(e),
-            Ok(bindings) => Ok(Self {
-                channel_bindings: bindings.map(Vec::into_boxed_slice),
-                ..self
-            }),
+            Ok(application_data) => Ok(self.with_application_data(application_data)),
         }
     }
+    /// Like `bind_to_channel`, using channel bindings of the given type
+    ///
+    /// # Errors
+    /// Forwards the failure of the underlying `Channel`
+    pub fn bind_to_channel_as<C: Channel>(self, channel: &C, kind: ChannelBindingType) -> Result<Self, C::Error> {
+        Ok(self.with_application_data(channel.channel_bindings_of(kind)?))
+    }
+    /// Binds the context to the network addresses of the initiator and the acceptor, which the peer has to match
+    #[must_use]
+    pub fn bind_to_addresses(mut self, initiator: Option<Address>, acceptor: Option<Address>) -> Self {
+        self.channel_bindings.initiator_address = initiator;
+        self.channel_bindings.acceptor_address = acceptor;
+        self
+    }
+    fn with_application_data(mut self, application_data: Option<Vec<u8>>) -> Self {
+        self.channel_bindings.application_data = application_data;
+        self
+    }
 }
 impl<CU: OutboundUsable> ClientBuilder<CU> {
     pub fn initialize(self) -> Result<StepOut<CU>, Error> {
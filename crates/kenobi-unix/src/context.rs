@@ -4,17 +4,18 @@ use std::{
     ptr::NonNull,
 };
 
+use kenobi_core::prf::PrfKey;
 use libgssapi_sys::{
-    GSS_C_INQ_SSPI_SESSION_KEY, gss_buffer_set_desc_struct, gss_ctx_id_struct, gss_delete_sec_context,
-    gss_inquire_sec_context_by_oid, gss_release_buffer_set,
+    _GSS_S_FAILURE, GSS_C_INQ_SSPI_SESSION_KEY, GSS_C_PRF_KEY_FULL, GSS_C_PRF_KEY_PARTIAL, gss_buffer_desc,
+    gss_buffer_set_desc_struct, gss_ctx_id_struct, gss_delete_sec_context, gss_inquire_sec_context_by_oid,
+    gss_pseudo_random, gss_release_buffer, gss_release_buffer_set,
 };
 
 use crate::Error;
 
 pub(crate) struct ContextHandle(NonNull<gss_ctx_id_struct>);
-// Does not expose a mutable interface and is (supposed to be) sole owner of the underlying context handle
+// Sole owner of the underlying context handle. Not Sync, as GSSAPI does not guarantee concurrent calls on one context to be safe
 unsafe impl Send for ContextHandle {}
-unsafe impl Sync for ContextHandle {}
 impl ContextHandle {
     /// # Safety
     /// Pointer must be a valid living security context
@@ -44,9 +45,55 @@ impl ContextHandle {
             return Err(minor_err);
         }
         let Some(buffer_set) = NonNull::new(buffer_set) else {
-            panic!("invalid session key")
+            return Err(Error::gss(_GSS_S_FAILURE).unwrap());
         };
-        Ok(SessionKey(buffer_set))
+        // Released on drop even if it holds no key
+        let session_key = SessionKey(buffer_set);
+        let set = unsafe { *buffer_set.as_ptr() };
+        if set.elements.is_null() || set.count == 0 || unsafe { (*set.elements).value.is_null() } {
+            return Err(Error::gss(_GSS_S_FAILURE).unwrap());
+        }
+        Ok(session_key)
+    }
+    pub fn pseudo_random(&self, key: PrfKey, input: &[u8], output_len: usize) -> Result<Vec<u8>, Error> {
+        let prf_key = match key {
+            PrfKey::Partial => GSS_C_PRF_KEY_PARTIAL,
+            PrfKey::Full => GSS_C_PRF_KEY_FULL,
+        };
+        let Ok(desired_output_len) = output_len.try_into() else {
+            return Err(Error::gss(_GSS_S_FAILURE).unwrap());
+        };
+        let mut minor = 0;
+        let mut input_buffer = gss_buffer_desc {
+            length: input.len(),
+            value: input.as_ptr() as *mut std::ffi::c_void,
+        };
+        let mut output_buffer = gss_buffer_desc {
+            length: 0,
+            value: std::ptr::null_mut(),
+        };
+        let major = unsafe {
+            gss_pseudo_random(
+                &raw mut minor,
+                self.0.as_ptr(),
+                prf_key as i32,
+                &raw mut input_buffer,
+                desired_output_len,
+                &raw mut output_buffer,
+            )
+        };
+        if let Some(err) = Error::gss(major) {
+            return Err(err);
+        } else if let Some(minor_err) = Error::mechanism(minor) {
+            return Err(minor_err);
+        }
+        if output_buffer.value.is_null() {
+            return Ok(Vec::new());
+        }
+        let output =
+            unsafe { std::slice::from_raw_parts(output_buffer.value as *const u8, output_buffer.length) }.to_vec();
+        let _maj = unsafe { gss_release_buffer(&raw mut minor, &raw mut output_buffer) };
+        Ok(output)
     }
 }
 impl Drop for ContextHandle {
@@ -62,16 +109,16 @@ impl Debug for ContextHandle {
     }
 }
 
+/// Buffer set holding at least one non-empty buffer, the key being the first
 pub struct SessionKey(NonNull<gss_buffer_set_desc_struct>);
 unsafe impl Sync for SessionKey {}
 unsafe impl Send for SessionKey {}
 impl SessionKey {
     pub fn as_slice(&self) -> &[u8] {
         let deref: gss_buffer_set_desc_struct = unsafe { *self.0.as_ptr() };
-        assert!(!deref.elements.is_null());
-        assert_ne!(deref.count, 0);
-        let key = unsafe { std::slice::from_raw_parts_mut(deref.elements, deref.count) }[0];
-        unsafe { std::slice::from_raw_parts(key.value as *const u8, key.length as usize) }
+        // Checked on creation
+        let key = unsafe { *deref.elements };
+        unsafe { std::slice::from_raw_parts(key.value as *const u8, key.length) }
     }
 }
 impl Drop for SessionKey {
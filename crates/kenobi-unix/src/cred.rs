@@ -38,10 +38,7 @@ impl<Usage: CredentialsUsage> Credentials<Usage> {
         let mut minor = 0;
         let mut validity = 0;
         let mut cred_handle = std::ptr::null_mut();
-        let mut mech = match mechanism {
-            Mechanism::KerberosV5 => crate::mech_kerberos(),
-            Mechanism::Spnego => crate::mech_spnego(),
-        };
+        let mut mech = crate::mech_oid(mechanism);
         let mut mech_set = gss_OID_set_desc {
             count: 1,
             elements: &raw mut mech,
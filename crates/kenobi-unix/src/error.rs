@@ -3,10 +3,13 @@ use std::{fmt::Display, num::NonZero};
 use libgssapi_sys::{
     _GSS_S_BAD_BINDINGS, _GSS_S_BAD_NAME, _GSS_S_BAD_NAMETYPE, _GSS_S_BAD_SIG, _GSS_S_CONTEXT_EXPIRED,
     _GSS_S_CREDENTIALS_EXPIRED, _GSS_S_DEFECTIVE_CREDENTIAL, _GSS_S_DEFECTIVE_TOKEN, _GSS_S_FAILURE, _GSS_S_NO_CONTEXT,
-    _GSS_S_NO_CRED, GSS_C_GSS_CODE, GSS_C_MECH_CODE, GSS_S_DUPLICATE_TOKEN, GSS_S_OLD_TOKEN, gss_buffer_desc_struct,
-    gss_display_status, gss_release_buffer,
+    _GSS_S_NO_CRED, GSS_C_GSS_CODE, GSS_C_MECH_CODE, GSS_S_DUPLICATE_TOKEN, GSS_S_GAP_TOKEN, GSS_S_OLD_TOKEN,
+    GSS_S_UNSEQ_TOKEN, gss_buffer_desc_struct, gss_display_status, gss_release_buffer,
 };
 
+/// Bits of a major status holding the routine error, as opposed to calling errors and supplementary info
+const ROUTINE_ERROR_MASK: u32 = 0x00ff_0000;
+
 #[derive(Clone, Copy, Debug)]
 pub struct MechanismErrorCode(NonZero<u32>);
 impl MechanismErrorCode {
@@ -63,6 +66,34 @@ impl GssErrorCode {
             _ => None,
         }
     }
+    /// Classifies the failure of `gss_verify_mic` and `gss_unwrap`
+    ///
+    /// Supplementary information (duplicate, old or out of sequence tokens) is only reported when there is no routine error
+    #[must_use]
+    pub fn kind_verify(self) -> Option<GssVerifyErrorKind> {
+        use GssVerifyErrorKind as Kind;
+        let code = u32::from(self.0);
+        match code & ROUTINE_ERROR_MASK {
+            0 => {}
+            _GSS_S_BAD_SIG => return Some(Kind::BadSignature),
+            _GSS_S_CONTEXT_EXPIRED => return Some(Kind::ContextExpired),
+            _GSS_S_DEFECTIVE_TOKEN => return Some(Kind::DefectiveToken),
+            _GSS_S_NO_CONTEXT => return Some(Kind::NoContext),
+            _GSS_S_FAILURE => return Some(Kind::Failure),
+            _ => return None,
+        }
+        if code & GSS_S_DUPLICATE_TOKEN != 0 {
+            Some(Kind::DuplicateToken)
+        } else if code & GSS_S_OLD_TOKEN != 0 {
+            Some(Kind::OldToken)
+        } else if code & GSS_S_UNSEQ_TOKEN != 0 {
+            Some(Kind::UnsequencedToken)
+        } else if code & GSS_S_GAP_TOKEN != 0 {
+            Some(Kind::GapToken)
+        } else {
+            None
+        }
+    }
 }
 impl Display for GssErrorCode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -160,3 +191,17 @@ pub enum GssAccErrorKind {
     NoContext,
     OldToken,
 }
+
+#[derive(Clone, Copy, Debug)]
+pub enum GssVerifyErrorKind {
+    BadSignature,
+    ContextExpired,
+    DefectiveToken,
+    DuplicateToken,
+    Failure,
+    /// An earlier token has not been received yet
+    GapToken,
+    NoContext,
+    OldToken,
+    UnsequencedToken,
+}
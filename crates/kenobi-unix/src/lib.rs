@@ -11,6 +11,7 @@ pub mod mech;
 mod name;
 pub mod server;
 pub mod sign_encrypt;
+pub mod split;
 
 static MECH_KERBEROS: &[u8] = b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x02";
 static MECH_SPNEGO: &[u8] = b"\x2b\x06\x01\x05\x05\x02";
@@ -21,11 +22,11 @@ fn oid(mech: &'static [u8]) -> gss_OID_desc {
         elements: mech.as_ptr() as *mut c_void,
     }
 }
-fn mech_kerberos() -> gss_OID_desc {
-    oid(MECH_KERBEROS)
-}
-fn mech_spnego() -> gss_OID_desc {
-    oid(MECH_SPNEGO)
+fn mech_oid(mechanism: mech::Mechanism) -> gss_OID_desc {
+    match mechanism {
+        mech::Mechanism::KerberosV5 => oid(MECH_KERBEROS),
+        mech::Mechanism::Spnego => oid(MECH_SPNEGO),
+    }
 }
 
 pub mod typestate {
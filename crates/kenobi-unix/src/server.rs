@@ -8,21 +8,32 @@ use std::{
 };
 
 use kenobi_core::{
+    channel_bindings::{ChannelBindingPolicy, ChannelBindings},
     cred::usage::{InboundUsable, Outbound},
+    mech::Mechanism,
+    prf::PrfKey,
+    qop::Qop,
     typestate::{Encryption, MaybeDelegation, MaybeEncryption, MaybeSigning, NoEncryption, NoSigning, Signing},
 };
 use libgssapi_sys::{
-    GSS_C_CONF_FLAG, GSS_C_INTEG_FLAG, GSS_S_COMPLETE, GSS_S_CONTINUE_NEEDED, gss_accept_sec_context,
-    gss_buffer_desc_struct, gss_inquire_context,
+    _GSS_S_BAD_BINDINGS, GSS_C_CONF_FLAG, GSS_C_INTEG_FLAG, GSS_S_COMPLETE, GSS_S_CONTINUE_NEEDED,
+    gss_accept_sec_context, gss_buffer_desc_struct, gss_delete_sec_context, gss_inquire_context,
 };
 
+/// Set on contexts whose client sent matching channel bindings, missing from headers before MIT krb5 1.19
+const GSS_C_CHANNEL_BOUND_FLAG: u32 = 2048;
+
 use crate::{
     Error,
-    buffer::{Token, as_channel_bindings, empty_token},
-    context::ContextHandle,
+    buffer::{RawChannelBindings, Token, empty_token},
+    context::{ContextHandle, SessionKey},
     cred::Credentials,
     name::NameHandle,
-    sign_encrypt,
+    sign_encrypt::{
+        self,
+        iov::{self, InPlacePlaintext, IovBuffer, IovLayout},
+    },
+    split::{self, Opener, Sealer},
 };
 pub use builder::ServerBuilder;
 mod builder;
@@ -50,6 +61,32 @@ impl<CU, S, E, D> ServerContext<CU, S, E, D> {
     pub fn last_token(&self) -> Option<&[u8]> {
         self.last_token.as_ref().map(Token::as_slice)
     }
+    /// # Errors
+    /// Forwards the failure from `gss_inquire_sec_context_by_oid`
+    pub fn session_key(&self) -> Result<SessionKey, Error> {
+        self.context.session_key()
+    }
+    /// Derives `output_len` bytes from `input` and the keys of the context, identically on both sides (RFC 4401)
+    ///
+    /// # Errors
+    /// - `GSS_S_FAILURE` if `output_len` does not fit the length type of GSSAPI
+    /// - Forwards the failure from `gss_pseudo_random`
+    pub fn prf(&self, key: PrfKey, input: &[u8], output_len: usize) -> Result<Vec<u8>, Error> {
+        self.context.pseudo_random(key, input, output_len)
+    }
+    #[must_use]
+    pub fn mechanism(&self) -> Mechanism {
+        self.cred.mechanism()
+    }
+    /// Whether the client bound the context to the same channel, as reported by MIT krb5 1.19 and newer
+    #[must_use]
+    pub fn channel_bound(&self) -> bool {
+        self.attributes & GSS_C_CHANNEL_BOUND_FLAG != 0
+    }
+    /// Takes the credentials the client delegated, if it did so
+    pub fn take_delegated_credentials(&mut self) -> Option<Credentials<Outbound>> {
+        self.delegated_creds.take()
+    }
     pub fn client_name(&mut self) -> Result<impl Display + Send + Sync, Error> {
         let mut min = 0;
         let mut initiator_name = ptr::null_mut();
@@ -114,7 +151,12 @@ impl<CU, E, D> ServerContext<CU, Signing, E, D> {
     /// # Errors
     /// - Error from the underlying `gss_wrap`
     pub fn sign(&mut self, message: &[u8]) -> Result<sign_encrypt::Signed, crate::Error> {
-        sign_encrypt::sign(&mut self.context, message)
+        sign_encrypt::sign(&mut self.context, Qop::DEFAULT, message)
+    }
+    /// # Errors
+    /// - Error from the underlying `gss_wrap`, e.g. if the mechanism does not support `qop`
+    pub fn sign_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<sign_encrypt::Signed, Error> {
+        sign_encrypt::sign(&mut self.context, qop, message)
     }
 
     /// # Errors
@@ -122,12 +164,183 @@ impl<CU, E, D> ServerContext<CU, Signing, E, D> {
     pub fn unwrap(&mut self, message: &[u8]) -> Result<sign_encrypt::Plaintext, crate::Error> {
         sign_encrypt::unwrap_raw(&mut self.context, message)
     }
+    /// Creates a signature over `message`, which is sent separately from the message itself
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_get_mic`
+    pub fn get_mic(&mut self, message: &[u8]) -> Result<sign_encrypt::Mic, Error> {
+        sign_encrypt::get_mic(&mut self.context, Qop::DEFAULT, message)
+    }
+    /// # Errors
+    /// - Error from the underlying `gss_get_mic`, e.g. if the mechanism does not support `qop`
+    pub fn get_mic_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<sign_encrypt::Mic, Error> {
+        sign_encrypt::get_mic(&mut self.context, qop, message)
+    }
+
+    /// Returns the QOP the signature was created with
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_verify_mic`, see [`GssErrorCode::kind_verify`](crate::error::GssErrorCode::kind_verify)
+    pub fn verify_mic(&mut self, message: &[u8], mic: &[u8]) -> Result<Qop, Error> {
+        sign_encrypt::verify_mic(&mut self.context, message, mic)
+    }
+
+    /// Lengths of the regions needed around `data_len` bytes of data for [`sign_iov`](Self::sign_iov)
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov_length`
+    pub fn sign_iov_layout(&mut self, data_len: usize) -> Result<IovLayout, Error> {
+        iov::layout(&mut self.context, false, Qop::DEFAULT, data_len)
+    }
+    /// Like [`sign_iov_layout`](Self::sign_iov_layout), for [`sign_iov_with_qop`](Self::sign_iov_with_qop)
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov_length`, e.g. if the mechanism does not support `qop`
+    pub fn sign_iov_layout_with_qop(&mut self, data_len: usize, qop: Qop) -> Result<IovLayout, Error> {
+        iov::layout(&mut self.context, false, qop, data_len)
+    }
+    /// Signs the message in place, writing into the caller-provided header, padding and trailer regions
+    ///
+    /// Returns the lengths of the regions actually used
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov`
+    pub fn sign_iov(&mut self, buffers: &mut [IovBuffer<'_>]) -> Result<IovLayout, Error> {
+        iov::wrap(&mut self.context, false, Qop::DEFAULT, buffers)
+    }
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov`, e.g. if the mechanism does not support `qop`
+    pub fn sign_iov_with_qop(&mut self, buffers: &mut [IovBuffer<'_>], qop: Qop) -> Result<IovLayout, Error> {
+        iov::wrap(&mut self.context, false, qop, buffers)
+    }
+    /// Turns `message` into a signed token, reusing its allocation
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov`, leaving `message` unspecified
+    pub fn sign_in_place(&mut self, message: &mut Vec<u8>, associated: &[&[u8]]) -> Result<(), Error> {
+        iov::wrap_vec(&mut self.context, false, Qop::DEFAULT, message, associated)
+    }
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov`, e.g. if the mechanism does not support `qop`, leaving `message` unspecified
+    pub fn sign_in_place_with_qop(
+        &mut self,
+        message: &mut Vec<u8>,
+        associated: &[&[u8]],
+        qop: Qop,
+    ) -> Result<(), Error> {
+        iov::wrap_vec(&mut self.context, false, qop, message, associated)
+    }
+    /// Verifies and decrypts the message in place, returning whether it was encrypted
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_unwrap_iov`
+    pub fn unwrap_iov(&mut self, buffers: &mut [IovBuffer<'_>]) -> Result<bool, Error> {
+        iov::unwrap(&mut self.context, buffers)
+    }
+    /// Verifies and decrypts a whole token in place, returning the part of `token` holding the plaintext
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_unwrap_iov`
+    pub fn unwrap_in_place<'a>(
+        &mut self,
+        token: &'a mut [u8],
+        associated: &[&[u8]],
+    ) -> Result<InPlacePlaintext<'a>, Error> {
+        iov::unwrap_in_place(&mut self.context, token, associated)
+    }
+    /// Splits the context into halves for sending and receiving, which may be used from different threads
+    pub fn split(self) -> (Sealer<E>, Opener<E>) {
+        split::split(self.context)
+    }
+
+    /// Largest message whose wrap token is at most `max_token_len` bytes long
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_size_limit`
+    pub fn max_plaintext_len(&mut self, max_token_len: usize, encrypt: bool) -> Result<usize, Error> {
+        sign_encrypt::max_plaintext_len(&mut self.context, encrypt, Qop::DEFAULT, max_token_len)
+    }
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_size_limit`, e.g. if the mechanism does not support `qop`
+    pub fn max_plaintext_len_with_qop(
+        &mut self,
+        max_token_len: usize,
+        encrypt: bool,
+        qop: Qop,
+    ) -> Result<usize, Error> {
+        sign_encrypt::max_plaintext_len(&mut self.context, encrypt, qop, max_token_len)
+    }
+    /// Number of bytes wrapping adds to a message of `data_len` bytes
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov_length`
+    pub fn wrap_overhead(&mut self, data_len: usize, encrypt: bool) -> Result<usize, Error> {
+        sign_encrypt::wrap_overhead(&mut self.context, encrypt, Qop::DEFAULT, data_len)
+    }
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov_length`, e.g. if the mechanism does not support `qop`
+    pub fn wrap_overhead_with_qop(&mut self, data_len: usize, encrypt: bool, qop: Qop) -> Result<usize, Error> {
+        sign_encrypt::wrap_overhead(&mut self.context, encrypt, qop, data_len)
+    }
 }
 impl<CU, S, D> ServerContext<CU, S, Encryption, D> {
     /// # Errors
     /// - Error from the underlying `gss_wrap`
     pub fn encrypt(&mut self, message: &[u8]) -> Result<sign_encrypt::Encrypted, crate::Error> {
-        sign_encrypt::encrypt(&mut self.context, message)
+        sign_encrypt::encrypt(&mut self.context, Qop::DEFAULT, message)
+    }
+    /// # Errors
+    /// - Error from the underlying `gss_wrap`, e.g. if the mechanism does not support `qop`
+    pub fn encrypt_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<sign_encrypt::Encrypted, Error> {
+        sign_encrypt::encrypt(&mut self.context, qop, message)
+    }
+
+    /// Lengths of the regions needed around `data_len` bytes of data for [`encrypt_iov`](Self::encrypt_iov)
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov_length`
+    pub fn encrypt_iov_layout(&mut self, data_len: usize) -> Result<IovLayout, Error> {
+        iov::layout(&mut self.context, true, Qop::DEFAULT, data_len)
+    }
+    /// Like [`encrypt_iov_layout`](Self::encrypt_iov_layout), for [`encrypt_iov_with_qop`](Self::encrypt_iov_with_qop)
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov_length`, e.g. if the mechanism does not support `qop`
+    pub fn encrypt_iov_layout_with_qop(&mut self, data_len: usize, qop: Qop) -> Result<IovLayout, Error> {
+        iov::layout(&mut self.context, true, qop, data_len)
+    }
+    /// Encrypts the data regions in place, writing into the caller-provided header, padding and trailer regions
+    ///
+    /// Returns the lengths of the regions actually used
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov`
+    /// - `GSS_S_FAILURE` if the mechanism did not encrypt the data
+    pub fn encrypt_iov(&mut self, buffers: &mut [IovBuffer<'_>]) -> Result<IovLayout, Error> {
+        iov::wrap(&mut self.context, true, Qop::DEFAULT, buffers)
+    }
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov`, e.g. if the mechanism does not support `qop`
+    pub fn encrypt_iov_with_qop(&mut self, buffers: &mut [IovBuffer<'_>], qop: Qop) -> Result<IovLayout, Error> {
+        iov::wrap(&mut self.context, true, qop, buffers)
+    }
+    /// Turns `message` into an encrypted token, reusing its allocation
+    ///
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov`, leaving `message` unspecified
+    /// - `GSS_S_FAILURE` if the mechanism did not encrypt the data
+    pub fn encrypt_in_place(&mut self, message: &mut Vec<u8>, associated: &[&[u8]]) -> Result<(), Error> {
+        iov::wrap_vec(&mut self.context, true, Qop::DEFAULT, message, associated)
+    }
+    /// # Errors
+    /// - Error from the underlying `gss_wrap_iov`, e.g. if the mechanism does not support `qop`, leaving `message` unspecified
+    pub fn encrypt_in_place_with_qop(
+        &mut self,
+        message: &mut Vec<u8>,
+        associated: &[&[u8]],
+        qop: Qop,
+    ) -> Result<(), Error> {
+        iov::wrap_vec(&mut self.context, true, qop, message, associated)
     }
 }
 
@@ -135,10 +348,12 @@ pub struct PendingServerContext<CU> {
     context: ContextHandle,
     cred: Arc<Credentials<CU>>,
     next_token: Token,
+    channel_bindings: ChannelBindings,
+    policy: ChannelBindingPolicy,
 }
 impl<CU: InboundUsable> PendingServerContext<CU> {
     pub fn step(self, token: &[u8]) -> Result<StepOut<CU>, Error> {
-        step(Some(self.context), self.cred, token, None)
+        step(Some(self.context), self.cred, token, self.channel_bindings, self.policy)
     }
 }
 impl<CU> PendingServerContext<CU> {
@@ -152,7 +367,8 @@ fn step<CU: InboundUsable>(
     mut ctx: Option<ContextHandle>,
     cred: Arc<Credentials<CU>>,
     token: &[u8],
-    channel_bindings: Option<&[u8]>,
+    channel_bindings: ChannelBindings,
+    policy: ChannelBindingPolicy,
 ) -> Result<StepOut<CU>, Error> {
     let mut ctx_ptr = ctx.as_mut().map(ContextHandle::as_mut).unwrap_or_default();
     let mut minor = 0;
@@ -160,18 +376,23 @@ fn step<CU: InboundUsable>(
         length: token.len(),
         value: token.as_ptr() as *mut c_void,
     };
-    let mut channel_binding_buffer = channel_bindings.map(as_channel_bindings);
+    let mut channel_binding_buffer = match policy {
+        ChannelBindingPolicy::Ignore => None,
+        ChannelBindingPolicy::Required | ChannelBindingPolicy::IfPresent => RawChannelBindings::new(&channel_bindings),
+    };
     let mut next_token = empty_token();
     let mut attributes = 0;
     let mut remaining_seconds = 0;
     let mut delegated_cred_handle = std::ptr::null_mut();
-    match unsafe {
+    let status = unsafe {
         gss_accept_sec_context(
             &raw mut minor,
             &raw mut ctx_ptr,
             cred.as_raw().as_ptr(),
             &raw mut token_buf,
-            channel_binding_buffer.as_mut().map_or(ptr::null_mut(), ptr::from_mut),
+            channel_binding_buffer
+                .as_mut()
+                .map_or(ptr::null_mut(), RawChannelBindings::as_mut_ptr),
             ptr::null_mut(),
             ptr::null_mut(),
             &raw mut next_token,
@@ -179,13 +400,19 @@ fn step<CU: InboundUsable>(
             &raw mut remaining_seconds,
             &raw mut delegated_cred_handle,
         )
-    } {
+    };
+    // The bindings may only move into a pending context once GSSAPI no longer points into them
+    drop(channel_binding_buffer);
+    match status {
         GSS_S_COMPLETE => {
             let Some(nn_ctx_ptr) = NonNull::new(ctx_ptr) else {
                 panic!("GSS returned COMPLETE but didn't offer a new context")
             };
             let last_token = unsafe { Token::from_raw(next_token) };
             let context = ctx.unwrap_or_else(|| unsafe { ContextHandle::from_raw(nn_ctx_ptr) });
+            if policy == ChannelBindingPolicy::Required && attributes & GSS_C_CHANNEL_BOUND_FLAG == 0 {
+                return Err(Error::gss(_GSS_S_BAD_BINDINGS).expect("is not GSS_S_COMPLETE"));
+            }
             let delegated_creds = NonNull::new(delegated_cred_handle).map(|ch| unsafe {
                 Credentials::from_raw_components(ch, cred.mechanism(), Duration::from_secs(remaining_seconds.into()))
             });
@@ -210,9 +437,18 @@ fn step<CU: InboundUsable>(
                 context,
                 cred,
                 next_token,
+                channel_bindings,
+                policy,
             }))
         }
-        code => todo!("Error code: {code:?}"),
+        code => {
+            if ctx.is_none() && !ctx_ptr.is_null() {
+                let mut s = 0;
+                unsafe { gss_delete_sec_context(&raw mut s, &raw mut ctx_ptr, ptr::null_mut()) };
+            }
+            // The routine error tells apart rejected bindings, tokens and credentials
+            Err(Error::gss(code).expect("is not GSS_S_COMPLETE"))
+        }
     }
 }
 
@@ -1,42 +1,76 @@
 use std::sync::Arc;
 
-use kenobi_core::{channel_bindings::Channel, cred::usage::InboundUsable};
+use kenobi_core::{
+    channel_bindings::{Address, Channel, ChannelBindingPolicy, ChannelBindingType, ChannelBindings},
+    cred::usage::InboundUsable,
+};
 
 use crate::{
     Error,
     cred::Credentials,
+    mech::Mechanism,
     server::{StepOut, step},
 };
 
 #[derive(Debug)]
 pub struct ServerBuilder<CU> {
     cred: Arc<Credentials<CU>>,
-    channel_bindings: Option<Box<[u8]>>,
+    channel_bindings: ChannelBindings,
+    policy: ChannelBindingPolicy,
 }
 impl<CU: InboundUsable> ServerBuilder<CU> {
     #[must_use]
     pub fn new(cred: Arc<Credentials<CU>>) -> ServerBuilder<CU> {
         ServerBuilder {
             cred,
-            channel_bindings: None,
+            channel_bindings: ChannelBindings::default(),
+            policy: ChannelBindingPolicy::default(),
         }
     }
 }
 impl<CU> ServerBuilder<CU> {
+    /// The mechanism of the credentials, which the context is established with
+    #[must_use]
+    pub fn mechanism(&self) -> Mechanism {
+        self.cred.mechanism()
+    }
     /// # Errors
     /// Forwards the failure of the underlying `Channel`
     pub fn bind_to_channel(self, channel: &impl Channel) -> Result<Self, impl std::error::Error> {
         match channel.channel_bindings() {
             Err(e) => Err(e),
-            Ok(bindings) => Ok(Self {
-                channel_bindings: bindings.map(Vec::into_boxed_slice),
-                ..self
-            }),
+            Ok(application_data) => Ok(self.with_application_data(application_data)),
         }
     }
+    /// Like `bind_to_channel`, using channel bindings of the given type
+    ///
+    /// # Errors
+    /// Forwards the failure of the underlying `Channel`
+    pub fn bind_to_channel_as<C: Channel>(self, channel: &C, kind: ChannelBindingType) -> Result<Self, C::Error> {
+        Ok(self.with_application_data(channel.channel_bindings_of(kind)?))
+    }
+    /// Binds the context to the network addresses of the initiator and the acceptor, which the peer has to match
+    #[must_use]
+    pub fn bind_to_addresses(mut self, initiator: Option<Address>, acceptor: Option<Address>) -> Self {
+        self.channel_bindings.initiator_address = initiator;
+        self.channel_bindings.acceptor_address = acceptor;
+        self
+    }
+    /// Decides whether clients have to bind to the channel
+    ///
+    /// `Required` relies on `GSS_C_CHANNEL_BOUND_FLAG` (MIT krb5 1.19 and newer),
+    /// so older libraries reject every client
+    #[must_use]
+    pub fn with_channel_binding_policy(self, policy: ChannelBindingPolicy) -> Self {
+        Self { policy, ..self }
+    }
+    fn with_application_data(mut self, application_data: Option<Vec<u8>>) -> Self {
+        self.channel_bindings.application_data = application_data;
+        self
+    }
 }
 impl<CU: InboundUsable> ServerBuilder<CU> {
     pub fn initialize(self, token: &[u8]) -> Result<StepOut<CU>, Error> {
-        step(None, self.cred, token, self.channel_bindings.as_deref())
+        step(None, self.cred, token, self.channel_bindings, self.policy)
     }
 }
@@ -4,17 +4,24 @@ use std::{
     ops::Deref,
 };
 
-use libgssapi_sys::{GSS_C_QOP_DEFAULT, gss_buffer_desc, gss_release_buffer, gss_unwrap, gss_wrap};
+use libgssapi_sys::{
+    GSS_C_QOP_DEFAULT, gss_buffer_desc, gss_get_mic, gss_release_buffer, gss_unwrap, gss_verify_mic, gss_wrap,
+    gss_wrap_size_limit,
+};
+
+use kenobi_core::qop::Qop;
 
 use crate::{Error, context::ContextHandle};
 
-pub(crate) fn sign(ctx: &mut ContextHandle, message: &[u8]) -> Result<Signed, Error> {
-    wrap(ctx, false, message).map(Signed)
+pub mod iov;
+
+pub(crate) fn sign(ctx: &mut ContextHandle, qop: Qop, message: &[u8]) -> Result<Signed, Error> {
+    wrap(ctx, false, qop, message).map(Signed)
 }
-pub(crate) fn encrypt(ctx: &mut ContextHandle, message: &[u8]) -> Result<Encrypted, Error> {
-    wrap(ctx, true, message).map(Encrypted)
+pub(crate) fn encrypt(ctx: &mut ContextHandle, qop: Qop, message: &[u8]) -> Result<Encrypted, Error> {
+    wrap(ctx, true, qop, message).map(Encrypted)
 }
-fn wrap(ctx: &mut ContextHandle, encrypt: bool, message: &[u8]) -> Result<SecurityBuffer, Error> {
+fn wrap(ctx: &mut ContextHandle, encrypt: bool, qop: Qop, message: &[u8]) -> Result<SecurityBuffer, Error> {
     let mut minor = 0;
     let mut input_buffer_desc = gss_buffer_desc {
         length: message.len(),
@@ -31,7 +38,7 @@ fn wrap(ctx: &mut ContextHandle, encrypt: bool, message: &[u8]) -> Result<Securi
             &raw mut minor,
             ctx.as_ptr().cast_mut(),
             i32::from(encrypt),
-            GSS_C_QOP_DEFAULT,
+            qop.value(),
             &raw mut input_buffer_desc,
             &raw mut conf_state,
             &raw mut output_buffer,
@@ -46,6 +53,38 @@ fn wrap(ctx: &mut ContextHandle, encrypt: bool, message: &[u8]) -> Result<Securi
     Ok(SecurityBuffer(output_buffer))
 }
 
+/// Largest message whose wrap token is at most `max_token_len` bytes long
+pub(crate) fn max_plaintext_len(
+    ctx: &mut ContextHandle,
+    encrypt: bool,
+    qop: Qop,
+    max_token_len: usize,
+) -> Result<usize, Error> {
+    let mut minor = 0;
+    let mut max_input = 0;
+    if let Some(major) = Error::gss(unsafe {
+        gss_wrap_size_limit(
+            &raw mut minor,
+            ctx.as_ptr().cast_mut(),
+            i32::from(encrypt),
+            qop.value(),
+            u32::try_from(max_token_len).unwrap_or(u32::MAX),
+            &raw mut max_input,
+        )
+    }) {
+        return Err(major);
+    }
+    if let Some(err) = Error::mechanism(minor) {
+        return Err(err);
+    }
+    Ok(max_input as usize)
+}
+/// Number of bytes wrapping adds to a message of `data_len` bytes
+pub(crate) fn wrap_overhead(ctx: &mut ContextHandle, encrypt: bool, qop: Qop, data_len: usize) -> Result<usize, Error> {
+    let layout = iov::layout(ctx, encrypt, qop, data_len)?;
+    Ok(layout.token_len(data_len) - data_len)
+}
+
 pub(crate) fn unwrap_raw(ctx: &mut ContextHandle, message: &[u8]) -> Result<Plaintext, Error> {
     let mut minor = 0;
     let mut input_buffer_desc = gss_buffer_desc {
@@ -57,6 +96,7 @@ pub(crate) fn unwrap_raw(ctx: &mut ContextHandle, message: &[u8]) -> Result<Plai
         value: std::ptr::null_mut(),
     };
     let mut conf_state = 0;
+    let mut qop_state = GSS_C_QOP_DEFAULT;
     if let Some(major) = Error::gss(unsafe {
         gss_unwrap(
             &raw mut minor,
@@ -64,7 +104,7 @@ pub(crate) fn unwrap_raw(ctx: &mut ContextHandle, message: &[u8]) -> Result<Plai
             &raw mut input_buffer_desc,
             &raw mut output_buffer,
             &raw mut conf_state,
-            std::ptr::null_mut(),
+            &raw mut qop_state,
         )
     }) {
         return Err(major);
@@ -73,17 +113,84 @@ pub(crate) fn unwrap_raw(ctx: &mut ContextHandle, message: &[u8]) -> Result<Plai
         return Err(minor);
     }
 
-    Ok(Plaintext::new(SecurityBuffer(output_buffer), conf_state != 0))
+    Ok(Plaintext::new(
+        SecurityBuffer(output_buffer),
+        conf_state != 0,
+        Qop::new(qop_state),
+    ))
+}
+
+pub(crate) fn get_mic(ctx: &mut ContextHandle, qop: Qop, message: &[u8]) -> Result<Mic, Error> {
+    let mut minor = 0;
+    let mut message_buffer = gss_buffer_desc {
+        length: message.len(),
+        value: message.as_ptr() as *mut c_void,
+    };
+    let mut token_buffer = gss_buffer_desc {
+        length: 0,
+        value: std::ptr::null_mut(),
+    };
+    if let Some(major) = Error::gss(unsafe {
+        gss_get_mic(
+            &raw mut minor,
+            ctx.as_ptr().cast_mut(),
+            qop.value(),
+            &raw mut message_buffer,
+            &raw mut token_buffer,
+        )
+    }) {
+        return Err(major);
+    }
+    if let Some(err) = Error::mechanism(minor) {
+        return Err(err);
+    }
+    Ok(Mic(SecurityBuffer(token_buffer)))
+}
+
+/// Returns the QOP the signature was created with
+///
+/// Use [`GssErrorCode::kind_verify`](crate::error::GssErrorCode::kind_verify) to tell apart the failures
+pub(crate) fn verify_mic(ctx: &mut ContextHandle, message: &[u8], mic: &[u8]) -> Result<Qop, Error> {
+    let mut minor = 0;
+    let mut message_buffer = gss_buffer_desc {
+        length: message.len(),
+        value: message.as_ptr() as *mut c_void,
+    };
+    let mut token_buffer = gss_buffer_desc {
+        length: mic.len(),
+        value: mic.as_ptr() as *mut c_void,
+    };
+    let mut qop_state = GSS_C_QOP_DEFAULT;
+    if let Some(major) = Error::gss(unsafe {
+        gss_verify_mic(
+            &raw mut minor,
+            ctx.as_ptr().cast_mut(),
+            &raw mut message_buffer,
+            &raw mut token_buffer,
+            &raw mut qop_state,
+        )
+    }) {
+        return Err(major);
+    }
+    if let Some(err) = Error::mechanism(minor) {
+        return Err(err);
+    }
+    Ok(Qop::new(qop_state))
 }
 
 #[derive(Debug)]
 pub struct Plaintext {
     buffer: SecurityBuffer,
     was_encrypted: bool,
+    qop: Qop,
 }
 impl Plaintext {
-    fn new(buffer: SecurityBuffer, was_encrypted: bool) -> Self {
-        Self { buffer, was_encrypted }
+    fn new(buffer: SecurityBuffer, was_encrypted: bool, qop: Qop) -> Self {
+        Self {
+            buffer,
+            was_encrypted,
+            qop,
+        }
     }
 }
 impl Deref for Plaintext {
@@ -101,6 +208,11 @@ impl Plaintext {
     pub fn was_encrypted(&self) -> bool {
         self.was_encrypted
     }
+    /// The QOP the message was wrapped with
+    #[must_use]
+    pub fn qop(&self) -> Qop {
+        self.qop
+    }
 }
 
 #[derive(Debug)]
@@ -136,6 +248,27 @@ impl AsRef<[u8]> for Signed {
     }
 }
 
+/// A detached signature over a message, which is not contained in the token
+#[derive(Debug)]
+pub struct Mic(SecurityBuffer);
+impl Mic {
+    #[must_use]
+    pub fn as_slice(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+impl Deref for Mic {
+    type Target = [u8];
+    fn deref(&self) -> &Self::Target {
+        self.as_slice()
+    }
+}
+impl AsRef<[u8]> for Mic {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
 struct SecurityBuffer(gss_buffer_desc);
 unsafe impl Send for SecurityBuffer {}
 unsafe impl Sync for SecurityBuffer {}
@@ -0,0 +1,261 @@
+//! In-place wrapping using `gss_wrap_iov`, avoiding the allocations and copies of `gss_wrap` and `gss_unwrap`
+//!
+//! Laid out contiguously as header, data, padding and trailer, the regions form a regular wrap token.
+//! `SignOnly` regions are integrity protected, but neither encrypted nor part of the token.
+
+use std::{ffi::c_void, ops::Deref, ptr};
+
+use kenobi_core::qop::Qop;
+use libgssapi_sys::{
+    _GSS_S_FAILURE, GSS_IOV_BUFFER_FLAG_ALLOCATED, GSS_IOV_BUFFER_TYPE_DATA, GSS_IOV_BUFFER_TYPE_HEADER,
+    GSS_IOV_BUFFER_TYPE_PADDING, GSS_IOV_BUFFER_TYPE_SIGN_ONLY, GSS_IOV_BUFFER_TYPE_STREAM,
+    GSS_IOV_BUFFER_TYPE_TRAILER, gss_buffer_desc, gss_iov_buffer_desc, gss_release_iov_buffer, gss_unwrap_iov,
+    gss_wrap_iov, gss_wrap_iov_length,
+};
+
+use crate::{Error, context::ContextHandle};
+
+/// A caller-owned region of a message
+#[derive(Debug)]
+pub enum IovBuffer<'a> {
+    Header(&'a mut [u8]),
+    Data(&'a mut [u8]),
+    SignOnly(&'a [u8]),
+    Padding(&'a mut [u8]),
+    Trailer(&'a mut [u8]),
+}
+impl IovBuffer<'_> {
+    fn as_raw(&mut self) -> gss_iov_buffer_desc {
+        let (type_, buffer) = match self {
+            Self::Header(b) => (GSS_IOV_BUFFER_TYPE_HEADER, ptr::from_mut(*b)),
+            Self::Data(b) => (GSS_IOV_BUFFER_TYPE_DATA, ptr::from_mut(*b)),
+            // GSSAPI does not write to SIGN_ONLY buffers
+            Self::SignOnly(b) => (GSS_IOV_BUFFER_TYPE_SIGN_ONLY, ptr::from_ref(*b).cast_mut()),
+            Self::Padding(b) => (GSS_IOV_BUFFER_TYPE_PADDING, ptr::from_mut(*b)),
+            Self::Trailer(b) => (GSS_IOV_BUFFER_TYPE_TRAILER, ptr::from_mut(*b)),
+        };
+        raw_buffer(type_, buffer.cast(), buffer.len())
+    }
+}
+
+fn raw_buffer(type_: u32, value: *mut c_void, length: usize) -> gss_iov_buffer_desc {
+    gss_iov_buffer_desc {
+        type_,
+        buffer: gss_buffer_desc { length, value },
+    }
+}
+
+/// Lengths of the regions surrounding the data of a wrap token
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IovLayout {
+    pub header: usize,
+    pub padding: usize,
+    pub trailer: usize,
+}
+impl IovLayout {
+    /// Length of the whole token for `data_len` bytes of data
+    #[must_use]
+    pub fn token_len(&self, data_len: usize) -> usize {
+        self.header + data_len + self.padding + self.trailer
+    }
+    fn from_raw(iov: &[gss_iov_buffer_desc]) -> Self {
+        let mut layout = Self::default();
+        for buffer in iov {
+            match buffer.type_ & !GSS_IOV_BUFFER_FLAG_ALLOCATED {
+                GSS_IOV_BUFFER_TYPE_HEADER => layout.header += buffer.buffer.length,
+                GSS_IOV_BUFFER_TYPE_PADDING => layout.padding += buffer.buffer.length,
+                GSS_IOV_BUFFER_TYPE_TRAILER => layout.trailer += buffer.buffer.length,
+                _ => {}
+            }
+        }
+        layout
+    }
+}
+
+/// Plaintext that has been unwrapped inside the buffer of its token
+#[derive(Debug)]
+pub struct InPlacePlaintext<'a> {
+    data: &'a mut [u8],
+    was_encrypted: bool,
+}
+impl<'a> InPlacePlaintext<'a> {
+    #[must_use]
+    pub fn was_encrypted(&self) -> bool {
+        self.was_encrypted
+    }
+    #[must_use]
+    pub fn into_slice(self) -> &'a mut [u8] {
+        self.data
+    }
+}
+impl Deref for InPlacePlaintext<'_> {
+    type Target = [u8];
+    fn deref(&self) -> &Self::Target {
+        self.data
+    }
+}
+
+fn check(major: u32, minor: u32) -> Result<(), Error> {
+    if let Some(err) = Error::gss(major) {
+        return Err(err);
+    }
+    if let Some(err) = Error::mechanism(minor) {
+        return Err(err);
+    }
+    Ok(())
+}
+
+pub(crate) fn layout(ctx: &mut ContextHandle, encrypt: bool, qop: Qop, data_len: usize) -> Result<IovLayout, Error> {
+    let mut minor = 0;
+    let mut iov = [
+        raw_buffer(GSS_IOV_BUFFER_TYPE_HEADER, ptr::null_mut(), 0),
+        raw_buffer(GSS_IOV_BUFFER_TYPE_DATA, ptr::null_mut(), data_len),
+        raw_buffer(GSS_IOV_BUFFER_TYPE_PADDING, ptr::null_mut(), 0),
+        raw_buffer(GSS_IOV_BUFFER_TYPE_TRAILER, ptr::null_mut(), 0),
+    ];
+    let major = unsafe {
+        gss_wrap_iov_length(
+            &raw mut minor,
+            ctx.as_ptr().cast_mut(),
+            i32::from(encrypt),
+            qop.value(),
+            ptr::null_mut(),
+            iov.as_mut_ptr(),
+            iov.len() as i32,
+        )
+    };
+    check(major, minor)?;
+    Ok(IovLayout::from_raw(&iov))
+}
+
+pub(crate) fn wrap(
+    ctx: &mut ContextHandle,
+    encrypt: bool,
+    qop: Qop,
+    buffers: &mut [IovBuffer<'_>],
+) -> Result<IovLayout, Error> {
+    let mut minor = 0;
+    let mut conf_state = 0;
+    let mut iov: Vec<_> = buffers.iter_mut().map(IovBuffer::as_raw).collect();
+    let major = unsafe {
+        gss_wrap_iov(
+            &raw mut minor,
+            ctx.as_ptr().cast_mut(),
+            i32::from(encrypt),
+            qop.value(),
+            &raw mut conf_state,
+            iov.as_mut_ptr(),
+            iov.len() as i32,
+        )
+    };
+    check(major, minor)?;
+    if encrypt && conf_state == 0 {
+        // The mechanism only signed the data, which must not pass as encrypted
+        return Err(Error::gss(_GSS_S_FAILURE).unwrap());
+    }
+    Ok(IovLayout::from_raw(&iov))
+}
+
+/// Wraps `message` into a token, reusing its allocation
+///
+/// The contents of `message` are unspecified if this fails
+pub(crate) fn wrap_vec(
+    ctx: &mut ContextHandle,
+    encrypt: bool,
+    qop: Qop,
+    message: &mut Vec<u8>,
+    associated: &[&[u8]],
+) -> Result<(), Error> {
+    let data_len = message.len();
+    let expected = layout(ctx, encrypt, qop, data_len)?;
+    message.resize(expected.token_len(data_len), 0);
+    message.copy_within(..data_len, expected.header);
+
+    let (header, rest) = message.split_at_mut(expected.header);
+    let (data, rest) = rest.split_at_mut(data_len);
+    let (padding, trailer) = rest.split_at_mut(expected.padding);
+    let mut buffers = vec![IovBuffer::Header(header)];
+    buffers.extend(associated.iter().map(|a| IovBuffer::SignOnly(a)));
+    buffers.extend([
+        IovBuffer::Data(data),
+        IovBuffer::Padding(padding),
+        IovBuffer::Trailer(trailer),
+    ]);
+    let actual = wrap(ctx, encrypt, qop, &mut buffers)?;
+    if actual.padding < expected.padding {
+        // Close the gap between padding and trailer
+        let trailer_start = expected.header + data_len + expected.padding;
+        message.copy_within(trailer_start.., expected.header + data_len + actual.padding);
+        message.truncate(actual.token_len(data_len));
+    }
+    Ok(())
+}
+
+/// Returns whether the message was encrypted
+pub(crate) fn unwrap(ctx: &mut ContextHandle, buffers: &mut [IovBuffer<'_>]) -> Result<bool, Error> {
+    let mut minor = 0;
+    let mut conf_state = 0;
+    let mut iov: Vec<_> = buffers.iter_mut().map(IovBuffer::as_raw).collect();
+    let major = unsafe {
+        gss_unwrap_iov(
+            &raw mut minor,
+            ctx.as_ptr().cast_mut(),
+            &raw mut conf_state,
+            ptr::null_mut(),
+            iov.as_mut_ptr(),
+            iov.len() as i32,
+        )
+    };
+    check(major, minor)?;
+    Ok(conf_state != 0)
+}
+
+pub(crate) fn unwrap_in_place<'a>(
+    ctx: &mut ContextHandle,
+    token: &'a mut [u8],
+    associated: &[&[u8]],
+) -> Result<InPlacePlaintext<'a>, Error> {
+    let mut minor = 0;
+    let mut conf_state = 0;
+    let mut iov = vec![raw_buffer(
+        GSS_IOV_BUFFER_TYPE_STREAM,
+        token.as_mut_ptr().cast(),
+        token.len(),
+    )];
+    iov.extend(
+        associated
+            .iter()
+            .map(|a| raw_buffer(GSS_IOV_BUFFER_TYPE_SIGN_ONLY, a.as_ptr() as *mut c_void, a.len())),
+    );
+    iov.push(raw_buffer(GSS_IOV_BUFFER_TYPE_DATA, ptr::null_mut(), 0));
+    let major = unsafe {
+        gss_unwrap_iov(
+            &raw mut minor,
+            ctx.as_ptr().cast_mut(),
+            &raw mut conf_state,
+            ptr::null_mut(),
+            iov.as_mut_ptr(),
+            iov.len() as i32,
+        )
+    };
+    check(major, minor)?;
+    let data = iov.last_mut().expect("data buffer was pushed last");
+    let length = data.buffer.length;
+    let range = if length == 0 {
+        0..0
+    } else if data.type_ & GSS_IOV_BUFFER_FLAG_ALLOCATED != 0 {
+        // The mechanism did not decrypt inside the stream, so the plaintext is moved there
+        let plaintext = unsafe { std::slice::from_raw_parts(data.buffer.value as *const u8, length) };
+        token[..length].copy_from_slice(plaintext);
+        let mut s = 0;
+        unsafe { gss_release_iov_buffer(&raw mut s, ptr::from_mut(data), 1) };
+        0..length
+    } else {
+        let offset = data.buffer.value as usize - token.as_ptr() as usize;
+        offset..offset + length
+    };
+    Ok(InPlacePlaintext {
+        data: &mut token[range],
+        was_encrypted: conf_state != 0,
+    })
+}
@@ -0,0 +1,115 @@
+//! Independent halves of a finished context, for sending and receiving from different threads
+//!
+//! GSSAPI does not guarantee that a context may be used from multiple threads at once,
+//! so both halves take turns on the shared context handle. Each call only holds it for its own duration.
+
+use std::{
+    marker::PhantomData,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use kenobi_core::{qop::Qop, typestate::Encryption};
+
+use crate::{
+    Error,
+    context::ContextHandle,
+    sign_encrypt::{self, Encrypted, Mic, Plaintext, Signed},
+};
+
+#[derive(Debug)]
+struct SharedContext(Mutex<ContextHandle>);
+impl SharedContext {
+    fn lock(&self) -> MutexGuard<'_, ContextHandle> {
+        // GSSAPI calls don't unwind, so a poisoned context is still consistent
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+pub(crate) fn split<E>(context: ContextHandle) -> (Sealer<E>, Opener<E>) {
+    let context = Arc::new(SharedContext(Mutex::new(context)));
+    (
+        Sealer {
+            context: context.clone(),
+            _enc: PhantomData,
+        },
+        Opener {
+            context,
+            _enc: PhantomData,
+        },
+    )
+}
+
+/// The sending half of a split context
+#[derive(Debug)]
+pub struct Sealer<E> {
+    context: Arc<SharedContext>,
+    _enc: PhantomData<E>,
+}
+impl<E> Sealer<E> {
+    /// # Errors
+    /// Forwards the failure from `gss_wrap`
+    pub fn sign(&mut self, message: &[u8]) -> Result<Signed, Error> {
+        sign_encrypt::sign(&mut self.context.lock(), Qop::DEFAULT, message)
+    }
+    /// # Errors
+    /// Forwards the failure from `gss_wrap`
+    pub fn sign_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<Signed, Error> {
+        sign_encrypt::sign(&mut self.context.lock(), qop, message)
+    }
+    /// # Errors
+    /// Forwards the failure from `gss_get_mic`
+    pub fn get_mic(&mut self, message: &[u8]) -> Result<Mic, Error> {
+        sign_encrypt::get_mic(&mut self.context.lock(), Qop::DEFAULT, message)
+    }
+    /// # Errors
+    /// Forwards the failure from `gss_get_mic`
+    pub fn get_mic_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<Mic, Error> {
+        sign_encrypt::get_mic(&mut self.context.lock(), qop, message)
+    }
+    /// # Errors
+    /// Forwards the failure from `gss_wrap_size_limit`
+    pub fn max_plaintext_len(&mut self, max_token_len: usize, encrypt: bool) -> Result<usize, Error> {
+        sign_encrypt::max_plaintext_len(&mut self.context.lock(), encrypt, Qop::DEFAULT, max_token_len)
+    }
+    /// # Errors
+    /// Forwards the failure from `gss_wrap_size_limit`
+    pub fn max_plaintext_len_with_qop(
+        &mut self,
+        max_token_len: usize,
+        encrypt: bool,
+        qop: Qop,
+    ) -> Result<usize, Error> {
+        sign_encrypt::max_plaintext_len(&mut self.context.lock(), encrypt, qop, max_token_len)
+    }
+}
+impl Sealer<Encryption> {
+    /// # Errors
+    /// Forwards the failure from `gss_wrap`
+    pub fn encrypt(&mut self, message: &[u8]) -> Result<Encrypted, Error> {
+        sign_encrypt::encrypt(&mut self.context.lock(), Qop::DEFAULT, message)
+    }
+    /// # Errors
+    /// Forwards the failure from `gss_wrap`
+    pub fn encrypt_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<Encrypted, Error> {
+        sign_encrypt::encrypt(&mut self.context.lock(), qop, message)
+    }
+}
+
+/// The receiving half of a split context
+#[derive(Debug)]
+pub struct Opener<E> {
+    context: Arc<SharedContext>,
+    _enc: PhantomData<E>,
+}
+impl<E> Opener<E> {
+    /// # Errors
+    /// Forwards the failure from `gss_unwrap`
+    pub fn unwrap(&mut self, message: &[u8]) -> Result<Plaintext, Error> {
+        sign_encrypt::unwrap_raw(&mut self.context.lock(), message)
+    }
+    /// # Errors
+    /// Forwards the failure from `gss_verify_mic`, see [`GssErrorCode::kind_verify`](crate::error::GssErrorCode::kind_verify)
+    pub fn verify_mic(&mut self, message: &[u8], mic: &[u8]) -> Result<Qop, Error> {
+        sign_encrypt::verify_mic(&mut self.context.lock(), message, mic)
+    }
+}
@@ -1,29 +1,42 @@
 use std::sync::Arc;
 
+use kenobi_testkdc::{OfflineInitiator, offline_environment};
 use kenobi_unix::{
     client::{ClientContext, StepOut},
     cred::Credentials,
     mech::Mechanism,
+    server::{ServerBuilder, StepOut as ServerStepOut},
 };
 
 #[test]
 fn main() {
-    let client_name = std::env::var("KERBEROS_TEST_USER_PRINCIPAL").ok();
-    let service_principal = std::env::var("KERBEROS_TEST_SERVICE_PRINCIPAL").ok();
-    let cred = match Credentials::outbound(client_name.as_deref(), None, Mechanism::KerberosV5) {
+    offline_environment();
+    let service_principal = OfflineInitiator::SERVICE;
+    let client_name = OfflineInitiator::CLIENT;
+
+    let cred = match Credentials::outbound(Some(client_name), None, Mechanism::KerberosV5) {
         Ok(cred) => cred,
         Err(err) => {
             eprintln!("Error: {err}");
             panic!()
         }
     };
-    let mut _ctx = match ClientContext::new(Arc::new(cred), service_principal.as_deref()) {
-        Ok(StepOut::Finished(_)) => return,
-        Ok(StepOut::Pending(pending)) => pending,
+    let server_cred = Credentials::inbound(None, None, Mechanism::KerberosV5).unwrap();
+    let (token, pending) = match ClientContext::new(Arc::new(cred), Some(service_principal)) {
+        Ok(StepOut::Finished(ctx)) => (ctx.last_token().unwrap().to_vec(), None),
+        Ok(StepOut::Pending(pending)) => (pending.next_token().to_vec(), Some(pending)),
         Err(err) => {
             eprintln!("Error initiating: {err}");
             panic!()
         }
     };
-    todo!();
+    let ServerStepOut::Finished(mut server) = ServerBuilder::new(Arc::new(server_cred)).initialize(&token).unwrap()
+    else {
+        panic!("Kerberos accepts the first token");
+    };
+    assert_eq!(server.client_name().unwrap().to_string(), client_name);
+    if let Some(pending) = pending {
+        let reply = server.last_token().expect("mutual authentication reply");
+        assert!(matches!(pending.step(reply), Ok(StepOut::Finished(_))));
+    }
 }
@@ -0,0 +1,142 @@
+//! In-place wrapping between a client and a server, using a cached ticket for `HTTP/localhost` instead of a KDC
+use std::sync::Arc;
+
+use kenobi_core::typestate::MaybeDelegation;
+use kenobi_testkdc::{OfflineInitiator, offline_environment};
+use kenobi_unix::{
+    client::{ClientBuilder, ClientContext, StepOut},
+    cred::{Credentials, Inbound, Outbound},
+    mech::Mechanism,
+    server::{ServerBuilder, ServerContext, StepOut as ServerStepOut},
+    sign_encrypt::iov::IovBuffer,
+    typestate::{Encryption, Signing},
+};
+
+type Client = ClientContext<Outbound, Signing, Encryption, MaybeDelegation>;
+type Server = ServerContext<Inbound, Signing, Encryption, MaybeDelegation>;
+
+const ASSOCIATED: &[u8] = b"sequence 42";
+
+fn establish() -> (Client, Server) {
+    offline_environment();
+    let cred = Credentials::outbound(None, None, Mechanism::KerberosV5).unwrap();
+    let builder = ClientBuilder::new(Arc::new(cred), Some(OfflineInitiator::SERVICE)).unwrap();
+    let Ok(StepOut::Finished(client)) = builder.request_encryption().initialize() else {
+        panic!("Kerberos without mutual authentication takes a single token");
+    };
+    let server_cred = Credentials::inbound(None, None, Mechanism::KerberosV5).unwrap();
+    let token = client.last_token().unwrap();
+    let Ok(ServerStepOut::Finished(server)) = ServerBuilder::new(Arc::new(server_cred)).initialize(token) else {
+        panic!("Kerberos accepts the first token");
+    };
+    let (Ok(Ok(client)), Ok(Ok(server))) = (
+        client.check_signing().map(ClientContext::check_encryption),
+        server.check_signing().map(ServerContext::check_encryption),
+    ) else {
+        panic!("encryption was requested");
+    };
+    (client, server)
+}
+
+#[test]
+fn encrypts_caller_regions_in_place() {
+    let (mut client, mut server) = establish();
+    let layout = client.encrypt_iov_layout(12).unwrap();
+    let (mut header, mut padding, mut trailer) =
+        (vec![0; layout.header], vec![0; layout.padding], vec![0; layout.trailer]);
+    let mut data = *b"hello there!";
+    let used = client
+        .encrypt_iov(&mut [
+            IovBuffer::Header(&mut header),
+            IovBuffer::SignOnly(ASSOCIATED),
+            IovBuffer::Data(&mut data),
+            IovBuffer::Padding(&mut padding),
+            IovBuffer::Trailer(&mut trailer),
+        ])
+        .unwrap();
+    assert_eq!(used, layout);
+    assert_ne!(&data, b"hello there!");
+
+    let mut tampered = data;
+    let rejected = server.unwrap_iov(&mut [
+        IovBuffer::Header(&mut header.clone()),
+        IovBuffer::SignOnly(b"sequence 43"),
+        IovBuffer::Data(&mut tampered),
+        IovBuffer::Padding(&mut padding.clone()),
+        IovBuffer::Trailer(&mut trailer.clone()),
+    ]);
+    assert!(rejected.is_err(), "the sign-only region is protected");
+
+    let was_encrypted = server
+        .unwrap_iov(&mut [
+            IovBuffer::Header(&mut header),
+            IovBuffer::SignOnly(ASSOCIATED),
+            IovBuffer::Data(&mut data),
+            IovBuffer::Padding(&mut padding),
+            IovBuffer::Trailer(&mut trailer),
+        ])
+        .unwrap();
+    assert!(was_encrypted);
+    assert_eq!(&data, b"hello there!");
+}
+
+#[test]
+fn signs_caller_regions_in_place() {
+    let (mut client, mut server) = establish();
+    let layout = server.sign_iov_layout(14).unwrap();
+    let (mut header, mut padding, mut trailer) =
+        (vec![0; layout.header], vec![0; layout.padding], vec![0; layout.trailer]);
+    let mut data = *b"general kenobi";
+    server
+        .sign_iov(&mut [
+            IovBuffer::Header(&mut header),
+            IovBuffer::Data(&mut data),
+            IovBuffer::Padding(&mut padding),
+            IovBuffer::Trailer(&mut trailer),
+        ])
+        .unwrap();
+    assert_eq!(&data, b"general kenobi", "signing leaves the data readable");
+
+    let token = [header, data.to_vec(), padding, trailer].concat();
+    let plaintext = client.unwrap(&token).unwrap();
+    assert_eq!(&*plaintext, b"general kenobi");
+}
+
+#[test]
+fn wraps_and_unwraps_whole_tokens_in_place() {
+    let (mut client, mut server) = establish();
+    for encrypt in [false, true] {
+        let mut token = b"you were the chosen one".to_vec();
+        if encrypt {
+            client.encrypt_in_place(&mut token, &[ASSOCIATED]).unwrap();
+        } else {
+            client.sign_in_place(&mut token, &[ASSOCIATED]).unwrap();
+        }
+        let layout = if encrypt {
+            client.encrypt_iov_layout(23).unwrap()
+        } else {
+            client.sign_iov_layout(23).unwrap()
+        };
+        assert_eq!(token.len(), layout.token_len(23));
+
+        assert!(server.unwrap_in_place(&mut token.clone(), &[]).is_err());
+        let plaintext = server.unwrap_in_place(&mut token, &[ASSOCIATED]).unwrap();
+        assert_eq!(plaintext.was_encrypted(), encrypt);
+        assert_eq!(&*plaintext, b"you were the chosen one");
+    }
+}
+
+/// Without sign-only regions, the token is a regular wrap token
+#[test]
+fn interoperates_with_regular_wrap_tokens() {
+    let (mut client, mut server) = establish();
+    let mut token = b"only regular".to_vec();
+    client.encrypt_in_place(&mut token, &[]).unwrap();
+    let plaintext = server.unwrap(&token).unwrap();
+    assert_eq!(&*plaintext, b"only regular");
+
+    let mut token = server.encrypt(b"the other way").unwrap().to_vec();
+    let plaintext = client.unwrap_in_place(&mut token, &[]).unwrap();
+    assert!(plaintext.was_encrypted());
+    assert_eq!(&*plaintext, b"the other way");
+}
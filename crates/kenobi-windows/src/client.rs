@@ -1,7 +1,9 @@
 use kenobi_core::cred::usage::OutboundUsable;
 use kenobi_core::flags::CapabilityFlags;
+use kenobi_core::qop::Qop;
 use kenobi_core::typestate::{
-    Encryption, MaybeDelegation, MaybeEncryption, MaybeSigning, NoDelegation, NoEncryption, NoSigning, Signing,
+    Delegation, Encryption, MaybeDelegation, MaybeEncryption, MaybeSigning, NoDelegation, NoEncryption, NoSigning,
+    Signing,
 };
 use std::sync::Arc;
 use std::{ffi::c_void, marker::PhantomData};
@@ -16,9 +18,10 @@ use windows::Win32::{
         SEC_I_CONTINUE_NEEDED,
     },
     Security::Authentication::Identity::{
-        ISC_REQ_FLAGS, ISC_REQ_MUTUAL_AUTH, ISC_RET_MUTUAL_AUTH, InitializeSecurityContextW, QueryContextAttributesW,
-        SEC_CHANNEL_BINDINGS, SECBUFFER_CHANNEL_BINDINGS, SECBUFFER_TOKEN, SECBUFFER_VERSION, SECPKG_ATTR_SESSION_KEY,
-        SECURITY_NATIVE_DREP, SecBuffer, SecBufferDesc, SecPkgContext_SessionKey,
+        ISC_REQ_FLAGS, ISC_REQ_MUTUAL_AUTH, ISC_REQ_REPLAY_DETECT, ISC_RET_DELEGATE, ISC_RET_MUTUAL_AUTH,
+        InitializeSecurityContextW, QueryContextAttributesW, SEC_CHANNEL_BINDINGS, SECBUFFER_CHANNEL_BINDINGS,
+        SECBUFFER_TOKEN, SECBUFFER_VERSION, SECPKG_ATTR_SESSION_KEY, SECURITY_NATIVE_DREP, SecBuffer, SecBufferDesc,
+        SecPkgContext_SessionKey,
     },
 };
 
@@ -31,7 +34,8 @@ use crate::{
     buffer::NonResizableVec,
     context::{ContextHandle, SessionKey},
     cred::Credentials,
-    sign_encrypt::{Altered, Encrypted, Plaintext, Signature},
+    sign_encrypt::{Altered, Encrypted, Mic, MicError, Plaintext, Signature},
+    split::{self, Opener, Sealer},
 };
 
 pub use builder::ClientBuilder;
@@ -69,15 +73,58 @@ impl<Usage, S, E, D> ClientContext<Usage, S, E, D> {
 }
 impl<Usage, E, D> ClientContext<Usage, Signing, E, D> {
     pub fn sign(&mut self, message: &[u8]) -> Result<Signature, WrapError> {
-        self.context.wrap_sign(message).map_err(WrapError)
+        self.context.wrap_sign(Qop::DEFAULT, message).map_err(WrapError)
+    }
+    pub fn sign_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<Signature, WrapError> {
+        self.context.wrap_sign(qop, message).map_err(WrapError)
     }
     pub fn unwrap(&mut self, message: &[u8]) -> Result<Plaintext, Altered> {
         self.context.unwrap(message)
     }
+    pub fn get_mic(&mut self, message: &[u8]) -> Result<Mic, WrapError> {
+        self.context.get_mic(Qop::DEFAULT, message).map_err(WrapError)
+    }
+    pub fn get_mic_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<Mic, WrapError> {
+        self.context.get_mic(qop, message).map_err(WrapError)
+    }
+    /// Returns the QOP the signature was created with
+    pub fn verify_mic(&mut self, message: &[u8], mic: &[u8]) -> Result<Qop, MicError> {
+        self.context.verify_mic(message, mic)
+    }
+    /// Splits the context into halves for sending and receiving, which may be used from different threads
+    pub fn split(self) -> (Sealer<E>, Opener<E>) {
+        split::split(self.context)
+    }
+    /// Largest message whose wrap token is at most `max_token_len` bytes long
+    ///
+    /// SSPI reports the same overhead for signed and encrypted messages
+    pub fn max_plaintext_len(&mut self, max_token_len: usize, _encrypt: bool) -> Result<usize, WrapError> {
+        self.context.max_plaintext_len(max_token_len).map_err(WrapError)
+    }
+    /// Upper bound on the number of bytes wrapping adds to a message of `data_len` bytes
+    pub fn wrap_overhead(&mut self, _data_len: usize, _encrypt: bool) -> Result<usize, WrapError> {
+        self.context.wrap_overhead().map_err(WrapError)
+    }
+    /// SSPI reports the same sizes for every quality of protection
+    pub fn max_plaintext_len_with_qop(
+        &mut self,
+        max_token_len: usize,
+        encrypt: bool,
+        _qop: Qop,
+    ) -> Result<usize, WrapError> {
+        self.max_plaintext_len(max_token_len, encrypt)
+    }
+    /// SSPI reports the same sizes for every quality of protection
+    pub fn wrap_overhead_with_qop(&mut self, data_len: usize, encrypt: bool, _qop: Qop) -> Result<usize, WrapError> {
+        self.wrap_overhead(data_len, encrypt)
+    }
 }
 impl<Usage, D> ClientContext<Usage, Signing, Encryption, D> {
     pub fn encrypt(&mut self, message: &[u8]) -> Result<Encrypted, WrapError> {
-        self.context.wrap_encrypt(message).map_err(WrapError)
+        self.context.wrap_encrypt(Qop::DEFAULT, message).map_err(WrapError)
+    }
+    pub fn encrypt_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<Encrypted, WrapError> {
+        self.context.wrap_encrypt(qop, message).map_err(WrapError)
     }
 }
 impl<Usage: OutboundUsable> ClientContext<Usage, NoSigning, NoEncryption, NoDelegation> {
@@ -109,6 +156,17 @@ impl<Usage, S, D> ClientContext<Usage, S, MaybeEncryption, D> {
         }
     }
 }
+type CheckDelegationResult<Usage, S, E> =
+    Result<ClientContext<Usage, S, E, Delegation>, ClientContext<Usage, S, E, NoDelegation>>;
+impl<Usage, S, E> ClientContext<Usage, S, E, MaybeDelegation> {
+    pub fn check_delegation(self) -> CheckDelegationResult<Usage, S, E> {
+        if self.attributes & ISC_RET_DELEGATE != 0 {
+            Ok(self.convert_policy())
+        } else {
+            Err(self.convert_policy())
+        }
+    }
+}
 impl<Usage, S1, E1, D1> ClientContext<Usage, S1, E1, D1> {
     fn convert_policy<S2, E2, D2>(self) -> ClientContext<Usage, S2, E2, D2> {
         let ClientContext {
@@ -287,6 +345,9 @@ fn convert_flags(flags: CapabilityFlags) -> ISC_REQ_FLAGS {
     if flags.contains_all(CapabilityFlags::MUTUAL_AUTH) {
         out_flags |= ISC_REQ_MUTUAL_AUTH;
     }
+    if flags.contains_all(CapabilityFlags::REPLAY_DETECT) {
+        out_flags |= ISC_REQ_REPLAY_DETECT;
+    }
     if flags.contains_all(CapabilityFlags::INTEGRITY) {
         out_flags |= ISC_REQ_INTEGRITY
     } else {
@@ -298,6 +359,10 @@ fn convert_flags(flags: CapabilityFlags) -> ISC_REQ_FLAGS {
     if flags.contains_all(CapabilityFlags::DELEGATE) {
         out_flags |= ISC_REQ_DELEGATE
     }
+    // Kerberos SSPI already refuses to forward the TGT to targets which are not OK-as-delegate
+    if flags.contains_all(CapabilityFlags::DELEGATE_IF_OK_AS_DELEGATE) {
+        out_flags |= ISC_REQ_DELEGATE
+    }
     out_flags
 }
 
@@ -9,7 +9,11 @@ use crate::{
     cred::Credentials,
 };
 use kenobi_core::flags::CapabilityFlags;
-use kenobi_core::{channel_bindings::Channel, cred::usage::OutboundUsable};
+use kenobi_core::{
+    channel_bindings::{Channel, ChannelBindingType},
+    cred::usage::OutboundUsable,
+    mech::Mechanism,
+};
 
 pub struct ClientBuilder<Usage> {
     cred: Arc<Credentials<Usage>>,
@@ -40,6 +44,10 @@ impl<Usage> ClientBuilder<Usage> {
             channel_bindings: None,
         }
     }
+    /// The mechanism of the credentials, which the context is established with
+    pub fn mechanism(&self) -> Mechanism {
+        self.cred.mechanism()
+    }
     pub fn with_flag(mut self, flag: CapabilityFlags) -> Self {
         self.flags.add_flag(flag);
         self
@@ -47,6 +55,9 @@ impl<Usage> ClientBuilder<Usage> {
     pub fn request_mutual_auth(self) -> Self {
         self.with_flag(CapabilityFlags::MUTUAL_AUTH)
     }
+    pub fn request_replay_detection(self) -> Self {
+        self.with_flag(CapabilityFlags::REPLAY_DETECT)
+    }
     pub fn request_encryption(self) -> Self {
         self.with_flag(CapabilityFlags::CONFIDENTIALITY)
     }
@@ -56,6 +67,10 @@ impl<Usage> ClientBuilder<Usage> {
     pub fn allow_delegation(self) -> Self {
         self.with_flag(CapabilityFlags::DELEGATE)
     }
+    /// SSPI only forwards the TGT to targets marked as OK-as-delegate anyway, so this requests `ISC_REQ_DELEGATE`
+    pub fn delegate_if_ok_as_delegate(self) -> Self {
+        self.with_flag(CapabilityFlags::DELEGATE_IF_OK_AS_DELEGATE)
+    }
     pub fn bind_to_channel<C: Channel>(self, channel: &C) -> Result<Self, C::Error> {
         match channel.channel_bindings() {
             Err(e) => Err(e),
@@ -65,6 +80,13 @@ impl<Usage> ClientBuilder<Usage> {
             }),
         }
     }
+    /// Like `bind_to_channel`, using channel bindings of the given type
+    pub fn bind_to_channel_as<C: Channel>(self, channel: &C, kind: ChannelBindingType) -> Result<Self, C::Error> {
+        Ok(Self {
+            channel_bindings: channel.channel_bindings_of(kind)?.map(Vec::into_boxed_slice),
+            ..self
+        })
+    }
 }
 impl<Usage: OutboundUsable> ClientBuilder<Usage> {
     pub fn initialize(self) -> Result<StepOut<Usage>, InitializeContextError> {
@@ -6,6 +6,7 @@ pub mod context;
 pub mod cred;
 pub mod server;
 pub mod sign_encrypt;
+pub mod split;
 
 const NEGOTIATE: PCWSTR = w!("Negotiate");
 const KERBEROS: PCWSTR = w!("Kerberos");
@@ -9,21 +9,22 @@ use windows::Win32::{
         Authentication::Identity::{
             ASC_REQ_CONFIDENTIALITY, ASC_REQ_DELEGATE, ASC_REQ_FLAGS, ASC_REQ_INTEGRITY, ASC_REQ_MUTUAL_AUTH,
             AcceptSecurityContext, QueryContextAttributesW, SEC_CHANNEL_BINDINGS, SECBUFFER_CHANNEL_BINDINGS,
-            SECBUFFER_TOKEN, SECBUFFER_VERSION, SECPKG_ATTR_NATIVE_NAMES, SECURITY_NATIVE_DREP, SecBuffer,
-            SecBufferDesc, SecPkgContext_NativeNamesW,
+            SECBUFFER_TOKEN, SECBUFFER_VERSION, SECPKG_ATTR_NATIVE_NAMES, SECPKG_ATTR_SESSION_KEY,
+            SECURITY_NATIVE_DREP, SecBuffer, SecBufferDesc, SecPkgContext_NativeNamesW, SecPkgContext_SessionKey,
         },
         Credentials::SecHandle,
     },
 };
 
-use kenobi_core::{cred::usage::InboundUsable, flags::CapabilityFlags};
+use kenobi_core::{cred::usage::InboundUsable, flags::CapabilityFlags, mech::Mechanism, qop::Qop};
 
 use crate::{
     buffer::{NativeNamesHandle, NonResizableVec},
-    context::ContextHandle,
+    context::{ContextHandle, SessionKey},
     cred::Credentials,
     server::typestate::{DelegationPolicy, EncryptionPolicy, SigningPolicy},
-    sign_encrypt::{Altered, Encrypted, Plaintext, Signature, WrapError},
+    sign_encrypt::{Altered, Encrypted, Mic, MicError, Plaintext, Signature, WrapError},
+    split::{self, Opener, Sealer},
 };
 
 mod builder;
@@ -67,23 +68,80 @@ impl<Usage, S, E, D> ServerContext<Usage, S, E, D> {
     pub fn last_token(&self) -> Option<&[u8]> {
         (!self.token_buffer.is_empty()).then_some(&self.token_buffer)
     }
+    pub fn mechanism(&self) -> Mechanism {
+        self.cred.mechanism()
+    }
     pub fn client_native_name(&self) -> Result<impl Display + Send + Sync, windows_result::Error> {
         let mut names: SecPkgContext_NativeNamesW = SecPkgContext_NativeNamesW::default();
         unsafe { QueryContextAttributesW(self.context.as_ptr(), SECPKG_ATTR_NATIVE_NAMES, (&raw mut names).cast())? }
         Ok(unsafe { NativeNamesHandle::from_raw(names) }.client())
     }
+    pub fn get_session_key(&self) -> windows_result::Result<SessionKey> {
+        let mut key = SecPkgContext_SessionKey::default();
+        unsafe {
+            QueryContextAttributesW(
+                self.context.as_ptr(),
+                SECPKG_ATTR_SESSION_KEY,
+                std::ptr::from_mut(&mut key) as *mut c_void,
+            )
+        }?;
+        unsafe { Ok(SessionKey::new(key)) }
+    }
 }
 impl<Usage, E, D> ServerContext<Usage, Signing, E, D> {
     pub fn sign(&mut self, message: &[u8]) -> Result<Signature, WrapError> {
-        self.context.wrap_sign(message).map_err(WrapError)
+        self.context.wrap_sign(Qop::DEFAULT, message).map_err(WrapError)
+    }
+    pub fn sign_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<Signature, WrapError> {
+        self.context.wrap_sign(qop, message).map_err(WrapError)
     }
     pub fn unwrap(&mut self, message: &[u8]) -> Result<Plaintext, Altered> {
         self.context.unwrap(message)
     }
+    pub fn get_mic(&mut self, message: &[u8]) -> Result<Mic, WrapError> {
+        self.context.get_mic(Qop::DEFAULT, message).map_err(WrapError)
+    }
+    pub fn get_mic_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<Mic, WrapError> {
+        self.context.get_mic(qop, message).map_err(WrapError)
+    }
+    /// Returns the QOP the signature was created with
+    pub fn verify_mic(&mut self, message: &[u8], mic: &[u8]) -> Result<Qop, MicError> {
+        self.context.verify_mic(message, mic)
+    }
+    /// Splits the context into halves for sending and receiving, which may be used from different threads
+    pub fn split(self) -> (Sealer<E>, Opener<E>) {
+        split::split(self.context)
+    }
+    /// Largest message whose wrap token is at most `max_token_len` bytes long
+    ///
+    /// SSPI reports the same overhead for signed and encrypted messages
+    pub fn max_plaintext_len(&mut self, max_token_len: usize, _encrypt: bool) -> Result<usize, WrapError> {
+        self.context.max_plaintext_len(max_token_len).map_err(WrapError)
+    }
+    /// Upper bound on the number of bytes wrapping adds to a message of `data_len` bytes
+    pub fn wrap_overhead(&mut self, _data_len: usize, _encrypt: bool) -> Result<usize, WrapError> {
+        self.context.wrap_overhead().map_err(WrapError)
+    }
+    /// SSPI reports the same sizes for every quality of protection
+    pub fn max_plaintext_len_with_qop(
+        &mut self,
+        max_token_len: usize,
+        encrypt: bool,
+        _qop: Qop,
+    ) -> Result<usize, WrapError> {
+        self.max_plaintext_len(max_token_len, encrypt)
+    }
+    /// SSPI reports the same sizes for every quality of protection
+    pub fn wrap_overhead_with_qop(&mut self, data_len: usize, encrypt: bool, _qop: Qop) -> Result<usize, WrapError> {
+        self.wrap_overhead(data_len, encrypt)
+    }
 }
 impl<Usage, S, D> ServerContext<Usage, S, Encryption, D> {
     pub fn encrypt(&mut self, message: &[u8]) -> Result<Encrypted, WrapError> {
-        self.context.wrap_encrypt(message).map_err(WrapError)
+        self.context.wrap_encrypt(Qop::DEFAULT, message).map_err(WrapError)
+    }
+    pub fn encrypt_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<Encrypted, WrapError> {
+        self.context.wrap_encrypt(qop, message).map_err(WrapError)
     }
 }
 impl<Usage, S, E> ServerContext<Usage, S, E, MaybeDelegation> {
@@ -5,7 +5,12 @@ use crate::{
     cred::Credentials,
     server::{StepOut, error::AcceptContextError},
 };
-use kenobi_core::{channel_bindings::Channel, cred::usage::InboundUsable, flags::CapabilityFlags};
+use kenobi_core::{
+    channel_bindings::{Channel, ChannelBindingType},
+    cred::usage::InboundUsable,
+    flags::CapabilityFlags,
+    mech::Mechanism,
+};
 
 #[derive(Debug)]
 pub struct ServerBuilder<Usage> {
@@ -21,6 +26,10 @@ impl<Usage> ServerBuilder<Usage> {
             flags: CapabilityFlags::default(),
         }
     }
+    /// The mechanism of the credentials, which the context is established with
+    pub fn mechanism(&self) -> Mechanism {
+        self.cred.mechanism()
+    }
     pub fn with_flag(mut self, flag: CapabilityFlags) -> Self {
         self.flags.add_flag(flag);
         self
@@ -46,6 +55,13 @@ impl<Usage> ServerBuilder<Usage> {
             }),
         }
     }
+    /// Like `bind_to_channel`, using channel bindings of the given type
+    pub fn bind_to_channel_as<C: Channel>(self, channel: &C, kind: ChannelBindingType) -> Result<Self, C::Error> {
+        Ok(Self {
+            channel_bindings: channel.channel_bindings_of(kind)?.map(Vec::into_boxed_slice),
+            ..self
+        })
+    }
 }
 impl<Usage: InboundUsable> ServerBuilder<Usage> {
     pub fn initialize(self, token: &[u8]) -> Result<StepOut<Usage>, AcceptContextError> {
@@ -1,11 +1,12 @@
 use std::{ffi::c_void, fmt::Display, ops::Deref};
 
+use kenobi_core::qop::Qop;
 use windows::Win32::{
-    Foundation::{SEC_E_INVALID_TOKEN, SEC_E_MESSAGE_ALTERED, SEC_E_OK},
+    Foundation::{SEC_E_INVALID_TOKEN, SEC_E_MESSAGE_ALTERED, SEC_E_OK, SEC_E_OUT_OF_SEQUENCE},
     Security::Authentication::Identity::{
-        DecryptMessage, EncryptMessage, QueryContextAttributesW, SECBUFFER_DATA, SECBUFFER_PADDING, SECBUFFER_STREAM,
-        SECBUFFER_TOKEN, SECBUFFER_VERSION, SECPKG_ATTR_SIZES, SECQOP_WRAP_NO_ENCRYPT, SecBuffer, SecBufferDesc,
-        SecPkgContext_Sizes,
+        DecryptMessage, EncryptMessage, MakeSignature, QueryContextAttributesW, SECBUFFER_DATA, SECBUFFER_PADDING,
+        SECBUFFER_READONLY, SECBUFFER_STREAM, SECBUFFER_TOKEN, SECBUFFER_VERSION, SECPKG_ATTR_SIZES,
+        SECQOP_WRAP_NO_ENCRYPT, SecBuffer, SecBufferDesc, SecPkgContext_Sizes, VerifySignature,
     },
 };
 use windows_result::HRESULT;
@@ -13,7 +14,7 @@ use windows_result::HRESULT;
 use crate::context::ContextHandle;
 
 impl ContextHandle {
-    fn wrap_raw(&mut self, encrypt: bool, message: &[u8]) -> windows_result::Result<Vec<u8>> {
+    fn wrap_raw(&mut self, encrypt: bool, qop: Qop, message: &[u8]) -> windows_result::Result<Vec<u8>> {
         let sizes = get_context_sizes(self).expect("Failed to get context info");
 
         let mut header = vec![0u8; sizes.cbSecurityTrailer as usize];
@@ -43,7 +44,11 @@ impl ContextHandle {
             cBuffers: buffers.len() as u32,
             pBuffers: buffers.as_mut_ptr(),
         };
-        let qop = if encrypt { 0 } else { SECQOP_WRAP_NO_ENCRYPT };
+        let qop = if encrypt {
+            qop.value()
+        } else {
+            qop.value() | SECQOP_WRAP_NO_ENCRYPT
+        };
         let res = unsafe { EncryptMessage(self.as_ptr(), qop, &sec_buffer, 0) };
         match res {
             HRESULT(0) => {
@@ -57,12 +62,12 @@ impl ContextHandle {
         }
     }
     /// ONLY USE WITH FINISHED CONTEXT
-    pub(crate) fn wrap_sign(&mut self, message: &[u8]) -> windows_result::Result<Signature> {
-        self.wrap_raw(false, message).map(Signature)
+    pub(crate) fn wrap_sign(&mut self, qop: Qop, message: &[u8]) -> windows_result::Result<Signature> {
+        self.wrap_raw(false, qop, message).map(Signature)
     }
     /// ONLY USED IN A FINISHED, ENCRYPTION-ALLOWED CONTEXT
-    pub(crate) fn wrap_encrypt(&mut self, message: &[u8]) -> windows_result::Result<Encrypted> {
-        self.wrap_raw(true, message).map(Encrypted)
+    pub(crate) fn wrap_encrypt(&mut self, qop: Qop, message: &[u8]) -> windows_result::Result<Encrypted> {
+        self.wrap_raw(true, qop, message).map(Encrypted)
     }
 
     pub(crate) fn unwrap(&mut self, message: &[u8]) -> Result<Plaintext, Altered> {
@@ -94,7 +99,8 @@ impl ContextHandle {
                 let buffer = input[header_length..header_length + data_length].to_vec();
                 Ok(Plaintext {
                     buffer,
-                    was_encrypted: pfqop != SECQOP_WRAP_NO_ENCRYPT,
+                    was_encrypted: pfqop & SECQOP_WRAP_NO_ENCRYPT == 0,
+                    qop: Qop::new(pfqop & !SECQOP_WRAP_NO_ENCRYPT),
                 })
             }
             SEC_E_MESSAGE_ALTERED | SEC_E_INVALID_TOKEN => Err(Altered),
@@ -103,6 +109,74 @@ impl ContextHandle {
     }
 }
 
+impl ContextHandle {
+    /// ONLY USE WITH FINISHED CONTEXT
+    pub(crate) fn get_mic(&mut self, qop: Qop, message: &[u8]) -> windows_result::Result<Mic> {
+        let sizes = get_context_sizes(self)?;
+        let mut token = vec![0u8; sizes.cbMaxSignature as usize];
+        let mut buffers = [
+            SecBuffer {
+                cbBuffer: message.len() as u32,
+                BufferType: SECBUFFER_DATA | SECBUFFER_READONLY,
+                pvBuffer: message.as_ptr() as *mut c_void,
+            },
+            SecBuffer {
+                cbBuffer: sizes.cbMaxSignature,
+                BufferType: SECBUFFER_TOKEN,
+                pvBuffer: token.as_mut_ptr() as *mut c_void,
+            },
+        ];
+        let buffer_desc = SecBufferDesc {
+            ulVersion: SECBUFFER_VERSION,
+            cBuffers: buffers.len() as u32,
+            pBuffers: buffers.as_mut_ptr(),
+        };
+        unsafe { MakeSignature(self.as_ptr(), qop.value(), &buffer_desc, 0) }?;
+        token.truncate(buffers[1].cbBuffer as usize);
+        Ok(Mic(token))
+    }
+    /// ONLY USE WITH FINISHED CONTEXT
+    ///
+    /// Returns the QOP the signature was created with
+    pub(crate) fn verify_mic(&mut self, message: &[u8], mic: &[u8]) -> Result<Qop, MicError> {
+        let mut buffers = [
+            SecBuffer {
+                cbBuffer: message.len() as u32,
+                BufferType: SECBUFFER_DATA | SECBUFFER_READONLY,
+                pvBuffer: message.as_ptr() as *mut c_void,
+            },
+            SecBuffer {
+                cbBuffer: mic.len() as u32,
+                BufferType: SECBUFFER_TOKEN | SECBUFFER_READONLY,
+                pvBuffer: mic.as_ptr() as *mut c_void,
+            },
+        ];
+        let buffer_desc = SecBufferDesc {
+            ulVersion: SECBUFFER_VERSION,
+            cBuffers: buffers.len() as u32,
+            pBuffers: buffers.as_mut_ptr(),
+        };
+        match unsafe { VerifySignature(self.as_ptr(), &buffer_desc, 0) } {
+            Ok(qop) => Ok(Qop::new(qop)),
+            Err(e) if e.code() == SEC_E_MESSAGE_ALTERED || e.code() == SEC_E_INVALID_TOKEN => Err(MicError::Altered),
+            Err(e) if e.code() == SEC_E_OUT_OF_SEQUENCE => Err(MicError::OutOfSequence),
+            Err(e) => Err(MicError::Other(WrapError(e))),
+        }
+    }
+}
+
+impl ContextHandle {
+    /// Upper bound on the bytes added by [`wrap_raw`](Self::wrap_raw), regardless of the message length
+    pub(crate) fn wrap_overhead(&self) -> windows_result::Result<usize> {
+        let sizes = get_context_sizes(self)?;
+        Ok(sizes.cbSecurityTrailer as usize + sizes.cbBlockSize as usize)
+    }
+    /// Largest message whose wrap token is at most `max_token_len` bytes long
+    pub(crate) fn max_plaintext_len(&self, max_token_len: usize) -> windows_result::Result<usize> {
+        Ok(max_token_len.saturating_sub(self.wrap_overhead()?))
+    }
+}
+
 fn get_context_sizes(ctx: &ContextHandle) -> windows_result::Result<SecPkgContext_Sizes> {
     let mut sizes = SecPkgContext_Sizes::default();
     unsafe {
@@ -131,11 +205,16 @@ impl Display for WrapError {
 pub struct Plaintext {
     buffer: Vec<u8>,
     was_encrypted: bool,
+    qop: Qop,
 }
 impl Plaintext {
     pub fn was_encrypted(&self) -> bool {
         self.was_encrypted
     }
+    /// The QOP the message was wrapped with, without the `SECQOP_WRAP_NO_ENCRYPT` flag
+    pub fn qop(&self) -> Qop {
+        self.qop
+    }
 }
 impl Deref for Plaintext {
     type Target = [u8];
@@ -170,6 +249,42 @@ impl Deref for Encrypted {
         &self.0
     }
 }
+/// A detached signature over a message, which is not contained in the token
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mic(Vec<u8>);
+impl Deref for Mic {
+    type Target = [u8];
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[derive(Debug)]
+pub enum MicError {
+    /// The message or its signature have been altered
+    Altered,
+    /// The signature is a replay or was received out of order
+    OutOfSequence,
+    Other(WrapError),
+}
+impl std::error::Error for MicError {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        match self {
+            Self::Other(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl Display for MicError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Altered => Altered.fmt(f),
+            Self::OutOfSequence => write!(f, "the signature was received out of sequence"),
+            Self::Other(e) => e.fmt(f),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Altered;
 impl std::error::Error for Altered {}
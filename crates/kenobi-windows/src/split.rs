@@ -0,0 +1,97 @@
+//! Independent halves of a finished context, for sending and receiving from different threads
+//!
+//! Both halves take turns on the shared context handle. Each call only holds it for its own duration.
+
+use std::{
+    marker::PhantomData,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use kenobi_core::{qop::Qop, typestate::Encryption};
+
+use crate::{
+    context::ContextHandle,
+    sign_encrypt::{Altered, Encrypted, Mic, MicError, Plaintext, Signature, WrapError},
+};
+
+#[derive(Debug)]
+struct SharedContext(Mutex<ContextHandle>);
+impl SharedContext {
+    fn lock(&self) -> MutexGuard<'_, ContextHandle> {
+        // SSPI calls don't unwind, so a poisoned context is still consistent
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+pub(crate) fn split<E>(context: ContextHandle) -> (Sealer<E>, Opener<E>) {
+    let context = Arc::new(SharedContext(Mutex::new(context)));
+    (
+        Sealer {
+            context: context.clone(),
+            _enc: PhantomData,
+        },
+        Opener {
+            context,
+            _enc: PhantomData,
+        },
+    )
+}
+
+/// The sending half of a split context
+#[derive(Debug)]
+pub struct Sealer<E> {
+    context: Arc<SharedContext>,
+    _enc: PhantomData<E>,
+}
+impl<E> Sealer<E> {
+    pub fn sign(&mut self, message: &[u8]) -> Result<Signature, WrapError> {
+        self.context.lock().wrap_sign(Qop::DEFAULT, message).map_err(WrapError)
+    }
+    pub fn sign_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<Signature, WrapError> {
+        self.context.lock().wrap_sign(qop, message).map_err(WrapError)
+    }
+    pub fn get_mic(&mut self, message: &[u8]) -> Result<Mic, WrapError> {
+        self.context.lock().get_mic(Qop::DEFAULT, message).map_err(WrapError)
+    }
+    pub fn get_mic_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<Mic, WrapError> {
+        self.context.lock().get_mic(qop, message).map_err(WrapError)
+    }
+    pub fn max_plaintext_len(&mut self, max_token_len: usize, _encrypt: bool) -> Result<usize, WrapError> {
+        self.context.lock().max_plaintext_len(max_token_len).map_err(WrapError)
+    }
+    /// SSPI reports the same sizes for every quality of protection
+    pub fn max_plaintext_len_with_qop(
+        &mut self,
+        max_token_len: usize,
+        encrypt: bool,
+        _qop: Qop,
+    ) -> Result<usize, WrapError> {
+        self.max_plaintext_len(max_token_len, encrypt)
+    }
+}
+impl Sealer<Encryption> {
+    pub fn encrypt(&mut self, message: &[u8]) -> Result<Encrypted, WrapError> {
+        self.context
+            .lock()
+            .wrap_encrypt(Qop::DEFAULT, message)
+            .map_err(WrapError)
+    }
+    pub fn encrypt_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<Encrypted, WrapError> {
+        self.context.lock().wrap_encrypt(qop, message).map_err(WrapError)
+    }
+}
+
+/// The receiving half of a split context
+#[derive(Debug)]
+pub struct Opener<E> {
+    context: Arc<SharedContext>,
+    _enc: PhantomData<E>,
+}
+impl<E> Opener<E> {
+    pub fn unwrap(&mut self, message: &[u8]) -> Result<Plaintext, Altered> {
+        self.context.lock().unwrap(message)
+    }
+    pub fn verify_mic(&mut self, message: &[u8], mic: &[u8]) -> Result<Qop, MicError> {
+        self.context.lock().verify_mic(message, mic)
+    }
+}
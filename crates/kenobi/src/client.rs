@@ -1,4 +1,9 @@
-use kenobi_core::{cred::usage::OutboundUsable, typestate::MaybeDelegation};
+use std::marker::PhantomData;
+
+use kenobi_core::{
+    cred::usage::OutboundUsable,
+    typestate::{Delegation, MaybeDelegation, Unbound},
+};
 
 #[cfg(unix)]
 use kenobi_unix::client::{
@@ -14,12 +19,17 @@ pub use error::InitializeError;
 use kenobi_core::typestate::{
     Encryption, MaybeEncryption, MaybeSigning, NoDelegation, NoEncryption, NoSigning, Signing,
 };
-pub use typestate::{EncryptionState, SigningState};
+pub use typestate::{DelegationState, EncryptionState, SigningState};
 
+#[cfg(unix)]
+use crate::key::{PrfError, PrfKey};
 use crate::{
-    client::typestate::DelegationState,
     cred::{Credentials, CredentialsUsage},
-    sign_encrypt::{Signature, UnwrapError, WrapError},
+    key::SessionKeyError,
+    sign_encrypt::{
+        ChunkError, Mic, Plaintext, Qop, Signature, UnwrapConfidentialError, UnwrapError, VerifyMicError, WrapError,
+    },
+    split::{Opener, Sealer, Split},
 };
 
 mod builder;
@@ -30,11 +40,12 @@ mod typestate;
 ///
 /// This context represents the client side of the authentication, and may have a last token to be delivered to the server.
 /// The final token may be used using `ClientContext::last_token`
-pub struct ClientContext<Usage, S: SigningState, E: EncryptionState, D: DelegationState> {
+pub struct ClientContext<Usage, S: SigningState, E: EncryptionState, D: DelegationState, B = Unbound> {
     #[cfg(windows)]
     inner: WinContext<Usage, S, E, D>,
     #[cfg(unix)]
     inner: UnixClientContext<Usage, S, E, D>,
+    _channel: PhantomData<B>,
 }
 impl<Usage: CredentialsUsage + OutboundUsable> ClientContext<Usage, NoSigning, NoEncryption, NoDelegation> {
     pub fn new_from_cred(
@@ -54,145 +65,319 @@ impl<Usage: CredentialsUsage + OutboundUsable> ClientContext<Usage, NoSigning, N
     }
 }
 #[cfg(windows)]
-impl<Usage, S: SigningState, E: EncryptionState, D: DelegationState> ClientContext<Usage, S, E, D> {
+impl<Usage, S: SigningState, E: EncryptionState, D: DelegationState, B> ClientContext<Usage, S, E, D, B> {
     #[must_use]
     pub fn last_token(&self) -> Option<&[u8]> {
         self.inner.last_token()
     }
-    #[must_use]
-    pub fn session_key(&self) -> impl std::ops::Deref<Target = [u8]> + use<Usage, S, E, D> {
-        self.inner.get_session_key().unwrap()
+    pub fn session_key(&self) -> Result<impl std::ops::Deref<Target = [u8]> + use<Usage, S, E, D, B>, SessionKeyError> {
+        self.inner.get_session_key().map_err(SessionKeyError::from_inner)
     }
 }
 
 #[cfg(unix)]
-impl<Usage, S: SigningState, E: EncryptionState, D: DelegationState> ClientContext<Usage, S, E, D> {
+impl<Usage, S: SigningState, E: EncryptionState, D: DelegationState, B> ClientContext<Usage, S, E, D, B> {
     #[must_use]
     pub fn last_token(&self) -> Option<&[u8]> {
         self.inner.last_token()
     }
-    #[must_use]
-    pub fn session_key(&self) -> impl std::ops::Deref<Target = [u8]> + use<Usage, S, E, D> {
-        self.inner.session_key().unwrap()
+    pub fn session_key(&self) -> Result<impl std::ops::Deref<Target = [u8]> + use<Usage, S, E, D, B>, SessionKeyError> {
+        self.inner.session_key().map_err(SessionKeyError::from_inner)
+    }
+    /// Derives `output_len` bytes from `input` and the keys of the context, identically on both sides (RFC 4401)
+    ///
+    /// Only available with GSSAPI, as SSPI has no equivalent
+    pub fn prf(&self, key: PrfKey, input: &[u8], output_len: usize) -> Result<Vec<u8>, PrfError> {
+        self.inner.prf(key, input, output_len).map_err(PrfError::from_inner)
     }
 }
 #[cfg(windows)]
-impl<Usage, E: EncryptionState, D: DelegationState> ClientContext<Usage, MaybeSigning, E, D> {
+impl<Usage, E: EncryptionState, D: DelegationState, B> ClientContext<Usage, MaybeSigning, E, D, B> {
     #[allow(clippy::type_complexity)]
-    pub fn check_signing(self) -> Result<ClientContext<Usage, Signing, E, D>, ClientContext<Usage, NoSigning, E, D>> {
+    pub fn check_signing(
+        self,
+    ) -> Result<ClientContext<Usage, Signing, E, D, B>, ClientContext<Usage, NoSigning, E, D, B>> {
         self.inner
             .check_signing()
-            .map(|inner| ClientContext { inner })
-            .map_err(|inner| ClientContext { inner })
+            .map(|inner| ClientContext {
+                inner,
+                _channel: PhantomData,
+            })
+            .map_err(|inner| ClientContext {
+                inner,
+                _channel: PhantomData,
+            })
     }
 }
 #[cfg(unix)]
-impl<Usage, E: EncryptionState, D: DelegationState> ClientContext<Usage, MaybeSigning, E, D> {
+impl<Usage, E: EncryptionState, D: DelegationState, B> ClientContext<Usage, MaybeSigning, E, D, B> {
     #[allow(clippy::type_complexity)]
-    pub fn check_signing(self) -> Result<ClientContext<Usage, Signing, E, D>, ClientContext<Usage, NoSigning, E, D>> {
+    pub fn check_signing(
+        self,
+    ) -> Result<ClientContext<Usage, Signing, E, D, B>, ClientContext<Usage, NoSigning, E, D, B>> {
         self.inner
             .check_signing()
-            .map(|inner| ClientContext { inner })
-            .map_err(|inner| ClientContext { inner })
+            .map(|inner| ClientContext {
+                inner,
+                _channel: PhantomData,
+            })
+            .map_err(|inner| ClientContext {
+                inner,
+                _channel: PhantomData,
+            })
     }
 }
 #[cfg(windows)]
-impl<Usage, S: SigningState, D: DelegationState> ClientContext<Usage, S, MaybeEncryption, D> {
+impl<Usage, S: SigningState, D: DelegationState, B> ClientContext<Usage, S, MaybeEncryption, D, B> {
     #[allow(clippy::type_complexity)]
     pub fn check_encryption(
         self,
-    ) -> Result<ClientContext<Usage, S, Encryption, D>, ClientContext<Usage, S, NoEncryption, D>> {
+    ) -> Result<ClientContext<Usage, S, Encryption, D, B>, ClientContext<Usage, S, NoEncryption, D, B>> {
         self.inner
             .check_encryption()
-            .map(|inner| ClientContext { inner })
-            .map_err(|inner| ClientContext { inner })
+            .map(|inner| ClientContext {
+                inner,
+                _channel: PhantomData,
+            })
+            .map_err(|inner| ClientContext {
+                inner,
+                _channel: PhantomData,
+            })
     }
 }
 #[cfg(unix)]
-impl<Usage, S: SigningState, D: DelegationState> ClientContext<Usage, S, MaybeEncryption, D> {
+impl<Usage, S: SigningState, D: DelegationState, B> ClientContext<Usage, S, MaybeEncryption, D, B> {
     #[allow(clippy::type_complexity)]
     pub fn check_encryption(
         self,
-    ) -> Result<ClientContext<Usage, S, Encryption, D>, ClientContext<Usage, S, NoEncryption, D>> {
+    ) -> Result<ClientContext<Usage, S, Encryption, D, B>, ClientContext<Usage, S, NoEncryption, D, B>> {
         self.inner
             .check_encryption()
-            .map(|inner| ClientContext { inner })
-            .map_err(|inner| ClientContext { inner })
+            .map(|inner| ClientContext {
+                inner,
+                _channel: PhantomData,
+            })
+            .map_err(|inner| ClientContext {
+                inner,
+                _channel: PhantomData,
+            })
+    }
+}
+impl<Usage, S: SigningState, E: EncryptionState, B> ClientContext<Usage, S, E, MaybeDelegation, B> {
+    #[allow(clippy::type_complexity)]
+    pub fn check_delegation(
+        self,
+    ) -> Result<ClientContext<Usage, S, E, Delegation, B>, ClientContext<Usage, S, E, NoDelegation, B>> {
+        self.inner
+            .check_delegation()
+            .map(|inner| ClientContext {
+                inner,
+                _channel: PhantomData,
+            })
+            .map_err(|inner| ClientContext {
+                inner,
+                _channel: PhantomData,
+            })
     }
 }
 
-impl<Usage, E: EncryptionState, D: DelegationState> ClientContext<Usage, Signing, E, D> {
+impl<Usage, E: EncryptionState, D: DelegationState, B> ClientContext<Usage, Signing, E, D, B> {
     pub fn sign(&mut self, message: &[u8]) -> Result<Signature, WrapError> {
         Ok(Signature::from_inner(
             self.inner.sign(message).map_err(WrapError::from_inner)?,
         ))
     }
-    pub fn unwrap(
+    /// Like `sign`, using a mechanism specific quality of protection
+    pub fn sign_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<Signature, WrapError> {
+        Ok(Signature::from_inner(
+            self.inner.sign_with_qop(message, qop).map_err(WrapError::from_inner)?,
+        ))
+    }
+    pub fn unwrap(&mut self, message: &[u8]) -> Result<Plaintext, UnwrapError> {
+        Ok(Plaintext::from_inner(
+            self.inner.unwrap(message).map_err(UnwrapError::from_inner)?,
+        ))
+    }
+    /// Creates a detached signature over `message`, to be sent alongside the message
+    pub fn get_mic(&mut self, message: &[u8]) -> Result<Mic, WrapError> {
+        Ok(Mic::from_inner(
+            self.inner.get_mic(message).map_err(WrapError::from_inner)?,
+        ))
+    }
+    /// Like `get_mic`, using a mechanism specific quality of protection
+    pub fn get_mic_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<Mic, WrapError> {
+        Ok(Mic::from_inner(
+            self.inner
+                .get_mic_with_qop(message, qop)
+                .map_err(WrapError::from_inner)?,
+        ))
+    }
+    /// Returns the QOP the signature was created with
+    pub fn verify_mic(&mut self, message: &[u8], mic: &[u8]) -> Result<Qop, VerifyMicError> {
+        self.inner.verify_mic(message, mic).map_err(VerifyMicError::from_inner)
+    }
+    /// Splits the context into halves for sending and receiving, which may be used from different threads
+    ///
+    /// The last token still has to be sent to the peer, it is handed back with the halves
+    pub fn split(self) -> Split<E, B> {
+        let last_token = self.last_token().map(<[u8]>::to_vec);
+        let (sealer, opener) = self.inner.split();
+        Split {
+            sealer: Sealer::from_inner(sealer),
+            opener: Opener::from_inner(opener),
+            last_token,
+        }
+    }
+    /// Largest message whose wrapped token is at most `max_token_len` bytes long
+    pub fn max_plaintext_len(&mut self, max_token_len: usize, encrypt: bool) -> Result<usize, WrapError> {
+        self.inner
+            .max_plaintext_len(max_token_len, encrypt)
+            .map_err(WrapError::from_inner)
+    }
+    /// Number of bytes wrapping adds to a message of `data_len` bytes
+    ///
+    /// On Windows, this is an upper bound independent of the message
+    pub fn wrap_overhead(&mut self, data_len: usize, encrypt: bool) -> Result<usize, WrapError> {
+        self.inner
+            .wrap_overhead(data_len, encrypt)
+            .map_err(WrapError::from_inner)
+    }
+    /// Like `max_plaintext_len`, for messages wrapped with the given quality of protection
+    pub fn max_plaintext_len_with_qop(
         &mut self,
-        message: &[u8],
-    ) -> Result<impl std::ops::Deref<Target = [u8]> + use<Usage, E, D>, UnwrapError> {
-        self.inner.unwrap(message).map_err(UnwrapError::from_inner)
+        max_token_len: usize,
+        encrypt: bool,
+        qop: Qop,
+    ) -> Result<usize, WrapError> {
+        self.inner
+            .max_plaintext_len_with_qop(max_token_len, encrypt, qop)
+            .map_err(WrapError::from_inner)
+    }
+    /// Like `wrap_overhead`, for messages wrapped with the given quality of protection
+    pub fn wrap_overhead_with_qop(&mut self, data_len: usize, encrypt: bool, qop: Qop) -> Result<usize, WrapError> {
+        self.inner
+            .wrap_overhead_with_qop(data_len, encrypt, qop)
+            .map_err(WrapError::from_inner)
+    }
+    /// Splits `payload` into signed tokens of at most `max_token_len` bytes each
+    pub fn sign_chunks(&mut self, payload: &[u8], max_token_len: usize) -> Result<Vec<Signature>, ChunkError> {
+        let chunk_len = ChunkError::check_len(self.max_plaintext_len(max_token_len, false)?, max_token_len)?;
+        payload.chunks(chunk_len).map(|chunk| Ok(self.sign(chunk)?)).collect()
     }
 }
-impl<Usage, D: DelegationState> ClientContext<Usage, Signing, Encryption, D> {
+impl<Usage, D: DelegationState, B> ClientContext<Usage, Signing, Encryption, D, B> {
     pub fn encrypt(
         &mut self,
         message: &[u8],
-    ) -> Result<impl std::ops::Deref<Target = [u8]> + use<Usage, D>, WrapError> {
+    ) -> Result<impl std::ops::Deref<Target = [u8]> + use<Usage, D, B>, WrapError> {
         self.inner.encrypt(message).map_err(WrapError::from_inner)
     }
+    /// Like `encrypt`, using a mechanism specific quality of protection
+    pub fn encrypt_with_qop(
+        &mut self,
+        message: &[u8],
+        qop: Qop,
+    ) -> Result<impl std::ops::Deref<Target = [u8]> + use<Usage, D, B>, WrapError> {
+        self.inner.encrypt_with_qop(message, qop).map_err(WrapError::from_inner)
+    }
+    /// Like `unwrap`, but rejects messages that were only signed instead of encrypted
+    pub fn unwrap_confidential(&mut self, message: &[u8]) -> Result<Plaintext, UnwrapConfidentialError> {
+        let plaintext = self.unwrap(message)?;
+        if !plaintext.was_encrypted() {
+            return Err(UnwrapConfidentialError::NotEncrypted);
+        }
+        Ok(plaintext)
+    }
+    /// Splits `payload` into encrypted tokens of at most `max_token_len` bytes each
+    pub fn encrypt_chunks(
+        &mut self,
+        payload: &[u8],
+        max_token_len: usize,
+    ) -> Result<Vec<impl std::ops::Deref<Target = [u8]> + use<Usage, D, B>>, ChunkError> {
+        let chunk_len = ChunkError::check_len(self.max_plaintext_len(max_token_len, true)?, max_token_len)?;
+        payload
+            .chunks(chunk_len)
+            .map(|chunk| Ok(self.encrypt(chunk)?))
+            .collect()
+    }
 }
 
-pub struct PendingClientContext<Usage> {
+pub struct PendingClientContext<Usage, B = Unbound> {
     #[cfg(windows)]
     inner: WinPendingClientContext<Usage>,
     #[cfg(unix)]
     inner: UnixPendingClientContext<Usage>,
+    _channel: PhantomData<B>,
 }
 
-impl<Usage> PendingClientContext<Usage> {
+impl<Usage, B> PendingClientContext<Usage, B> {
     #[must_use]
     pub fn next_token(&self) -> &[u8] {
         self.inner.next_token()
     }
 }
 #[cfg(windows)]
-impl<Usage: OutboundUsable> PendingClientContext<Usage> {
-    pub fn step(self, token: &[u8]) -> Result<StepOut<Usage>, InitializeError> {
+impl<Usage: OutboundUsable, B> PendingClientContext<Usage, B> {
+    pub fn step(self, token: &[u8]) -> Result<StepOut<Usage, B>, InitializeError> {
         match self.inner.step(token)? {
-            WinStepOut::Completed(inner) => Ok(StepOut::Finished(ClientContext { inner })),
-            WinStepOut::Pending(inner) => Ok(StepOut::Pending(PendingClientContext { inner })),
+            WinStepOut::Completed(inner) => Ok(StepOut::Finished(ClientContext {
+                inner,
+                _channel: PhantomData,
+            })),
+            WinStepOut::Pending(inner) => Ok(StepOut::Pending(PendingClientContext {
+                inner,
+                _channel: PhantomData,
+            })),
         }
     }
 }
 
 #[cfg(unix)]
-impl<Usage: OutboundUsable> PendingClientContext<Usage> {
-    pub fn step(self, token: &[u8]) -> Result<StepOut<Usage>, InitializeError> {
+impl<Usage: OutboundUsable, B> PendingClientContext<Usage, B> {
+    pub fn step(self, token: &[u8]) -> Result<StepOut<Usage, B>, InitializeError> {
         match self.inner.step(token)? {
-            UnixStepOut::Finished(inner) => Ok(StepOut::Finished(ClientContext { inner })),
-            UnixStepOut::Pending(inner) => Ok(StepOut::Pending(PendingClientContext { inner })),
+            UnixStepOut::Finished(inner) => Ok(StepOut::Finished(ClientContext {
+                inner,
+                _channel: PhantomData,
+            })),
+            UnixStepOut::Pending(inner) => Ok(StepOut::Pending(PendingClientContext {
+                inner,
+                _channel: PhantomData,
+            })),
         }
     }
 }
 
-pub enum StepOut<Usage> {
-    Pending(PendingClientContext<Usage>),
-    Finished(ClientContext<Usage, MaybeSigning, MaybeEncryption, MaybeDelegation>),
+/// `B` tells whether the context is bound to a channel
+pub enum StepOut<Usage, B = Unbound> {
+    Pending(PendingClientContext<Usage, B>),
+    Finished(ClientContext<Usage, MaybeSigning, MaybeEncryption, MaybeDelegation, B>),
 }
-impl<Usage> StepOut<Usage> {
+impl<Usage, B> StepOut<Usage, B> {
     #[cfg(windows)]
-    fn from_windows(win: WinStepOut<Usage>) -> StepOut<Usage> {
+    fn from_windows(win: WinStepOut<Usage>) -> StepOut<Usage, B> {
         match win {
-            WinStepOut::Completed(inner) => Self::Finished(ClientContext { inner }),
-            WinStepOut::Pending(inner) => Self::Pending(PendingClientContext { inner }),
+            WinStepOut::Completed(inner) => Self::Finished(ClientContext {
+                inner,
+                _channel: PhantomData,
+            }),
+            WinStepOut::Pending(inner) => Self::Pending(PendingClientContext {
+                inner,
+                _channel: PhantomData,
+            }),
         }
     }
     #[cfg(unix)]
-    fn from_unix(win: UnixStepOut<Usage>) -> StepOut<Usage> {
+    fn from_unix(win: UnixStepOut<Usage>) -> StepOut<Usage, B> {
         match win {
-            UnixStepOut::Finished(inner) => Self::Finished(ClientContext { inner }),
-            UnixStepOut::Pending(inner) => Self::Pending(PendingClientContext { inner }),
+            UnixStepOut::Finished(inner) => Self::Finished(ClientContext {
+                inner,
+                _channel: PhantomData,
+            }),
+            UnixStepOut::Pending(inner) => Self::Pending(PendingClientContext {
+                inner,
+                _channel: PhantomData,
+            }),
         }
     }
 }
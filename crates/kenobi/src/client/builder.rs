@@ -1,24 +1,77 @@
-use kenobi_core::{channel_bindings::Channel, cred::usage::OutboundUsable};
+#[cfg(unix)]
+use kenobi_core::channel_bindings::Address;
+use std::marker::PhantomData;
+
+use kenobi_core::{
+    channel_bindings::{BindError, Channel, ChannelBindingType},
+    cred::usage::OutboundUsable,
+    mech::Mechanism,
+    typestate::{Bound, Unbound},
+};
 
 use crate::{
+    channel_bindings::Precomputed,
     client::{StepOut, error::InitializeError},
     cred::Credentials,
 };
 
 /// A Builder to setup a signing and encryption policy for a client context.
 /// finish setting up with `ClientBuilder::initialize`
+///
+/// `B` tells whether the context will be bound to a channel
 #[derive(Debug)]
-pub struct ClientBuilder<Usage> {
+pub struct ClientBuilder<Usage, B = Unbound> {
     #[cfg(windows)]
     inner: kenobi_windows::client::ClientBuilder<Usage>,
     #[cfg(unix)]
     inner: kenobi_unix::client::ClientBuilder<Usage>,
+    _channel: PhantomData<B>,
+}
+impl<Usage, B> ClientBuilder<Usage, B> {
+    /// Binds the context to `channel`
+    ///
+    /// # Errors
+    /// Fails if the channel fails or offers no `tls-server-end-point` bindings
+    pub fn bind_to_channel<C: Channel>(self, channel: &C) -> Result<ClientBuilder<Usage, Bound>, BindError<C::Error>> {
+        self.bind(channel.channel_bindings(), ChannelBindingType::TlsServerEndPoint)
+    }
+    /// Like `bind_to_channel`, using channel bindings of the given type
+    ///
+    /// # Errors
+    /// Fails if the channel fails or offers no bindings of this type
+    pub fn bind_to_channel_as<C: Channel>(
+        self,
+        channel: &C,
+        kind: ChannelBindingType,
+    ) -> Result<ClientBuilder<Usage, Bound>, BindError<C::Error>> {
+        self.bind(channel.channel_bindings_of(kind), kind)
+    }
+    fn bind<E>(
+        self,
+        bindings: Result<Option<Vec<u8>>, E>,
+        kind: ChannelBindingType,
+    ) -> Result<ClientBuilder<Usage, Bound>, BindError<E>> {
+        let bindings = bindings
+            .map_err(BindError::Channel)?
+            .ok_or(BindError::Unavailable(kind))?;
+        let Ok(inner) = self.inner.bind_to_channel_as(&Precomputed(bindings), kind);
+        Ok(ClientBuilder {
+            inner,
+            _channel: PhantomData,
+        })
+    }
 }
-impl<Usage> ClientBuilder<Usage> {
-    pub fn bind_to_channel<C: Channel>(self, channel: &C) -> Result<Self, C::Error> {
-        match self.inner.bind_to_channel(channel) {
-            Ok(inner) => Ok(Self { inner }),
-            Err(e) => Err(e),
+#[cfg(unix)]
+impl<Usage, B> ClientBuilder<Usage, B> {
+    /// Binds the context to the network addresses of the initiator and the acceptor, which the peer has to match
+    ///
+    /// Only available with GSSAPI
+    #[must_use]
+    pub fn bind_to_addresses(self, initiator: Option<Address>, acceptor: Option<Address>) -> Self {
+        let inner = self.inner.bind_to_addresses(initiator, acceptor);
+        Self {
+            inner,
+            _channel: PhantomData,
         }
     }
 }
@@ -28,7 +81,10 @@ impl<Usage: OutboundUsable> ClientBuilder<Usage> {
     #[must_use]
     pub fn new_from_credentials(cred: Credentials<Usage>, target_principal: Option<&str>) -> Self {
         let inner = kenobi_windows::client::ClientBuilder::new_from_credentials(cred.inner, target_principal);
-        ClientBuilder { inner }
+        ClientBuilder {
+            inner,
+            _channel: PhantomData,
+        }
     }
 }
 
@@ -37,43 +93,81 @@ impl<Usage: OutboundUsable> ClientBuilder<Usage> {
     #[must_use]
     pub fn new_from_credentials(cred: Credentials<Usage>, target_principal: Option<&str>) -> ClientBuilder<Usage> {
         let inner = kenobi_unix::client::ClientBuilder::new(cred.inner, target_principal).unwrap();
-        ClientBuilder { inner }
+        ClientBuilder {
+            inner,
+            _channel: PhantomData,
+        }
     }
 }
 
-impl<Usage> ClientBuilder<Usage> {
+impl<Usage, B> ClientBuilder<Usage, B> {
+    /// The mechanism of the credentials, which the context is established with
+    #[must_use]
+    pub fn mechanism(&self) -> Mechanism {
+        self.inner.mechanism()
+    }
     #[must_use]
     pub fn request_mutual_auth(self) -> Self {
         let inner = { self.inner.request_mutual_auth() };
-        ClientBuilder { inner }
+        ClientBuilder {
+            inner,
+            _channel: PhantomData,
+        }
+    }
+    /// Has the peers reject messages and signatures they already received
+    #[must_use]
+    pub fn request_replay_detection(self) -> Self {
+        let inner = { self.inner.request_replay_detection() };
+        ClientBuilder {
+            inner,
+            _channel: PhantomData,
+        }
     }
     #[must_use]
     pub fn request_signing(self) -> Self {
         let inner = { self.inner.request_signing() };
-        ClientBuilder { inner }
+        ClientBuilder {
+            inner,
+            _channel: PhantomData,
+        }
     }
     #[must_use]
     pub fn request_encryption(self) -> Self {
         let inner = { self.inner.request_encryption() };
-        ClientBuilder { inner }
+        ClientBuilder {
+            inner,
+            _channel: PhantomData,
+        }
     }
     #[must_use]
     pub fn request_delegation(self) -> Self {
         let inner = { self.inner.allow_delegation() };
-        ClientBuilder { inner }
+        ClientBuilder {
+            inner,
+            _channel: PhantomData,
+        }
+    }
+    /// Only delegates to targets the KDC marked as OK-as-delegate, instead of any target
+    #[must_use]
+    pub fn delegate_if_ok_as_delegate(self) -> Self {
+        let inner = { self.inner.delegate_if_ok_as_delegate() };
+        ClientBuilder {
+            inner,
+            _channel: PhantomData,
+        }
     }
 }
 
 #[cfg(windows)]
-impl<Usage: OutboundUsable> ClientBuilder<Usage> {
-    pub fn initialize(self) -> Result<StepOut<Usage>, InitializeError> {
+impl<Usage: OutboundUsable, B> ClientBuilder<Usage, B> {
+    pub fn initialize(self) -> Result<StepOut<Usage, B>, InitializeError> {
         Ok(StepOut::from_windows(self.inner.initialize()?))
     }
 }
 
 #[cfg(unix)]
-impl<Usage: OutboundUsable> ClientBuilder<Usage> {
-    pub fn initialize(self) -> Result<StepOut<Usage>, InitializeError> {
+impl<Usage: OutboundUsable, B> ClientBuilder<Usage, B> {
+    pub fn initialize(self) -> Result<StepOut<Usage, B>, InitializeError> {
         self.inner
             .initialize()
             .map(StepOut::from_unix)
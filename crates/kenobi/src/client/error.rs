@@ -14,6 +14,23 @@ pub enum InitializeError {
     NoCredentials,
     Unknown,
 }
+impl std::error::Error for InitializeError {}
+impl std::fmt::Display for InitializeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::BadChannelBindings => "the channel bindings do not match",
+            Self::BadSignature => "a token has an invalid signature",
+            Self::ContextExpired => "the context has expired",
+            Self::CredentialsExpired => "the credentials have expired",
+            Self::DefectiveToken => "a token is defective",
+            Self::InvalidContext => "the context is invalid",
+            Self::InvalidCredentials => "the credentials are invalid",
+            Self::InvalidName => "the target name is invalid",
+            Self::NoCredentials => "no credentials are available",
+            Self::Unknown => "unknown failure",
+        })
+    }
+}
 
 #[cfg(unix)]
 impl From<kenobi_unix::Error> for InitializeError {
@@ -35,7 +52,7 @@ impl From<kenobi_unix::Error> for InitializeError {
                     Kind::NoCredentials => Self::NoCredentials,
                 },
             },
-            _ => todo!(),
+            Error::Mechanism(_) => Self::Unknown,
         }
     }
 }
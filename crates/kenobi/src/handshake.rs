@@ -0,0 +1,243 @@
+//! Drivers running a whole authentication exchange over a [`Transport`]
+//!
+//! Both sides keep exchanging tokens until their context is finished,
+//! including the final token of the side finishing first (e.g. the mutual authentication reply of the server)
+
+use std::{
+    fmt::Display,
+    io::{Read, Write},
+};
+
+use kenobi_core::{
+    cred::usage::{InboundUsable, OutboundUsable},
+    typestate::{MaybeDelegation, MaybeEncryption, MaybeSigning},
+};
+
+use crate::{
+    client::{ClientBuilder, ClientContext, InitializeError, StepOut as ClientStepOut},
+    server::{AcceptError, ServerBuilder, ServerContext, StepOut as ServerStepOut},
+};
+
+/// A bidirectional channel delivering whole tokens between client and server
+pub trait Transport {
+    type Error;
+    /// Sends a single token to the peer
+    fn send_token(&mut self, token: &[u8]) -> Result<(), Self::Error>;
+    /// Receives a single token from the peer
+    ///
+    /// Implementations should refuse tokens longer than `max_len` before buffering them
+    fn receive_token(&mut self, max_len: usize) -> Result<Vec<u8>, Self::Error>;
+}
+impl<T: Transport + ?Sized> Transport for &mut T {
+    type Error = T::Error;
+    fn send_token(&mut self, token: &[u8]) -> Result<(), Self::Error> {
+        (**self).send_token(token)
+    }
+    fn receive_token(&mut self, max_len: usize) -> Result<Vec<u8>, Self::Error> {
+        (**self).receive_token(max_len)
+    }
+}
+
+/// Frames every token with its length as a 4 byte big endian prefix
+#[derive(Debug)]
+pub struct LengthPrefixed<S> {
+    stream: S,
+}
+impl<S> LengthPrefixed<S> {
+    pub fn new(stream: S) -> Self {
+        Self { stream }
+    }
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+impl<S: Read + Write> Transport for LengthPrefixed<S> {
+    type Error = std::io::Error;
+    fn send_token(&mut self, token: &[u8]) -> Result<(), Self::Error> {
+        let length = u32::try_from(token.len()).map_err(|_| std::io::ErrorKind::InvalidInput)?;
+        self.stream.write_all(&length.to_be_bytes())?;
+        self.stream.write_all(token)?;
+        self.stream.flush()
+    }
+    fn receive_token(&mut self, max_len: usize) -> Result<Vec<u8>, Self::Error> {
+        let mut length = [0; 4];
+        self.stream.read_exact(&mut length)?;
+        let length = u32::from_be_bytes(length) as usize;
+        if length > max_len {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("token of {length} bytes exceeds the limit of {max_len} bytes"),
+            ));
+        }
+        let mut token = vec![0; length];
+        self.stream.read_exact(&mut token)?;
+        Ok(token)
+    }
+}
+
+/// Bounds on an authentication exchange, to stop misbehaving peers early
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum number of tokens accepted from the peer
+    pub max_rounds: usize,
+    /// Maximum length of a single token received from the peer
+    pub max_token_len: usize,
+}
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_rounds: 8,
+            max_token_len: 64 * 1024,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum HandshakeError<E> {
+    Transport(E),
+    Initialize(InitializeError),
+    Accept(AcceptError),
+    TooManyRounds { max: usize },
+    TokenTooLarge { len: usize, max: usize },
+}
+impl<E: Display> Display for HandshakeError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Transport(e) => write!(f, "token transport failed: {e}"),
+            Self::Initialize(e) => write!(f, "client context failed: {e}"),
+            Self::Accept(e) => write!(f, "server context failed: {e}"),
+            Self::TooManyRounds { max } => write!(f, "authentication did not finish within {max} rounds"),
+            Self::TokenTooLarge { len, max } => {
+                write!(f, "received token of {len} bytes exceeds the limit of {max} bytes")
+            }
+        }
+    }
+}
+impl<E: std::error::Error + 'static> std::error::Error for HandshakeError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Transport(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn check_round<E>(rounds: &mut usize, limits: Limits) -> Result<(), HandshakeError<E>> {
+    *rounds += 1;
+    if *rounds > limits.max_rounds {
+        return Err(HandshakeError::TooManyRounds { max: limits.max_rounds });
+    }
+    Ok(())
+}
+pub(crate) fn check_token_len<E>(len: usize, max: usize) -> Result<(), HandshakeError<E>> {
+    if len > max {
+        return Err(HandshakeError::TokenTooLarge { len, max });
+    }
+    Ok(())
+}
+
+fn receive<T: Transport>(
+    transport: &mut T,
+    rounds: &mut usize,
+    limits: Limits,
+) -> Result<Vec<u8>, HandshakeError<T::Error>> {
+    check_round(rounds, limits)?;
+    let token = transport
+        .receive_token(limits.max_token_len)
+        .map_err(HandshakeError::Transport)?;
+    check_token_len(token.len(), limits.max_token_len)?;
+    Ok(token)
+}
+
+/// Runs the client side of the authentication using the default [`Limits`]
+///
+/// # Errors
+/// Fails if the transport fails, the context could not be established or the server violated the limits
+#[allow(clippy::type_complexity)]
+pub fn authenticate_client<Usage: OutboundUsable, B, T: Transport>(
+    builder: ClientBuilder<Usage, B>,
+    transport: &mut T,
+) -> Result<ClientContext<Usage, MaybeSigning, MaybeEncryption, MaybeDelegation, B>, HandshakeError<T::Error>> {
+    authenticate_client_with_limits(builder, transport, Limits::default())
+}
+
+/// Runs the client side of the authentication
+///
+/// # Errors
+/// Fails if the transport fails, the context could not be established or the server violated the limits
+#[allow(clippy::type_complexity)]
+pub fn authenticate_client_with_limits<Usage: OutboundUsable, B, T: Transport>(
+    builder: ClientBuilder<Usage, B>,
+    transport: &mut T,
+    limits: Limits,
+) -> Result<ClientContext<Usage, MaybeSigning, MaybeEncryption, MaybeDelegation, B>, HandshakeError<T::Error>> {
+    let mut rounds = 0;
+    let mut step = builder.initialize().map_err(HandshakeError::Initialize)?;
+    loop {
+        match step {
+            ClientStepOut::Pending(pending) => {
+                transport
+                    .send_token(pending.next_token())
+                    .map_err(HandshakeError::Transport)?;
+                let token = receive(transport, &mut rounds, limits)?;
+                step = pending.step(&token).map_err(HandshakeError::Initialize)?;
+            }
+            ClientStepOut::Finished(context) => {
+                if let Some(token) = context.last_token() {
+                    transport.send_token(token).map_err(HandshakeError::Transport)?;
+                }
+                return Ok(context);
+            }
+        }
+    }
+}
+
+/// Runs the server side of the authentication using the default [`Limits`]
+///
+/// # Errors
+/// Fails if the transport fails, the context could not be established or the client violated the limits
+#[allow(clippy::type_complexity)]
+pub fn authenticate_server<Usage: InboundUsable, B, T: Transport>(
+    builder: ServerBuilder<Usage, B>,
+    transport: &mut T,
+) -> Result<ServerContext<Usage, MaybeSigning, MaybeEncryption, MaybeDelegation, B>, HandshakeError<T::Error>> {
+    authenticate_server_with_limits(builder, transport, Limits::default())
+}
+
+/// Runs the server side of the authentication
+///
+/// # Errors
+/// Fails if the transport fails, the context could not be established or the client violated the limits
+#[allow(clippy::type_complexity)]
+pub fn authenticate_server_with_limits<Usage: InboundUsable, B, T: Transport>(
+    builder: ServerBuilder<Usage, B>,
+    transport: &mut T,
+    limits: Limits,
+) -> Result<ServerContext<Usage, MaybeSigning, MaybeEncryption, MaybeDelegation, B>, HandshakeError<T::Error>> {
+    let mut rounds = 0;
+    let token = receive(transport, &mut rounds, limits)?;
+    let mut step = builder.initialize(&token).map_err(HandshakeError::Accept)?;
+    loop {
+        match step {
+            ServerStepOut::Pending(pending) => {
+                transport
+                    .send_token(pending.next_token())
+                    .map_err(HandshakeError::Transport)?;
+                let token = receive(transport, &mut rounds, limits)?;
+                step = pending.step(&token).map_err(HandshakeError::Accept)?;
+            }
+            ServerStepOut::Finished(context) => {
+                if let Some(token) = context.last_token() {
+                    transport.send_token(token).map_err(HandshakeError::Transport)?;
+                }
+                return Ok(context);
+            }
+        }
+    }
+}
@@ -0,0 +1,267 @@
+//! Helpers for HTTP Negotiate authentication (RFC 4559), for servers as well as proxies
+//!
+//! These only deal with header values, so they work with any HTTP library.
+//! A client sends the initial token in an `Authorization` (or `Proxy-Authorization`) header,
+//! answers further `401` (or `407`) challenges the same way, and finally processes the
+//! mutual authentication token the server may attach to its successful response.
+
+use std::fmt::Display;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use kenobi_core::{
+    cred::usage::OutboundUsable,
+    typestate::{MaybeDelegation, MaybeEncryption, MaybeSigning},
+};
+
+use crate::client::{ClientContext, InitializeError, StepOut};
+
+/// The authentication schemes carrying GSSAPI tokens
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scheme {
+    Negotiate,
+    Kerberos,
+}
+impl Scheme {
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Negotiate => "Negotiate",
+            Self::Kerberos => "Kerberos",
+        }
+    }
+    fn from_name(name: &str) -> Option<Self> {
+        [Self::Negotiate, Self::Kerberos]
+            .into_iter()
+            .find(|scheme| scheme.name().eq_ignore_ascii_case(name))
+    }
+}
+impl Display for Scheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Whether the authentication is with the origin server or with a proxy
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthTarget {
+    Server,
+    Proxy,
+}
+impl AuthTarget {
+    /// The status code demanding authentication, `401` or `407`
+    #[must_use]
+    pub fn status(self) -> u16 {
+        match self {
+            Self::Server => 401,
+            Self::Proxy => 407,
+        }
+    }
+    #[must_use]
+    pub fn from_status(status: u16) -> Option<Self> {
+        match status {
+            401 => Some(Self::Server),
+            407 => Some(Self::Proxy),
+            _ => None,
+        }
+    }
+    /// The response header carrying the challenges
+    #[must_use]
+    pub fn challenge_header(self) -> &'static str {
+        match self {
+            Self::Server => "WWW-Authenticate",
+            Self::Proxy => "Proxy-Authenticate",
+        }
+    }
+    /// The request header carrying the credentials
+    #[must_use]
+    pub fn authorization_header(self) -> &'static str {
+        match self {
+            Self::Server => "Authorization",
+            Self::Proxy => "Proxy-Authorization",
+        }
+    }
+}
+
+/// A single challenge of a `WWW-Authenticate` or `Proxy-Authenticate` header
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Challenge<'a> {
+    pub scheme: &'a str,
+    pub token68: Option<&'a str>,
+    /// Parameters with surrounding quotes removed, but not unescaped
+    pub params: Vec<(&'a str, &'a str)>,
+}
+
+/// Splits at commas outside of quoted strings
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    let mut start = 0;
+    let mut end = false;
+    std::iter::from_fn(move || {
+        if end {
+            return None;
+        }
+        for (i, c) in value[start..].char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_quotes => escaped = true,
+                '"' => in_quotes = !in_quotes,
+                ',' if !in_quotes => {
+                    let element = &value[start..start + i];
+                    start += i + 1;
+                    return Some(element);
+                }
+                _ => {}
+            }
+        }
+        end = true;
+        Some(&value[start..])
+    })
+    .map(str::trim)
+    .filter(|element| !element.is_empty())
+}
+
+fn is_tchar(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+}
+fn is_token68(value: &str) -> bool {
+    let data = value.trim_end_matches('=');
+    !data.is_empty() && data.chars().all(|c| c.is_ascii_alphanumeric() || "-._~+/".contains(c))
+}
+fn parse_param(element: &str) -> Option<(&str, &str)> {
+    let (name, value) = element.split_once('=')?;
+    let name = name.trim_end();
+    if name.is_empty() || !name.chars().all(is_tchar) {
+        return None;
+    }
+    let value = value.trim_start();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value);
+    Some((name, value))
+}
+
+/// Parses the challenges of one `WWW-Authenticate` or `Proxy-Authenticate` header value
+///
+/// Elements that fit no challenge are skipped
+#[must_use]
+pub fn parse_challenges(value: &str) -> Vec<Challenge<'_>> {
+    let mut challenges: Vec<Challenge<'_>> = Vec::new();
+    for element in split_list(value) {
+        let (first, rest) = match element.split_once(' ') {
+            Some((first, rest)) => (first, Some(rest.trim_start())),
+            None => (element, None),
+        };
+        let starts_challenge = first.chars().all(is_tchar) && rest.is_none_or(|rest| !rest.starts_with('='));
+        if starts_challenge {
+            let mut challenge = Challenge {
+                scheme: first,
+                token68: None,
+                params: Vec::new(),
+            };
+            match rest {
+                Some(rest) if is_token68(rest) => challenge.token68 = Some(rest),
+                Some(rest) => challenge.params.extend(parse_param(rest)),
+                None => {}
+            }
+            challenges.push(challenge);
+        } else if let (Some(challenge), Some(param)) = (challenges.last_mut(), parse_param(element)) {
+            challenge.params.push(param);
+        }
+    }
+    challenges
+}
+
+/// A challenge that can be answered with a GSSAPI token
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NegotiateChallenge {
+    pub scheme: Scheme,
+    /// The decoded token of a continued exchange, `None` for the initial challenge
+    pub token: Option<Vec<u8>>,
+}
+
+/// Picks the `Negotiate` challenge, or the `Kerberos` one if there is no `Negotiate` challenge
+///
+/// `values` are all values of the `WWW-Authenticate` (or `Proxy-Authenticate`) header
+///
+/// # Errors
+/// Fails if the token of the picked challenge is not valid base64
+pub fn select_challenge<'a>(
+    values: impl IntoIterator<Item = &'a str>,
+) -> Result<Option<NegotiateChallenge>, HttpAuthError> {
+    let mut selected: Option<(Scheme, Option<&str>)> = None;
+    for challenge in values.into_iter().flat_map(parse_challenges) {
+        let Some(scheme) = Scheme::from_name(challenge.scheme) else {
+            continue;
+        };
+        if selected.is_none_or(|(selected, _)| selected == Scheme::Kerberos && scheme == Scheme::Negotiate) {
+            selected = Some((scheme, challenge.token68));
+        }
+    }
+    selected
+        .map(|(scheme, token)| {
+            Ok(NegotiateChallenge {
+                scheme,
+                token: token.map(decode).transpose()?,
+            })
+        })
+        .transpose()
+}
+
+fn decode(token: &str) -> Result<Vec<u8>, HttpAuthError> {
+    STANDARD.decode(token).map_err(|_| HttpAuthError::InvalidBase64)
+}
+
+/// Builds the value of an `Authorization` (or `Proxy-Authorization`) header carrying `token`
+#[must_use]
+pub fn authorization_value(scheme: Scheme, token: &[u8]) -> String {
+    format!("{scheme} {}", STANDARD.encode(token))
+}
+
+/// Completes the client context with the token the server attached to its successful response
+///
+/// `values` are all values of the `WWW-Authenticate` header of the final response.
+/// A context that still waits for mutual authentication has to receive a token,
+/// a context that is already finished ignores it.
+///
+/// # Errors
+/// Fails if the server did not send a token although one is needed, or the token does not complete the context
+pub fn finish_client<'a, Usage: OutboundUsable, B>(
+    step: StepOut<Usage, B>,
+    values: impl IntoIterator<Item = &'a str>,
+) -> Result<ClientContext<Usage, MaybeSigning, MaybeEncryption, MaybeDelegation, B>, HttpAuthError> {
+    let pending = match step {
+        StepOut::Finished(context) => return Ok(context),
+        StepOut::Pending(pending) => pending,
+    };
+    let token = select_challenge(values)?
+        .and_then(|challenge| challenge.token)
+        .ok_or(HttpAuthError::MissingToken)?;
+    match pending.step(&token).map_err(HttpAuthError::Initialize)? {
+        StepOut::Finished(context) => Ok(context),
+        StepOut::Pending(_) => Err(HttpAuthError::NotFinished),
+    }
+}
+
+#[derive(Debug)]
+pub enum HttpAuthError {
+    /// The token of a challenge is not valid base64
+    InvalidBase64,
+    /// The server did not send the token needed to finish the context
+    MissingToken,
+    /// The final token of the server did not finish the context
+    NotFinished,
+    Initialize(InitializeError),
+}
+impl std::error::Error for HttpAuthError {}
+impl Display for HttpAuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidBase64 => write!(f, "the challenge token is not valid base64"),
+            Self::MissingToken => write!(f, "the server did not send a mutual authentication token"),
+            Self::NotFinished => write!(f, "the final token of the server did not finish authentication"),
+            Self::Initialize(e) => write!(f, "client context failed: {e}"),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::fmt::Display;
+
+pub use kenobi_core::prf::PrfKey;
+
+/// Failure to query the session key of an established context
+#[derive(Debug)]
+pub struct SessionKeyError {
+    #[cfg(windows)]
+    pub(crate) inner: windows_result::Error,
+    #[cfg(unix)]
+    pub(crate) inner: kenobi_unix::Error,
+}
+impl SessionKeyError {
+    #[cfg(windows)]
+    pub(crate) fn from_inner(inner: windows_result::Error) -> Self {
+        Self { inner }
+    }
+    #[cfg(unix)]
+    pub(crate) fn from_inner(inner: kenobi_unix::Error) -> Self {
+        Self { inner }
+    }
+}
+impl std::error::Error for SessionKeyError {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        Some(&self.inner)
+    }
+}
+impl Display for SessionKeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+/// Failure to derive keys using the pseudo-random function of an established context
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct PrfError {
+    pub(crate) inner: kenobi_unix::Error,
+}
+#[cfg(unix)]
+impl PrfError {
+    pub(crate) fn from_inner(inner: kenobi_unix::Error) -> Self {
+        Self { inner }
+    }
+}
+#[cfg(unix)]
+impl std::error::Error for PrfError {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        Some(&self.inner)
+    }
+}
+#[cfg(unix)]
+impl Display for PrfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.inner.fmt(f)
+    }
+}
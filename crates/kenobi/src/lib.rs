@@ -1,6 +1,18 @@
 pub mod client;
+pub mod handshake;
+pub mod http;
+pub mod key;
+#[cfg(feature = "reqwest")]
+pub mod reqwest;
+pub mod sasl;
 pub mod server;
 pub mod sign_encrypt;
+pub mod split;
+pub mod stream;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+#[cfg(feature = "tower")]
+pub mod tower;
 
 pub mod cred {
     use std::sync::Arc;
@@ -54,6 +66,15 @@ pub mod cred {
             }
         }
     }
+    #[cfg(unix)]
+    impl<Usage> Credentials<Usage> {
+        pub(crate) fn from_unix(inner: UnixCred<Usage>) -> Self {
+            Self {
+                inner: Arc::new(inner),
+                _marker: PhantomData,
+            }
+        }
+    }
     impl<Usage: CredentialsUsage> Credentials<Usage> {
         pub fn mechanism(&self) -> Mechanism {
             self.inner.mechanism()
@@ -91,12 +112,26 @@ pub mod cred {
     }
 }
 pub mod channel_bindings {
-    pub use kenobi_core::channel_bindings::Channel;
+    #[cfg(feature = "rustls")]
+    pub use kenobi_core::channel_bindings::ServerChannel;
+    pub use kenobi_core::channel_bindings::{Address, BindError, Channel, ChannelBindingPolicy, ChannelBindingType};
+
+    /// Bindings of any type computed ahead of time, after checking that the channel offers them
+    pub(crate) struct Precomputed(pub(crate) Vec<u8>);
+    impl Channel for Precomputed {
+        type Error = std::convert::Infallible;
+        fn channel_bindings(&self) -> Result<Option<Vec<u8>>, Self::Error> {
+            Ok(Some(self.0.clone()))
+        }
+        fn channel_bindings_of(&self, _kind: ChannelBindingType) -> Result<Option<Vec<u8>>, Self::Error> {
+            self.channel_bindings()
+        }
+    }
 }
 pub mod typestate {
     pub use kenobi_core::typestate::{
-        Delegation, Encryption, MaybeDelegation, MaybeEncryption, MaybeSigning, NoDelegation, NoEncryption, NoSigning,
-        Signing,
+        Bound, Delegation, Encryption, MaybeDelegation, MaybeEncryption, MaybeSigning, NoDelegation, NoEncryption,
+        NoSigning, Signing, Unbound,
     };
 }
 pub use kenobi_core::mech;
@@ -0,0 +1,263 @@
+//! Negotiate authentication (RFC 4559) for requests sent with `reqwest`
+//!
+//! [`NegotiateClient`] sends every request as is, and only authenticates if the server (`401`) or the
+//! proxy (`407`) asks for `Negotiate` or `Kerberos`.
+//! The service principal is `HTTP/<host>`, with the host taken from the request URL or the configured proxy.
+//! Credentials are acquired once and shared by all requests of the client.
+
+use std::fmt::Display;
+
+use ::reqwest::{
+    Client, Request, Response,
+    header::{AUTHORIZATION, HeaderName, HeaderValue, PROXY_AUTHENTICATE, PROXY_AUTHORIZATION, WWW_AUTHENTICATE},
+};
+use kenobi_core::cred::usage::Outbound;
+
+use crate::{
+    client::{ClientBuilder, InitializeError, StepOut},
+    cred::Credentials,
+    http::{AuthTarget, HttpAuthError, Scheme, authorization_value, finish_client, select_challenge},
+    tokio::blocking,
+};
+
+/// Maximum number of challenges answered within one exchange
+const MAX_ROUNDS: usize = 8;
+
+/// A `reqwest` client answering Negotiate challenges of servers and proxies
+#[derive(Clone, Debug)]
+pub struct NegotiateClient {
+    client: Client,
+    credentials: Credentials<Outbound>,
+    proxy_host: Option<String>,
+    delegation: bool,
+    #[cfg(any(feature = "reqwest-native-tls", feature = "reqwest-rustls"))]
+    channel_bindings: bool,
+}
+impl NegotiateClient {
+    #[must_use]
+    pub fn new(client: Client, credentials: Credentials<Outbound>) -> Self {
+        Self {
+            client,
+            credentials,
+            proxy_host: None,
+            delegation: false,
+            #[cfg(any(feature = "reqwest-native-tls", feature = "reqwest-rustls"))]
+            channel_bindings: false,
+        }
+    }
+    /// The host of the proxy configured on the client, needed to answer `407` challenges
+    #[must_use]
+    pub fn with_proxy_host(self, host: impl Into<String>) -> Self {
+        Self {
+            proxy_host: Some(host.into()),
+            ..self
+        }
+    }
+    /// Delegates the credentials to servers the KDC marked as OK-as-delegate
+    #[must_use]
+    pub fn with_delegation(self) -> Self {
+        Self {
+            delegation: true,
+            ..self
+        }
+    }
+    /// Binds the authentication to the TLS connection with the server (`tls-server-end-point`)
+    ///
+    /// The client has to be built with `ClientBuilder::tls_info(true)`, otherwise no bindings are added.
+    /// Needs the `reqwest-native-tls` or `reqwest-rustls` feature, which also enable that TLS backend of `reqwest`.
+    /// Authentication with a proxy is never bound
+    #[cfg(any(feature = "reqwest-native-tls", feature = "reqwest-rustls"))]
+    #[must_use]
+    pub fn with_channel_bindings(self) -> Self {
+        Self {
+            channel_bindings: true,
+            ..self
+        }
+    }
+    #[must_use]
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Sends `request`, authenticating with the server and the proxy if they ask for it
+    ///
+    /// Responses to successful authentications are only returned if their mutual authentication token is valid.
+    /// If authentication is rejected, the final `401` or `407` response is returned.
+    ///
+    /// # Errors
+    /// Fails if sending fails, the body of `request` can not be sent twice, or the context could not be established
+    pub async fn execute(&self, request: Request) -> Result<Response, NegotiateError> {
+        let mut response = self.client.execute(try_clone(&request)?).await?;
+        let mut attempted = Vec::new();
+        while let Some(target) = AuthTarget::from_status(response.status().as_u16()) {
+            if attempted.contains(&target) {
+                break;
+            }
+            attempted.push(target);
+            response = self.authenticate(&request, response, target).await?;
+        }
+        Ok(response)
+    }
+
+    async fn authenticate(
+        &self,
+        request: &Request,
+        response: Response,
+        target: AuthTarget,
+    ) -> Result<Response, NegotiateError> {
+        let Some(challenge) = select_challenge(challenges(&response, target))? else {
+            return Ok(response);
+        };
+        let scheme = challenge.scheme;
+        let host = match target {
+            AuthTarget::Server => request.url().host_str(),
+            AuthTarget::Proxy => self.proxy_host.as_deref(),
+        }
+        .ok_or(NegotiateError::NoHost(target))?;
+        let principal = format!("HTTP/{host}");
+        let mut builder =
+            ClientBuilder::new_from_credentials(self.credentials.clone(), Some(&principal)).request_mutual_auth();
+        if self.delegation {
+            builder = builder.request_delegation().delegate_if_ok_as_delegate();
+        }
+        #[cfg(any(feature = "reqwest-native-tls", feature = "reqwest-rustls"))]
+        if self.channel_bindings
+            && target == AuthTarget::Server
+            && let Some(bindings) = channel_bindings(&response)
+        {
+            let bound = builder
+                .bind_to_channel(&bindings)
+                .expect("end point bindings are always offered");
+            return self.exchange(request, target, scheme, bound).await;
+        }
+        self.exchange(request, target, scheme, builder).await
+    }
+
+    async fn exchange<B: Send + 'static>(
+        &self,
+        request: &Request,
+        target: AuthTarget,
+        scheme: Scheme,
+        builder: ClientBuilder<Outbound, B>,
+    ) -> Result<Response, NegotiateError> {
+        let mut step = blocking(move || builder.initialize())
+            .await?
+            .map_err(NegotiateError::Initialize)?;
+        for _ in 0..MAX_ROUNDS {
+            let token = match &step {
+                StepOut::Pending(pending) => pending.next_token(),
+                StepOut::Finished(context) => context.last_token().unwrap_or_default(),
+            };
+            let mut attempt = try_clone(request)?;
+            attempt.headers_mut().insert(
+                authorization_header(target),
+                HeaderValue::try_from(authorization_value(scheme, token)).expect("base64 is a valid header value"),
+            );
+            let response = self.client.execute(attempt).await?;
+            if response.status().as_u16() != target.status() {
+                if response.status().is_success() {
+                    let values: Vec<String> = challenges(&response, target).map(str::to_owned).collect();
+                    blocking(move || finish_client(step, values.iter().map(String::as_str))).await??;
+                }
+                return Ok(response);
+            }
+            let StepOut::Pending(pending) = step else {
+                return Ok(response);
+            };
+            let Some(token) = select_challenge(challenges(&response, target))?.and_then(|c| c.token) else {
+                return Ok(response);
+            };
+            step = blocking(move || pending.step(&token))
+                .await?
+                .map_err(NegotiateError::Initialize)?;
+        }
+        Err(NegotiateError::TooManyRounds)
+    }
+}
+
+fn try_clone(request: &Request) -> Result<Request, NegotiateError> {
+    request.try_clone().ok_or(NegotiateError::BodyNotCloneable)
+}
+fn challenges(response: &Response, target: AuthTarget) -> impl Iterator<Item = &str> {
+    let name = match target {
+        AuthTarget::Server => WWW_AUTHENTICATE,
+        AuthTarget::Proxy => PROXY_AUTHENTICATE,
+    };
+    response
+        .headers()
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+}
+fn authorization_header(target: AuthTarget) -> HeaderName {
+    match target {
+        AuthTarget::Server => AUTHORIZATION,
+        AuthTarget::Proxy => PROXY_AUTHORIZATION,
+    }
+}
+
+#[cfg(any(feature = "reqwest-native-tls", feature = "reqwest-rustls"))]
+fn channel_bindings(response: &Response) -> Option<crate::channel_bindings::Precomputed> {
+    let certificate = response
+        .extensions()
+        .get::<::reqwest::tls::TlsInfo>()?
+        .peer_certificate()?;
+    Some(crate::channel_bindings::Precomputed(
+        kenobi_core::channel_bindings::tls_server_end_point(certificate),
+    ))
+}
+
+#[derive(Debug)]
+pub enum NegotiateError {
+    Request(::reqwest::Error),
+    /// The body of the request is a stream, which can not be sent again after the challenge
+    BodyNotCloneable,
+    /// There is no host to derive the service principal from
+    NoHost(AuthTarget),
+    Initialize(InitializeError),
+    /// A challenge was malformed or the mutual authentication token of the server was invalid
+    Http(HttpAuthError),
+    TooManyRounds,
+    /// Running GSSAPI/SSPI on the blocking thread pool failed
+    Io(std::io::Error),
+}
+impl From<::reqwest::Error> for NegotiateError {
+    fn from(value: ::reqwest::Error) -> Self {
+        Self::Request(value)
+    }
+}
+impl From<HttpAuthError> for NegotiateError {
+    fn from(value: HttpAuthError) -> Self {
+        Self::Http(value)
+    }
+}
+impl From<std::io::Error> for NegotiateError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+impl std::error::Error for NegotiateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Request(e) => Some(e),
+            Self::Initialize(e) => Some(e),
+            Self::Http(e) => Some(e),
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl Display for NegotiateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Request(e) => write!(f, "request failed: {e}"),
+            Self::BodyNotCloneable => write!(f, "the request body can not be resent for authentication"),
+            Self::NoHost(AuthTarget::Server) => write!(f, "the request URL has no host"),
+            Self::NoHost(AuthTarget::Proxy) => write!(f, "the proxy demanded authentication, but its host is unknown"),
+            Self::Initialize(e) => write!(f, "client context failed: {e}"),
+            Self::Http(e) => write!(f, "{e}"),
+            Self::TooManyRounds => write!(f, "authentication did not finish within {MAX_ROUNDS} rounds"),
+            Self::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
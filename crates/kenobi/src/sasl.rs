@@ -0,0 +1,785 @@
+//! The SASL `GSSAPI` (RFC 4752) and `GSS-SPNEGO` mechanisms on top of client and server contexts
+//!
+//! The SASL framing itself (e.g. the LDAP bind or the IMAP `AUTHENTICATE` command) is left to the application,
+//! which only passes the mechanism data along. With `GSSAPI`, the context establishment is followed by the
+//! negotiation of a security layer, wrapped by the established context. `GSS-SPNEGO` has no such negotiation,
+//! its security layer follows from the properties of the context.
+//!
+//! Both mechanisms require a context allowing at least integrity protection. The builders have to use
+//! credentials of the matching mechanism, Kerberos for `GSSAPI` and SPNEGO for `GSS-SPNEGO`.
+
+use std::{fmt::Display, ops::BitOr};
+
+use kenobi_core::{
+    cred::usage::{InboundUsable, OutboundUsable},
+    mech::Mechanism,
+    typestate::{Encryption, MaybeDelegation, MaybeEncryption, MaybeSigning, NoEncryption, Signing},
+};
+
+use crate::{
+    client::{ClientBuilder, ClientContext, InitializeError, PendingClientContext, StepOut as ClientStepOut},
+    server::{AcceptError, PendingServerContext, ServerBuilder, ServerContext, StepOut as ServerStepOut},
+    sign_encrypt::{UnwrapError, WrapError},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SaslMechanism {
+    Gssapi,
+    GssSpnego,
+}
+impl SaslMechanism {
+    /// The registered SASL mechanism name
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gssapi => "GSSAPI",
+            Self::GssSpnego => "GSS-SPNEGO",
+        }
+    }
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "GSSAPI" => Some(Self::Gssapi),
+            "GSS-SPNEGO" => Some(Self::GssSpnego),
+            _ => None,
+        }
+    }
+    /// The mechanism the context has to be established with
+    #[must_use]
+    pub fn gss_mechanism(self) -> Mechanism {
+        match self {
+            Self::Gssapi => Mechanism::KerberosV5,
+            Self::GssSpnego => Mechanism::Spnego,
+        }
+    }
+}
+impl Display for SaslMechanism {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A set of security layers, encoded like the bitmask of RFC 4752
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SecurityLayers(u8);
+impl SecurityLayers {
+    pub const NONE: Self = Self(1);
+    pub const INTEGRITY: Self = Self(2);
+    pub const CONFIDENTIALITY: Self = Self(4);
+    pub const ALL: Self = Self(7);
+    #[must_use]
+    pub fn contains(self, layer: SecurityLayer) -> bool {
+        self.0 & layer.bit() != 0
+    }
+    /// The most protective layer of the set
+    fn strongest(self) -> Option<SecurityLayer> {
+        [
+            SecurityLayer::Confidentiality,
+            SecurityLayer::Integrity,
+            SecurityLayer::None,
+        ]
+        .into_iter()
+        .find(|layer| self.contains(*layer))
+    }
+    fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+impl BitOr for SecurityLayers {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+impl Default for SecurityLayers {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+/// The protection applied to the messages following the authentication
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecurityLayer {
+    /// Messages are sent as is
+    None,
+    /// Messages are signed
+    Integrity,
+    /// Messages are encrypted
+    Confidentiality,
+}
+impl SecurityLayer {
+    fn bit(self) -> u8 {
+        match self {
+            Self::None => 1,
+            Self::Integrity => 2,
+            Self::Confidentiality => 4,
+        }
+    }
+    fn from_bit(bit: u8) -> Option<Self> {
+        match bit {
+            1 => Some(Self::None),
+            2 => Some(Self::Integrity),
+            4 => Some(Self::Confidentiality),
+            _ => None,
+        }
+    }
+}
+
+/// The outcome of the security layer negotiation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Negotiated {
+    pub layer: SecurityLayer,
+    /// Largest wrapped message the peer accepts
+    pub max_send_size: u32,
+    /// Largest wrapped message the peer may send
+    pub max_receive_size: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SaslClientConfig {
+    /// Security layers acceptable to the client
+    pub layers: SecurityLayers,
+    /// Largest wrapped message the client accepts
+    pub max_receive_size: u32,
+    /// Identity to act as, if different from the authenticated one
+    pub authzid: Option<String>,
+}
+impl Default for SaslClientConfig {
+    fn default() -> Self {
+        Self {
+            layers: SecurityLayers::ALL,
+            max_receive_size: 64 * 1024,
+            authzid: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SaslServerConfig {
+    /// Security layers offered to the client
+    pub layers: SecurityLayers,
+    /// Largest wrapped message the server accepts
+    pub max_receive_size: u32,
+}
+impl Default for SaslServerConfig {
+    fn default() -> Self {
+        Self {
+            layers: SecurityLayers::ALL,
+            max_receive_size: 64 * 1024,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SaslError {
+    Initialize(InitializeError),
+    Accept(AcceptError),
+    /// The credentials of the builder are not for the mechanism the SASL mechanism is built on
+    WrongMechanism(Mechanism),
+    /// The established context does not allow signing, so no security layer can be negotiated
+    NoIntegrity,
+    Wrap(WrapError),
+    Unwrap(UnwrapError),
+    /// The peer sent a security layer message that does not follow RFC 4752
+    Malformed,
+    /// None of the security layers offered by the server is acceptable
+    NoCommonLayer,
+    /// The client chose a security layer that was not offered
+    LayerNotOffered,
+    /// The requested authorization identity is not valid UTF-8
+    InvalidAuthzid,
+}
+impl From<WrapError> for SaslError {
+    fn from(value: WrapError) -> Self {
+        Self::Wrap(value)
+    }
+}
+impl From<UnwrapError> for SaslError {
+    fn from(value: UnwrapError) -> Self {
+        Self::Unwrap(value)
+    }
+}
+impl std::error::Error for SaslError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Initialize(e) => Some(e),
+            Self::Accept(e) => Some(e),
+            Self::Wrap(e) => Some(e),
+            Self::Unwrap(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl Display for SaslError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Initialize(e) => write!(f, "client context failed: {e}"),
+            Self::Accept(e) => write!(f, "server context failed: {e}"),
+            Self::WrongMechanism(mechanism) => write!(f, "credentials for {mechanism:?} cannot be used here"),
+            Self::NoIntegrity => write!(f, "the established context does not allow signing"),
+            Self::Wrap(e) => e.fmt(f),
+            Self::Unwrap(e) => e.fmt(f),
+            Self::Malformed => write!(f, "malformed security layer message"),
+            Self::NoCommonLayer => write!(f, "no acceptable security layer was offered"),
+            Self::LayerNotOffered => write!(f, "the chosen security layer was not offered"),
+            Self::InvalidAuthzid => write!(f, "the authorization identity is not valid UTF-8"),
+        }
+    }
+}
+
+macro_rules! session {
+    ($(#[$meta:meta])* $session:ident, $context:ident) => {
+        $(#[$meta])*
+        pub enum $session<Usage> {
+            Encryption($context<Usage, Signing, Encryption, MaybeDelegation>),
+            NoEncryption($context<Usage, Signing, NoEncryption, MaybeDelegation>),
+        }
+        impl<Usage> $session<Usage> {
+            fn new(context: $context<Usage, MaybeSigning, MaybeEncryption, MaybeDelegation>) -> Result<Self, SaslError> {
+                let context = context.check_signing().map_err(|_| SaslError::NoIntegrity)?;
+                Ok(match context.check_encryption() {
+                    Ok(context) => Self::Encryption(context),
+                    Err(context) => Self::NoEncryption(context),
+                })
+            }
+            /// Security layers the context is able to provide
+            fn layers(&self) -> SecurityLayers {
+                match self {
+                    Self::Encryption(_) => SecurityLayers::ALL,
+                    Self::NoEncryption(_) => SecurityLayers::NONE | SecurityLayers::INTEGRITY,
+                }
+            }
+            fn sign(&mut self, message: &[u8]) -> Result<Vec<u8>, WrapError> {
+                match self {
+                    Self::Encryption(context) => Ok(context.sign(message)?.to_vec()),
+                    Self::NoEncryption(context) => Ok(context.sign(message)?.to_vec()),
+                }
+            }
+            fn unwrap(&mut self, token: &[u8]) -> Result<Vec<u8>, UnwrapError> {
+                match self {
+                    Self::Encryption(context) => Ok(context.unwrap(token)?.to_vec()),
+                    Self::NoEncryption(context) => Ok(context.unwrap(token)?.to_vec()),
+                }
+            }
+        }
+    };
+}
+session!(
+    /// A client context that finished the SASL exchange, typed by whether it allows encryption
+    ClientSession,
+    ClientContext
+);
+session!(
+    /// A server context that finished the SASL exchange, typed by whether it allows encryption
+    ServerSession,
+    ServerContext
+);
+
+/// Negotiated values for `GSS-SPNEGO`, which follow from the context alone
+fn implied(layers: SecurityLayers, max_size: u32) -> Result<Negotiated, SaslError> {
+    Ok(Negotiated {
+        layer: layers.strongest().ok_or(SaslError::NoCommonLayer)?,
+        max_send_size: max_size,
+        max_receive_size: max_size,
+    })
+}
+
+fn layer_message(layers: SecurityLayers, max_size: u32) -> [u8; 4] {
+    let [_, a, b, c] = max_size.min(MAX_LAYER_SIZE).to_be_bytes();
+    [layers.0, a, b, c]
+}
+fn parse_layer_message(message: &[u8]) -> Result<(u8, u32, &[u8]), SaslError> {
+    let Some(([layers, size @ ..], rest)) = message.split_first_chunk::<4>() else {
+        return Err(SaslError::Malformed);
+    };
+    let [a, b, c] = *size;
+    Ok((*layers, u32::from_be_bytes([0, a, b, c]), rest))
+}
+
+/// Largest size representable in the 3 byte field of the security layer messages
+const MAX_LAYER_SIZE: u32 = 0x00ff_ffff;
+
+/// The maximum size is only meaningful if a layer wraps the messages
+fn max_size_for(layer: SecurityLayer, max_size: u32) -> u32 {
+    match layer {
+        SecurityLayer::None => 0,
+        SecurityLayer::Integrity | SecurityLayer::Confidentiality => max_size.min(MAX_LAYER_SIZE),
+    }
+}
+
+/// The unwrapped message of the server offering its layers
+fn layer_offer(config: &SaslServerConfig, available: SecurityLayers) -> Result<[u8; 4], SaslError> {
+    let offered = config.layers.intersection(available);
+    if offered.strongest().is_none() {
+        return Err(SaslError::NoCommonLayer);
+    }
+    let max_size = if offered == SecurityLayers::NONE {
+        0
+    } else {
+        config.max_receive_size
+    };
+    Ok(layer_message(offered, max_size))
+}
+
+/// Picks the strongest layer of the offer acceptable to the client and builds its unwrapped reply
+fn choose_layer(
+    offer: &[u8],
+    config: &SaslClientConfig,
+    available: SecurityLayers,
+) -> Result<(Negotiated, Vec<u8>), SaslError> {
+    let (offered, max_send_size, _) = parse_layer_message(offer)?;
+    let layer = SecurityLayers(offered)
+        .intersection(config.layers)
+        .intersection(available)
+        .strongest()
+        .ok_or(SaslError::NoCommonLayer)?;
+    let max_receive_size = max_size_for(layer, config.max_receive_size);
+    let mut reply = layer_message(SecurityLayers(layer.bit()), max_receive_size).to_vec();
+    reply.extend_from_slice(config.authzid.as_deref().unwrap_or_default().as_bytes());
+    let negotiated = Negotiated {
+        layer,
+        max_send_size,
+        max_receive_size,
+    };
+    Ok((negotiated, reply))
+}
+
+/// Checks the unwrapped reply of the client against the offer of the server
+fn accept_choice(
+    reply: &[u8],
+    config: &SaslServerConfig,
+    available: SecurityLayers,
+) -> Result<(Negotiated, Option<String>), SaslError> {
+    let (chosen, max_send_size, authzid) = parse_layer_message(reply)?;
+    let layer = SecurityLayer::from_bit(chosen).ok_or(SaslError::Malformed)?;
+    if !config.layers.intersection(available).contains(layer) {
+        return Err(SaslError::LayerNotOffered);
+    }
+    let authzid = match authzid {
+        [] => None,
+        authzid => Some(String::from_utf8(authzid.to_vec()).map_err(|_| SaslError::InvalidAuthzid)?),
+    };
+    let negotiated = Negotiated {
+        layer,
+        max_send_size,
+        max_receive_size: max_size_for(layer, config.max_receive_size),
+    };
+    Ok((negotiated, authzid))
+}
+
+fn check_mechanism(mechanism: SaslMechanism, credentials: Mechanism) -> Result<(), SaslError> {
+    if mechanism.gss_mechanism() == credentials {
+        Ok(())
+    } else {
+        Err(SaslError::WrongMechanism(credentials))
+    }
+}
+
+enum ClientState<Usage> {
+    Handshake(PendingClientContext<Usage>),
+    AwaitingLayers {
+        session: ClientSession<Usage>,
+        response: Vec<u8>,
+    },
+}
+
+/// The client side of a SASL exchange that is still in progress
+pub struct SaslClient<Usage> {
+    mechanism: SaslMechanism,
+    config: SaslClientConfig,
+    state: ClientState<Usage>,
+}
+pub enum ClientStep<Usage> {
+    /// Send [`SaslClient::response`] and pass the following challenge of the server to [`SaslClient::step`]
+    Continue(SaslClient<Usage>),
+    Finished(SaslClientOutcome<Usage>),
+}
+impl<Usage: OutboundUsable> SaslClient<Usage> {
+    /// Starts the exchange, the first response is the initial response of the client
+    pub fn start(
+        builder: ClientBuilder<Usage>,
+        mechanism: SaslMechanism,
+        config: SaslClientConfig,
+    ) -> Result<ClientStep<Usage>, SaslError> {
+        check_mechanism(mechanism, builder.mechanism())?;
+        let step = builder.initialize().map_err(SaslError::Initialize)?;
+        Self::after_handshake_step(mechanism, config, step)
+    }
+    /// Processes the next challenge of the server
+    pub fn step(self, challenge: &[u8]) -> Result<ClientStep<Usage>, SaslError> {
+        let Self {
+            mechanism,
+            config,
+            state,
+        } = self;
+        match state {
+            ClientState::Handshake(pending) => {
+                let step = pending.step(challenge).map_err(SaslError::Initialize)?;
+                Self::after_handshake_step(mechanism, config, step)
+            }
+            ClientState::AwaitingLayers { mut session, .. } => {
+                let message = session.unwrap(challenge)?;
+                let (negotiated, reply) = choose_layer(&message, &config, session.layers())?;
+                let response = session.sign(&reply)?;
+                Ok(ClientStep::Finished(SaslClientOutcome {
+                    session,
+                    negotiated,
+                    response: Some(response),
+                }))
+            }
+        }
+    }
+    fn after_handshake_step(
+        mechanism: SaslMechanism,
+        config: SaslClientConfig,
+        step: ClientStepOut<Usage>,
+    ) -> Result<ClientStep<Usage>, SaslError> {
+        let context = match step {
+            ClientStepOut::Pending(pending) => {
+                return Ok(ClientStep::Continue(Self {
+                    mechanism,
+                    config,
+                    state: ClientState::Handshake(pending),
+                }));
+            }
+            ClientStepOut::Finished(context) => context,
+        };
+        let last_token = context.last_token().map(<[u8]>::to_vec);
+        let session = ClientSession::new(context)?;
+        match mechanism {
+            SaslMechanism::Gssapi => Ok(ClientStep::Continue(Self {
+                mechanism,
+                state: ClientState::AwaitingLayers {
+                    session,
+                    response: last_token.unwrap_or_default(),
+                },
+                config,
+            })),
+            SaslMechanism::GssSpnego => Ok(ClientStep::Finished(SaslClientOutcome {
+                negotiated: implied(session.layers().intersection(config.layers), config.max_receive_size)?,
+                session,
+                response: last_token,
+            })),
+        }
+    }
+}
+impl<Usage> SaslClient<Usage> {
+    #[must_use]
+    pub fn mechanism(&self) -> SaslMechanism {
+        self.mechanism
+    }
+    /// The data to send to the server next
+    #[must_use]
+    pub fn response(&self) -> &[u8] {
+        match &self.state {
+            ClientState::Handshake(pending) => pending.next_token(),
+            ClientState::AwaitingLayers { response, .. } => response,
+        }
+    }
+}
+
+pub struct SaslClientOutcome<Usage> {
+    session: ClientSession<Usage>,
+    negotiated: Negotiated,
+    response: Option<Vec<u8>>,
+}
+impl<Usage> SaslClientOutcome<Usage> {
+    /// The last data to send to the server, if any
+    #[must_use]
+    pub fn response(&self) -> Option<&[u8]> {
+        self.response.as_deref()
+    }
+    #[must_use]
+    pub fn negotiated(&self) -> Negotiated {
+        self.negotiated
+    }
+    pub fn into_session(self) -> ClientSession<Usage> {
+        self.session
+    }
+}
+
+enum ServerState<Usage> {
+    Handshake(PendingServerContext<Usage>),
+    /// The last token of the context establishment has to be acknowledged by the client
+    SendingLastToken {
+        session: ServerSession<Usage>,
+        challenge: Vec<u8>,
+    },
+    AwaitingChoice {
+        session: ServerSession<Usage>,
+        challenge: Vec<u8>,
+    },
+}
+
+/// The server side of a SASL exchange that is still in progress
+pub struct SaslServer<Usage> {
+    mechanism: SaslMechanism,
+    config: SaslServerConfig,
+    state: ServerState<Usage>,
+}
+pub enum ServerStep<Usage> {
+    /// Send [`SaslServer::challenge`] and pass the following response of the client to [`SaslServer::step`]
+    Continue(SaslServer<Usage>),
+    Finished(SaslServerOutcome<Usage>),
+}
+impl<Usage: InboundUsable> SaslServer<Usage> {
+    /// Starts the exchange with the initial response of the client
+    pub fn start(
+        builder: ServerBuilder<Usage>,
+        mechanism: SaslMechanism,
+        config: SaslServerConfig,
+        initial_response: &[u8],
+    ) -> Result<ServerStep<Usage>, SaslError> {
+        check_mechanism(mechanism, builder.mechanism())?;
+        let step = builder.initialize(initial_response).map_err(SaslError::Accept)?;
+        Self::after_handshake_step(mechanism, config, step)
+    }
+    /// Processes the next response of the client
+    pub fn step(self, response: &[u8]) -> Result<ServerStep<Usage>, SaslError> {
+        let Self {
+            mechanism,
+            config,
+            state,
+        } = self;
+        match state {
+            ServerState::Handshake(pending) => {
+                let step = pending.step(response).map_err(SaslError::Accept)?;
+                Self::after_handshake_step(mechanism, config, step)
+            }
+            ServerState::SendingLastToken { mut session, .. } => {
+                if !response.is_empty() {
+                    return Err(SaslError::Malformed);
+                }
+                let challenge = Self::offer(&config, &mut session)?;
+                Ok(ServerStep::Continue(Self {
+                    mechanism,
+                    config,
+                    state: ServerState::AwaitingChoice { session, challenge },
+                }))
+            }
+            ServerState::AwaitingChoice { mut session, .. } => {
+                let message = session.unwrap(response)?;
+                let (negotiated, authzid) = accept_choice(&message, &config, session.layers())?;
+                Ok(ServerStep::Finished(SaslServerOutcome {
+                    session,
+                    negotiated,
+                    authzid,
+                    challenge: None,
+                }))
+            }
+        }
+    }
+    fn offer(config: &SaslServerConfig, session: &mut ServerSession<Usage>) -> Result<Vec<u8>, SaslError> {
+        let offer = layer_offer(config, session.layers())?;
+        Ok(session.sign(&offer)?)
+    }
+    fn after_handshake_step(
+        mechanism: SaslMechanism,
+        config: SaslServerConfig,
+        step: ServerStepOut<Usage>,
+    ) -> Result<ServerStep<Usage>, SaslError> {
+        let context = match step {
+            ServerStepOut::Pending(pending) => {
+                return Ok(ServerStep::Continue(Self {
+                    mechanism,
+                    config,
+                    state: ServerState::Handshake(pending),
+                }));
+            }
+            ServerStepOut::Finished(context) => context,
+        };
+        let last_token = context.last_token().map(<[u8]>::to_vec);
+        let mut session = ServerSession::new(context)?;
+        let state = match (mechanism, last_token) {
+            (SaslMechanism::GssSpnego, challenge) => {
+                return Ok(ServerStep::Finished(SaslServerOutcome {
+                    negotiated: implied(session.layers().intersection(config.layers), config.max_receive_size)?,
+                    session,
+                    authzid: None,
+                    challenge,
+                }));
+            }
+            (SaslMechanism::Gssapi, Some(challenge)) => ServerState::SendingLastToken { session, challenge },
+            (SaslMechanism::Gssapi, None) => {
+                let challenge = Self::offer(&config, &mut session)?;
+                ServerState::AwaitingChoice { session, challenge }
+            }
+        };
+        Ok(ServerStep::Continue(Self {
+            mechanism,
+            config,
+            state,
+        }))
+    }
+}
+impl<Usage> SaslServer<Usage> {
+    #[must_use]
+    pub fn mechanism(&self) -> SaslMechanism {
+        self.mechanism
+    }
+    /// The data to send to the client next
+    #[must_use]
+    pub fn challenge(&self) -> &[u8] {
+        match &self.state {
+            ServerState::Handshake(pending) => pending.next_token(),
+            ServerState::SendingLastToken { challenge, .. } | ServerState::AwaitingChoice { challenge, .. } => {
+                challenge
+            }
+        }
+    }
+}
+
+pub struct SaslServerOutcome<Usage> {
+    session: ServerSession<Usage>,
+    negotiated: Negotiated,
+    authzid: Option<String>,
+    challenge: Option<Vec<u8>>,
+}
+impl<Usage> SaslServerOutcome<Usage> {
+    /// Additional data to send along with the successful outcome, if any
+    #[must_use]
+    pub fn challenge(&self) -> Option<&[u8]> {
+        self.challenge.as_deref()
+    }
+    #[must_use]
+    pub fn negotiated(&self) -> Negotiated {
+        self.negotiated
+    }
+    /// The identity the client requested to act as, which the application has to authorize
+    #[must_use]
+    pub fn authzid(&self) -> Option<&str> {
+        self.authzid.as_deref()
+    }
+    pub fn into_session(self) -> ServerSession<Usage> {
+        self.session
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOTH: SecurityLayers = SecurityLayers(SecurityLayers::NONE.0 | SecurityLayers::INTEGRITY.0);
+
+    #[test]
+    fn layer_messages_round_trip() {
+        let message = layer_message(SecurityLayers::ALL, 0x0001_0203);
+        assert_eq!(message, [7, 1, 2, 3]);
+        assert_eq!(parse_layer_message(&message).unwrap(), (7, 0x0001_0203, [].as_slice()));
+
+        let mut with_authzid = layer_message(SecurityLayers::INTEGRITY, 512).to_vec();
+        with_authzid.extend_from_slice(b"admin");
+        assert_eq!(
+            parse_layer_message(&with_authzid).unwrap(),
+            (2, 512, b"admin".as_slice())
+        );
+    }
+
+    #[test]
+    fn layer_message_caps_size_at_three_bytes() {
+        assert_eq!(
+            layer_message(SecurityLayers::INTEGRITY, u32::MAX),
+            [2, 0xff, 0xff, 0xff]
+        );
+    }
+
+    #[test]
+    fn short_layer_message_is_malformed() {
+        assert!(matches!(parse_layer_message(&[7, 0, 0]), Err(SaslError::Malformed)));
+    }
+
+    #[test]
+    fn strongest_prefers_confidentiality() {
+        assert_eq!(SecurityLayers::ALL.strongest(), Some(SecurityLayer::Confidentiality));
+        assert_eq!(BOTH.strongest(), Some(SecurityLayer::Integrity));
+        assert_eq!(SecurityLayers::NONE.strongest(), Some(SecurityLayer::None));
+        assert_eq!(SecurityLayers(0).strongest(), None);
+    }
+
+    #[test]
+    fn intersection_keeps_common_layers() {
+        assert_eq!(SecurityLayers::ALL.intersection(BOTH), BOTH);
+        assert_eq!(SecurityLayers::CONFIDENTIALITY.intersection(BOTH), SecurityLayers(0));
+    }
+
+    #[test]
+    fn client_chooses_strongest_acceptable_layer() {
+        let config = SaslClientConfig {
+            authzid: Some("admin".to_owned()),
+            ..SaslClientConfig::default()
+        };
+        let offer = layer_message(SecurityLayers::ALL, 4096);
+        let (negotiated, reply) = choose_layer(&offer, &config, BOTH).unwrap();
+        assert_eq!(
+            negotiated,
+            Negotiated {
+                layer: SecurityLayer::Integrity,
+                max_send_size: 4096,
+                max_receive_size: 64 * 1024,
+            }
+        );
+        assert_eq!(reply, b"\x02\x01\x00\x00admin");
+    }
+
+    #[test]
+    fn client_without_common_layer_fails() {
+        let config = SaslClientConfig {
+            layers: SecurityLayers::CONFIDENTIALITY,
+            ..SaslClientConfig::default()
+        };
+        let offer = layer_message(BOTH, 4096);
+        assert!(matches!(
+            choose_layer(&offer, &config, SecurityLayers::ALL),
+            Err(SaslError::NoCommonLayer)
+        ));
+    }
+
+    #[test]
+    fn server_without_common_layer_fails() {
+        let config = SaslServerConfig {
+            layers: SecurityLayers::CONFIDENTIALITY,
+            ..SaslServerConfig::default()
+        };
+        assert!(matches!(layer_offer(&config, BOTH), Err(SaslError::NoCommonLayer)));
+    }
+
+    #[test]
+    fn server_offers_no_size_without_wrapping() {
+        let config = SaslServerConfig {
+            layers: SecurityLayers::NONE,
+            ..SaslServerConfig::default()
+        };
+        assert_eq!(layer_offer(&config, SecurityLayers::ALL).unwrap(), [1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn server_reports_no_receive_size_without_wrapping() {
+        let config = SaslServerConfig::default();
+        let reply = layer_message(SecurityLayers::NONE, 0);
+        let (negotiated, authzid) = accept_choice(&reply, &config, SecurityLayers::ALL).unwrap();
+        assert_eq!(negotiated.layer, SecurityLayer::None);
+        assert_eq!(negotiated.max_receive_size, 0);
+        assert_eq!(authzid, None);
+    }
+
+    #[test]
+    fn server_rejects_invalid_choices() {
+        let config = SaslServerConfig::default();
+        let several = layer_message(BOTH, 0);
+        assert!(matches!(
+            accept_choice(&several, &config, SecurityLayers::ALL),
+            Err(SaslError::Malformed)
+        ));
+        let unavailable = layer_message(SecurityLayers::CONFIDENTIALITY, 0);
+        assert!(matches!(
+            accept_choice(&unavailable, &config, BOTH),
+            Err(SaslError::LayerNotOffered)
+        ));
+        let mut invalid_authzid = layer_message(SecurityLayers::INTEGRITY, 0).to_vec();
+        invalid_authzid.extend_from_slice(&[0xff, 0xfe]);
+        assert!(matches!(
+            accept_choice(&invalid_authzid, &config, SecurityLayers::ALL),
+            Err(SaslError::InvalidAuthzid)
+        ));
+    }
+}
@@ -1,10 +1,30 @@
+use std::marker::PhantomData;
+
+use crate::{
+    client::{DelegationState, EncryptionState, SigningState},
+    key::SessionKeyError,
+    sign_encrypt::{
+        ChunkError, Mic, Plaintext, Qop, Signature, UnwrapConfidentialError, UnwrapError, VerifyMicError, WrapError,
+    },
+    split::{Opener, Sealer, Split},
+};
+#[cfg(unix)]
+use crate::{
+    cred::Credentials,
+    key::{PrfError, PrfKey},
+};
 pub use builder::ServerBuilder;
-pub use error::AcceptError;
+pub use error::{AcceptError, ClientNameError};
 use kenobi_core::{
     cred::usage::InboundUsable,
-    typestate::{MaybeDelegation, MaybeEncryption, MaybeSigning},
+    mech::Mechanism,
+    typestate::{
+        Encryption, MaybeDelegation, MaybeEncryption, MaybeSigning, NoEncryption, NoSigning, Signing, Unbound,
+    },
 };
 #[cfg(unix)]
+use kenobi_core::{cred::usage::Outbound, typestate::Bound};
+#[cfg(unix)]
 use kenobi_unix::server::{
     PendingServerContext as UnixPendingContext, ServerContext as UnixContext, StepOut as UnixStepOut,
 };
@@ -17,41 +37,255 @@ use kenobi_windows::server::{
 mod builder;
 mod error;
 
-pub struct ServerContext<Usage> {
+/// A server context that has finished authentication
+///
+/// The final token for the client may be retrieved using `ServerContext::last_token`
+pub struct ServerContext<
+    Usage,
+    S: SigningState = MaybeSigning,
+    E: EncryptionState = MaybeEncryption,
+    D: DelegationState = MaybeDelegation,
+    B = Unbound,
+> {
     #[cfg(windows)]
-    inner: WinContext<Usage, MaybeSigning, MaybeEncryption, MaybeDelegation>,
+    inner: WinContext<Usage, S, E, D>,
     #[cfg(unix)]
-    inner: UnixContext<Usage, MaybeSigning, MaybeEncryption, MaybeDelegation>,
+    inner: UnixContext<Usage, S, E, D>,
+    _channel: PhantomData<B>,
 }
 
-impl<Usage> ServerContext<Usage> {
+impl<Usage, S: SigningState, E: EncryptionState, D: DelegationState, B> ServerContext<Usage, S, E, D, B> {
     #[must_use]
     pub fn last_token(&self) -> Option<&[u8]> {
         self.inner.last_token()
     }
+    #[must_use]
+    pub fn mechanism(&self) -> Mechanism {
+        self.inner.mechanism()
+    }
+}
+
+impl<Usage, E: EncryptionState, D: DelegationState, B> ServerContext<Usage, MaybeSigning, E, D, B> {
+    #[allow(clippy::type_complexity)]
+    pub fn check_signing(
+        self,
+    ) -> Result<ServerContext<Usage, Signing, E, D, B>, ServerContext<Usage, NoSigning, E, D, B>> {
+        self.inner
+            .check_signing()
+            .map(|inner| ServerContext {
+                inner,
+                _channel: PhantomData,
+            })
+            .map_err(|inner| ServerContext {
+                inner,
+                _channel: PhantomData,
+            })
+    }
+}
+impl<Usage, S: SigningState, D: DelegationState, B> ServerContext<Usage, S, MaybeEncryption, D, B> {
+    #[allow(clippy::type_complexity)]
+    pub fn check_encryption(
+        self,
+    ) -> Result<ServerContext<Usage, S, Encryption, D, B>, ServerContext<Usage, S, NoEncryption, D, B>> {
+        self.inner
+            .check_encryption()
+            .map(|inner| ServerContext {
+                inner,
+                _channel: PhantomData,
+            })
+            .map_err(|inner| ServerContext {
+                inner,
+                _channel: PhantomData,
+            })
+    }
+}
+
+impl<Usage, E: EncryptionState, D: DelegationState, B> ServerContext<Usage, Signing, E, D, B> {
+    pub fn sign(&mut self, message: &[u8]) -> Result<Signature, WrapError> {
+        Ok(Signature::from_inner(
+            self.inner.sign(message).map_err(WrapError::from_inner)?,
+        ))
+    }
+    /// Like `sign`, using a mechanism specific quality of protection
+    pub fn sign_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<Signature, WrapError> {
+        Ok(Signature::from_inner(
+            self.inner.sign_with_qop(message, qop).map_err(WrapError::from_inner)?,
+        ))
+    }
+    pub fn unwrap(&mut self, message: &[u8]) -> Result<Plaintext, UnwrapError> {
+        Ok(Plaintext::from_inner(
+            self.inner.unwrap(message).map_err(UnwrapError::from_inner)?,
+        ))
+    }
+    /// Creates a detached signature over `message`, to be sent alongside the message
+    pub fn get_mic(&mut self, message: &[u8]) -> Result<Mic, WrapError> {
+        Ok(Mic::from_inner(
+            self.inner.get_mic(message).map_err(WrapError::from_inner)?,
+        ))
+    }
+    /// Like `get_mic`, using a mechanism specific quality of protection
+    pub fn get_mic_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<Mic, WrapError> {
+        Ok(Mic::from_inner(
+            self.inner
+                .get_mic_with_qop(message, qop)
+                .map_err(WrapError::from_inner)?,
+        ))
+    }
+    /// Returns the QOP the signature was created with
+    pub fn verify_mic(&mut self, message: &[u8], mic: &[u8]) -> Result<Qop, VerifyMicError> {
+        self.inner.verify_mic(message, mic).map_err(VerifyMicError::from_inner)
+    }
+    /// Splits the context into halves for sending and receiving, which may be used from different threads
+    ///
+    /// The last token still has to be sent to the peer, it is handed back with the halves
+    ///
+    /// Delegated credentials have to be taken with `take_delegated_credentials` before splitting
+    pub fn split(self) -> Split<E, B> {
+        let last_token = self.last_token().map(<[u8]>::to_vec);
+        let (sealer, opener) = self.inner.split();
+        Split {
+            sealer: Sealer::from_inner(sealer),
+            opener: Opener::from_inner(opener),
+            last_token,
+        }
+    }
+    /// Largest message whose wrapped token is at most `max_token_len` bytes long
+    pub fn max_plaintext_len(&mut self, max_token_len: usize, encrypt: bool) -> Result<usize, WrapError> {
+        self.inner
+            .max_plaintext_len(max_token_len, encrypt)
+            .map_err(WrapError::from_inner)
+    }
+    /// Number of bytes wrapping adds to a message of `data_len` bytes
+    ///
+    /// On Windows, this is an upper bound independent of the message
+    pub fn wrap_overhead(&mut self, data_len: usize, encrypt: bool) -> Result<usize, WrapError> {
+        self.inner
+            .wrap_overhead(data_len, encrypt)
+            .map_err(WrapError::from_inner)
+    }
+    /// Like `max_plaintext_len`, for messages wrapped with the given quality of protection
+    pub fn max_plaintext_len_with_qop(
+        &mut self,
+        max_token_len: usize,
+        encrypt: bool,
+        qop: Qop,
+    ) -> Result<usize, WrapError> {
+        self.inner
+            .max_plaintext_len_with_qop(max_token_len, encrypt, qop)
+            .map_err(WrapError::from_inner)
+    }
+    /// Like `wrap_overhead`, for messages wrapped with the given quality of protection
+    pub fn wrap_overhead_with_qop(&mut self, data_len: usize, encrypt: bool, qop: Qop) -> Result<usize, WrapError> {
+        self.inner
+            .wrap_overhead_with_qop(data_len, encrypt, qop)
+            .map_err(WrapError::from_inner)
+    }
+    /// Splits `payload` into signed tokens of at most `max_token_len` bytes each
+    pub fn sign_chunks(&mut self, payload: &[u8], max_token_len: usize) -> Result<Vec<Signature>, ChunkError> {
+        let chunk_len = ChunkError::check_len(self.max_plaintext_len(max_token_len, false)?, max_token_len)?;
+        payload.chunks(chunk_len).map(|chunk| Ok(self.sign(chunk)?)).collect()
+    }
+}
+impl<Usage, D: DelegationState, B> ServerContext<Usage, Signing, Encryption, D, B> {
+    pub fn encrypt(
+        &mut self,
+        message: &[u8],
+    ) -> Result<impl std::ops::Deref<Target = [u8]> + use<Usage, D, B>, WrapError> {
+        self.inner.encrypt(message).map_err(WrapError::from_inner)
+    }
+    /// Like `encrypt`, using a mechanism specific quality of protection
+    pub fn encrypt_with_qop(
+        &mut self,
+        message: &[u8],
+        qop: Qop,
+    ) -> Result<impl std::ops::Deref<Target = [u8]> + use<Usage, D, B>, WrapError> {
+        self.inner.encrypt_with_qop(message, qop).map_err(WrapError::from_inner)
+    }
+    /// Like `unwrap`, but rejects messages that were only signed instead of encrypted
+    pub fn unwrap_confidential(&mut self, message: &[u8]) -> Result<Plaintext, UnwrapConfidentialError> {
+        let plaintext = self.unwrap(message)?;
+        if !plaintext.was_encrypted() {
+            return Err(UnwrapConfidentialError::NotEncrypted);
+        }
+        Ok(plaintext)
+    }
+    /// Splits `payload` into encrypted tokens of at most `max_token_len` bytes each
+    pub fn encrypt_chunks(
+        &mut self,
+        payload: &[u8],
+        max_token_len: usize,
+    ) -> Result<Vec<impl std::ops::Deref<Target = [u8]> + use<Usage, D, B>>, ChunkError> {
+        let chunk_len = ChunkError::check_len(self.max_plaintext_len(max_token_len, true)?, max_token_len)?;
+        payload
+            .chunks(chunk_len)
+            .map(|chunk| Ok(self.encrypt(chunk)?))
+            .collect()
+    }
 }
 
 #[cfg(unix)]
-impl<Usage> ServerContext<Usage> {
-    pub fn client_name(&mut self) -> impl std::fmt::Display + Send + Sync {
-        self.inner.client_name().unwrap()
+impl<Usage, S: SigningState, E: EncryptionState, D: DelegationState, B> ServerContext<Usage, S, E, D, B> {
+    pub fn client_name(&mut self) -> Result<impl std::fmt::Display + Send + Sync, ClientNameError> {
+        self.inner.client_name().map_err(ClientNameError::from_inner)
+    }
+    /// Takes the credentials the client delegated, to act on its behalf
+    ///
+    /// Only available with GSSAPI, as SSPI delegates by impersonating the client instead
+    pub fn take_delegated_credentials(&mut self) -> Option<Credentials<Outbound>> {
+        self.inner.take_delegated_credentials().map(Credentials::from_unix)
+    }
+    /// Whether the client bound the context to the same channel
+    ///
+    /// Only available with GSSAPI, and always `false` before MIT krb5 1.19
+    #[must_use]
+    pub fn channel_bound(&self) -> bool {
+        self.inner.channel_bound()
+    }
+    pub fn session_key(&self) -> Result<impl std::ops::Deref<Target = [u8]> + use<Usage, S, E, D, B>, SessionKeyError> {
+        self.inner.session_key().map_err(SessionKeyError::from_inner)
+    }
+    /// Derives `output_len` bytes from `input` and the keys of the context, identically on both sides (RFC 4401)
+    ///
+    /// Only available with GSSAPI, as SSPI has no equivalent
+    pub fn prf(&self, key: PrfKey, input: &[u8], output_len: usize) -> Result<Vec<u8>, PrfError> {
+        self.inner.prf(key, input, output_len).map_err(PrfError::from_inner)
+    }
+}
+#[cfg(unix)]
+impl<Usage, S: SigningState, E: EncryptionState, D: DelegationState> ServerContext<Usage, S, E, D, Unbound> {
+    /// Checks whether the client bound the context to the same channel
+    ///
+    /// Only available with GSSAPI, and never succeeds before MIT krb5 1.19
+    #[allow(clippy::type_complexity)]
+    pub fn check_channel_bound(self) -> Result<ServerContext<Usage, S, E, D, Bound>, Self> {
+        if !self.inner.channel_bound() {
+            return Err(self);
+        }
+        Ok(ServerContext {
+            inner: self.inner,
+            _channel: PhantomData,
+        })
     }
 }
 #[cfg(windows)]
-impl<Usage> ServerContext<Usage> {
-    pub fn client_name(&mut self) -> impl std::fmt::Display + Send + Sync {
-        self.inner.client_native_name().unwrap()
+impl<Usage, S: SigningState, E: EncryptionState, D: DelegationState, B> ServerContext<Usage, S, E, D, B> {
+    pub fn client_name(&mut self) -> Result<impl std::fmt::Display + Send + Sync, ClientNameError> {
+        self.inner.client_native_name().map_err(ClientNameError::from_inner)
+    }
+    pub fn session_key(&self) -> Result<impl std::ops::Deref<Target = [u8]> + use<Usage, S, E, D, B>, SessionKeyError> {
+        self.inner.get_session_key().map_err(SessionKeyError::from_inner)
     }
 }
 
-pub struct PendingServerContext<Usage> {
+pub struct PendingServerContext<Usage, B = Unbound> {
     #[cfg(windows)]
     inner: WinPendingContext<Usage>,
     #[cfg(unix)]
     inner: UnixPendingContext<Usage>,
+    _channel: PhantomData<B>,
 }
 
-impl<Usage> PendingServerContext<Usage> {
+impl<Usage, B> PendingServerContext<Usage, B> {
     #[must_use]
     pub fn next_token(&self) -> &[u8] {
         self.inner.next_token()
@@ -59,41 +293,66 @@ impl<Usage> PendingServerContext<Usage> {
 }
 
 #[cfg(windows)]
-impl<Usage: InboundUsable> PendingServerContext<Usage> {
-    pub fn step(self, token: &[u8]) -> Result<StepOut<Usage>, AcceptError> {
+impl<Usage: InboundUsable, B> PendingServerContext<Usage, B> {
+    pub fn step(self, token: &[u8]) -> Result<StepOut<Usage, B>, AcceptError> {
         match self.inner.step(token)? {
-            WinStepOut::Pending(inner) => Ok(StepOut::Pending(PendingServerContext { inner })),
-            WinStepOut::Completed(inner) => Ok(StepOut::Finished(ServerContext { inner })),
+            WinStepOut::Pending(inner) => Ok(StepOut::Pending(PendingServerContext {
+                inner,
+                _channel: PhantomData,
+            })),
+            WinStepOut::Completed(inner) => Ok(StepOut::Finished(ServerContext {
+                inner,
+                _channel: PhantomData,
+            })),
         }
     }
 }
 #[cfg(unix)]
-impl<Usage: InboundUsable> PendingServerContext<Usage> {
-    pub fn step(self, token: &[u8]) -> Result<StepOut<Usage>, AcceptError> {
+impl<Usage: InboundUsable, B> PendingServerContext<Usage, B> {
+    pub fn step(self, token: &[u8]) -> Result<StepOut<Usage, B>, AcceptError> {
         match self.inner.step(token)? {
-            UnixStepOut::Pending(inner) => Ok(StepOut::Pending(PendingServerContext { inner })),
-            UnixStepOut::Finished(inner) => Ok(StepOut::Finished(ServerContext { inner })),
+            UnixStepOut::Pending(inner) => Ok(StepOut::Pending(PendingServerContext {
+                inner,
+                _channel: PhantomData,
+            })),
+            UnixStepOut::Finished(inner) => Ok(StepOut::Finished(ServerContext {
+                inner,
+                _channel: PhantomData,
+            })),
         }
     }
 }
 
-pub enum StepOut<Usage> {
-    Pending(PendingServerContext<Usage>),
-    Finished(ServerContext<Usage>),
+/// `B` tells whether the context is bound to a channel
+pub enum StepOut<Usage, B = Unbound> {
+    Pending(PendingServerContext<Usage, B>),
+    Finished(ServerContext<Usage, MaybeSigning, MaybeEncryption, MaybeDelegation, B>),
 }
-impl<Usage> StepOut<Usage> {
+impl<Usage, B> StepOut<Usage, B> {
     #[cfg(windows)]
-    fn from_windows(win: WinStepOut<Usage>) -> StepOut<Usage> {
+    fn from_windows(win: WinStepOut<Usage>) -> StepOut<Usage, B> {
         match win {
-            WinStepOut::Completed(inner) => Self::Finished(ServerContext { inner }),
-            WinStepOut::Pending(inner) => Self::Pending(PendingServerContext { inner }),
+            WinStepOut::Completed(inner) => Self::Finished(ServerContext {
+                inner,
+                _channel: PhantomData,
+            }),
+            WinStepOut::Pending(inner) => Self::Pending(PendingServerContext {
+                inner,
+                _channel: PhantomData,
+            }),
         }
     }
     #[cfg(unix)]
-    fn from_unix(unix: UnixStepOut<Usage>) -> StepOut<Usage> {
+    fn from_unix(unix: UnixStepOut<Usage>) -> StepOut<Usage, B> {
         match unix {
-            UnixStepOut::Pending(inner) => Self::Pending(PendingServerContext { inner }),
-            UnixStepOut::Finished(inner) => Self::Finished(ServerContext { inner }),
+            UnixStepOut::Pending(inner) => Self::Pending(PendingServerContext {
+                inner,
+                _channel: PhantomData,
+            }),
+            UnixStepOut::Finished(inner) => Self::Finished(ServerContext {
+                inner,
+                _channel: PhantomData,
+            }),
         }
     }
 }
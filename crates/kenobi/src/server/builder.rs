@@ -1,34 +1,137 @@
-use kenobi_core::{channel_bindings::Channel, cred::usage::InboundUsable};
+#[cfg(unix)]
+use kenobi_core::channel_bindings::{Address, ChannelBindingPolicy};
+use std::marker::PhantomData;
+
+use kenobi_core::{
+    channel_bindings::{BindError, Channel, ChannelBindingType},
+    cred::usage::InboundUsable,
+    mech::Mechanism,
+    typestate::{Bound, Unbound},
+};
 
 use crate::{
+    channel_bindings::Precomputed,
     cred::Credentials,
     server::{AcceptError, StepOut},
 };
 
+/// `B` tells whether the context will be bound to a channel
+///
+/// Binding only makes the builder `Bound` once clients are required to bind, see `require_channel_binding`
 #[derive(Debug)]
-pub struct ServerBuilder<Usage> {
+pub struct ServerBuilder<Usage, B = Unbound> {
     #[cfg(windows)]
     inner: kenobi_windows::server::ServerBuilder<Usage>,
     #[cfg(unix)]
     inner: kenobi_unix::server::ServerBuilder<Usage>,
+    _channel: PhantomData<B>,
+}
+impl<Usage, B> ServerBuilder<Usage, B> {
+    /// Binds the context to `channel`, clients that do not bind are still accepted by default
+    ///
+    /// # Errors
+    /// Fails if the channel fails or offers no `tls-server-end-point` bindings
+    pub fn bind_to_channel<C: Channel>(self, channel: &C) -> Result<Self, BindError<C::Error>> {
+        self.bind(channel.channel_bindings(), ChannelBindingType::TlsServerEndPoint)
+    }
+    /// Like `bind_to_channel`, using channel bindings of the given type
+    ///
+    /// # Errors
+    /// Fails if the channel fails or offers no bindings of this type
+    pub fn bind_to_channel_as<C: Channel>(
+        self,
+        channel: &C,
+        kind: ChannelBindingType,
+    ) -> Result<Self, BindError<C::Error>> {
+        self.bind(channel.channel_bindings_of(kind), kind)
+    }
+    fn bind<E>(self, bindings: Result<Option<Vec<u8>>, E>, kind: ChannelBindingType) -> Result<Self, BindError<E>> {
+        let bindings = bindings
+            .map_err(BindError::Channel)?
+            .ok_or(BindError::Unavailable(kind))?;
+        let Ok(inner) = self.inner.bind_to_channel_as(&Precomputed(bindings), kind);
+        Ok(ServerBuilder {
+            inner,
+            _channel: PhantomData,
+        })
+    }
 }
+#[cfg(unix)]
+impl<Usage, B> ServerBuilder<Usage, B> {
+    /// Binds the context to the network addresses of the initiator and the acceptor, which the peer has to match
+    ///
+    /// Only available with GSSAPI
+    #[must_use]
+    pub fn bind_to_addresses(self, initiator: Option<Address>, acceptor: Option<Address>) -> Self {
+        let inner = self.inner.bind_to_addresses(initiator, acceptor);
+        Self {
+            inner,
+            _channel: PhantomData,
+        }
+    }
+}
+#[cfg(unix)]
 impl<Usage> ServerBuilder<Usage> {
-    pub fn bind_to_channel(self, channel: &impl Channel) -> Result<Self, impl std::error::Error> {
-        match self.inner.bind_to_channel(channel) {
-            Ok(inner) => Ok(Self { inner }),
-            Err(e) => Err(e),
+    /// Decides whether clients have to bind to the channel, `IfPresent` by default
+    ///
+    /// `Required` needs MIT krb5 1.19 or newer, older libraries reject every client.
+    /// Only available with GSSAPI, as SSPI does not report whether the client bound
+    #[must_use]
+    pub fn with_channel_binding_policy(self, policy: ChannelBindingPolicy) -> Self {
+        let inner = self.inner.with_channel_binding_policy(policy);
+        Self {
+            inner,
+            _channel: PhantomData,
         }
     }
+    /// Binds the context to `channel` and rejects clients that did not bind to it, which makes the finished context `Bound`
+    ///
+    /// Like the `Required` policy, this needs MIT krb5 1.19 or newer. Only available with GSSAPI
+    ///
+    /// # Errors
+    /// Fails if the channel fails or offers no `tls-server-end-point` bindings
+    pub fn require_channel_binding<C: Channel>(
+        self,
+        channel: &C,
+    ) -> Result<ServerBuilder<Usage, Bound>, BindError<C::Error>> {
+        self.require_channel_binding_as(channel, ChannelBindingType::TlsServerEndPoint)
+    }
+    /// Like `require_channel_binding`, using channel bindings of the given type
+    ///
+    /// # Errors
+    /// Fails if the channel fails or offers no bindings of this type
+    pub fn require_channel_binding_as<C: Channel>(
+        self,
+        channel: &C,
+        kind: ChannelBindingType,
+    ) -> Result<ServerBuilder<Usage, Bound>, BindError<C::Error>> {
+        let bound = self.bind_to_channel_as(channel, kind)?;
+        let inner = bound.inner.with_channel_binding_policy(ChannelBindingPolicy::Required);
+        Ok(ServerBuilder {
+            inner,
+            _channel: PhantomData,
+        })
+    }
+}
+impl<Usage, B> ServerBuilder<Usage, B> {
+    /// The mechanism of the credentials, which the context is established with
+    #[must_use]
+    pub fn mechanism(&self) -> Mechanism {
+        self.inner.mechanism()
+    }
 }
 #[cfg(windows)]
-impl<Usage> ServerBuilder<Usage> {
+impl<Usage, B> ServerBuilder<Usage, B> {
     pub fn with_mutual_auth(self) -> Self {
         let inner = self.inner.offer_mutual_auth();
-        Self { inner }
+        Self {
+            inner,
+            _channel: PhantomData,
+        }
     }
 }
 #[cfg(unix)]
-impl<Usage> ServerBuilder<Usage> {
+impl<Usage, B> ServerBuilder<Usage, B> {
     pub fn with_mutual_auth(self) -> Self {
         self
     }
@@ -39,9 +142,15 @@ impl<Usage: InboundUsable> ServerBuilder<Usage> {
     #[must_use]
     pub fn new_from_credentials(cred: Credentials<Usage>) -> Self {
         let inner = kenobi_windows::server::ServerBuilder::new_from_credentials(cred.inner);
-        ServerBuilder { inner }
+        ServerBuilder {
+            inner,
+            _channel: PhantomData,
+        }
     }
-    pub fn initialize(self, token: &[u8]) -> Result<StepOut<Usage>, AcceptError> {
+}
+#[cfg(windows)]
+impl<Usage: InboundUsable, B> ServerBuilder<Usage, B> {
+    pub fn initialize(self, token: &[u8]) -> Result<StepOut<Usage, B>, AcceptError> {
         self.inner
             .initialize(token)
             .map(StepOut::from_windows)
@@ -53,9 +162,15 @@ impl<Usage: InboundUsable> ServerBuilder<Usage> {
     #[must_use]
     pub fn new_from_credentials(cred: Credentials<Usage>) -> Self {
         let inner = kenobi_unix::server::ServerBuilder::new(cred.inner);
-        ServerBuilder { inner }
+        ServerBuilder {
+            inner,
+            _channel: PhantomData,
+        }
     }
-    pub fn initialize(self, token: &[u8]) -> Result<StepOut<Usage>, AcceptError> {
+}
+#[cfg(unix)]
+impl<Usage: InboundUsable, B> ServerBuilder<Usage, B> {
+    pub fn initialize(self, token: &[u8]) -> Result<StepOut<Usage, B>, AcceptError> {
         self.inner
             .initialize(token)
             .map_err(AcceptError::from)
@@ -15,6 +15,24 @@ pub enum AcceptError {
     OldToken,
     Unknown,
 }
+impl std::error::Error for AcceptError {}
+impl std::fmt::Display for AcceptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::BadChannelBindings => "the channel bindings do not match",
+            Self::BadSignature => "a token has an invalid signature",
+            Self::CredentialsExpired => "the credentials have expired",
+            Self::DefectiveToken => "a token is defective",
+            Self::DuplicateToken => "a token was replayed",
+            Self::Failure => "the context could not be accepted",
+            Self::InvalidCredentials => "the credentials are invalid",
+            Self::InvalidContext => "the context is invalid",
+            Self::NoCredentials => "no credentials are available",
+            Self::OldToken => "a token is too old",
+            Self::Unknown => "unknown failure",
+        })
+    }
+}
 
 #[cfg(unix)]
 impl From<kenobi_unix::Error> for AcceptError {
@@ -36,7 +54,7 @@ impl From<kenobi_unix::Error> for AcceptError {
                     Kind::OldToken => Self::OldToken,
                 },
             },
-            _ => todo!(),
+            Error::Mechanism(_) => Self::Unknown,
         }
     }
 }
@@ -56,3 +74,32 @@ impl From<AcceptContextError> for AcceptError {
         }
     }
 }
+
+/// Failure to query the name of the client of an established context
+#[derive(Debug)]
+pub struct ClientNameError {
+    #[cfg(windows)]
+    inner: windows_result::Error,
+    #[cfg(unix)]
+    inner: kenobi_unix::Error,
+}
+impl ClientNameError {
+    #[cfg(windows)]
+    pub(crate) fn from_inner(inner: windows_result::Error) -> Self {
+        Self { inner }
+    }
+    #[cfg(unix)]
+    pub(crate) fn from_inner(inner: kenobi_unix::Error) -> Self {
+        Self { inner }
+    }
+}
+impl std::error::Error for ClientNameError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.inner)
+    }
+}
+impl std::fmt::Display for ClientNameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "could not query the client name: {}", self.inner)
+    }
+}
@@ -1,5 +1,7 @@
 use std::fmt::Display;
 
+pub use kenobi_core::qop::Qop;
+
 pub struct Signature {
     #[cfg(windows)]
     pub(crate) win: kenobi_windows::sign_encrypt::Signature,
@@ -35,6 +37,54 @@ impl std::ops::Deref for Signature {
     }
 }
 
+/// A message that has been verified and, if it was encrypted, decrypted
+pub struct Plaintext {
+    #[cfg(windows)]
+    pub(crate) win: kenobi_windows::sign_encrypt::Plaintext,
+    #[cfg(unix)]
+    pub(crate) unix: kenobi_unix::sign_encrypt::Plaintext,
+}
+#[cfg(windows)]
+impl Plaintext {
+    pub(crate) fn from_inner(win: kenobi_windows::sign_encrypt::Plaintext) -> Self {
+        Self { win }
+    }
+    pub fn as_slice(&self) -> &[u8] {
+        &self.win
+    }
+    /// Whether the message was protected with confidentiality, rather than only integrity
+    pub fn was_encrypted(&self) -> bool {
+        self.win.was_encrypted()
+    }
+    /// The QOP the message was wrapped with
+    pub fn qop(&self) -> Qop {
+        self.win.qop()
+    }
+}
+#[cfg(unix)]
+impl Plaintext {
+    pub(crate) fn from_inner(unix: kenobi_unix::sign_encrypt::Plaintext) -> Self {
+        Self { unix }
+    }
+    pub fn as_slice(&self) -> &[u8] {
+        self.unix.as_slice()
+    }
+    /// Whether the message was protected with confidentiality, rather than only integrity
+    pub fn was_encrypted(&self) -> bool {
+        self.unix.was_encrypted()
+    }
+    /// The QOP the message was wrapped with
+    pub fn qop(&self) -> Qop {
+        self.unix.qop()
+    }
+}
+impl std::ops::Deref for Plaintext {
+    type Target = [u8];
+    fn deref(&self) -> &Self::Target {
+        self.as_slice()
+    }
+}
+
 #[derive(Debug)]
 pub struct WrapError {
     #[cfg(windows)]
@@ -90,3 +140,164 @@ impl Display for UnwrapError {
         self.inner.fmt(f)
     }
 }
+
+#[derive(Debug)]
+pub enum UnwrapConfidentialError {
+    /// The message was valid, but only protected with integrity
+    NotEncrypted,
+    Unwrap(UnwrapError),
+}
+impl From<UnwrapError> for UnwrapConfidentialError {
+    fn from(value: UnwrapError) -> Self {
+        Self::Unwrap(value)
+    }
+}
+impl std::error::Error for UnwrapConfidentialError {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        match self {
+            Self::NotEncrypted => None,
+            Self::Unwrap(e) => Some(e),
+        }
+    }
+}
+impl Display for UnwrapConfidentialError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotEncrypted => write!(f, "the message was not encrypted"),
+            Self::Unwrap(e) => e.fmt(f),
+        }
+    }
+}
+
+/// A detached signature over a message, to be sent alongside the message
+pub struct Mic {
+    #[cfg(windows)]
+    pub(crate) win: kenobi_windows::sign_encrypt::Mic,
+    #[cfg(unix)]
+    pub(crate) unix: kenobi_unix::sign_encrypt::Mic,
+}
+#[cfg(windows)]
+impl Mic {
+    pub(crate) fn from_inner(win: kenobi_windows::sign_encrypt::Mic) -> Self {
+        Self { win }
+    }
+    pub fn as_slice(&self) -> &[u8] {
+        &self.win
+    }
+}
+#[cfg(unix)]
+impl Mic {
+    pub(crate) fn from_inner(unix: kenobi_unix::sign_encrypt::Mic) -> Self {
+        Self { unix }
+    }
+    pub fn as_slice(&self) -> &[u8] {
+        self.unix.as_slice()
+    }
+}
+impl std::ops::Deref for Mic {
+    type Target = [u8];
+    fn deref(&self) -> &Self::Target {
+        self.as_slice()
+    }
+}
+
+#[derive(Debug)]
+pub enum VerifyMicError {
+    /// The message or its signature have been altered
+    BadSignature,
+    /// The signature has already been verified before
+    DuplicateToken,
+    /// The signature is too old to be checked for duplication
+    OldToken,
+    /// A later signature has already been verified, or an earlier one is missing
+    OutOfSequence,
+    Other(WrapError),
+}
+#[cfg(windows)]
+impl VerifyMicError {
+    pub(crate) fn from_inner(inner: kenobi_windows::sign_encrypt::MicError) -> Self {
+        use kenobi_windows::sign_encrypt::MicError;
+        match inner {
+            MicError::Altered => Self::BadSignature,
+            MicError::OutOfSequence => Self::OutOfSequence,
+            MicError::Other(e) => Self::Other(WrapError::from_inner(e)),
+        }
+    }
+}
+#[cfg(unix)]
+impl VerifyMicError {
+    pub(crate) fn from_inner(inner: kenobi_unix::Error) -> Self {
+        use kenobi_unix::{Error, error::GssVerifyErrorKind as Kind};
+        match inner {
+            Error::Gss(gss) => match gss.kind_verify() {
+                Some(Kind::BadSignature | Kind::DefectiveToken) => Self::BadSignature,
+                Some(Kind::DuplicateToken) => Self::DuplicateToken,
+                Some(Kind::OldToken) => Self::OldToken,
+                Some(Kind::UnsequencedToken | Kind::GapToken) => Self::OutOfSequence,
+                Some(Kind::ContextExpired | Kind::NoContext | Kind::Failure) | None => {
+                    Self::Other(WrapError::from_inner(inner))
+                }
+            },
+            Error::Mechanism(_) => Self::Other(WrapError::from_inner(inner)),
+        }
+    }
+}
+impl std::error::Error for VerifyMicError {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        match self {
+            Self::Other(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl Display for VerifyMicError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadSignature => write!(f, "the message or its signature have been altered"),
+            Self::DuplicateToken => write!(f, "the signature has already been verified before"),
+            Self::OldToken => write!(f, "the signature is too old to be checked for duplication"),
+            Self::OutOfSequence => write!(f, "the signature was received out of sequence"),
+            Self::Other(e) => e.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ChunkError {
+    /// Not even a single byte of plaintext fits into a token of this length
+    LimitTooSmall {
+        max_token_len: usize,
+    },
+    Wrap(WrapError),
+}
+impl ChunkError {
+    pub(crate) fn check_len(max_plaintext_len: usize, max_token_len: usize) -> Result<usize, Self> {
+        if max_plaintext_len == 0 {
+            return Err(Self::LimitTooSmall { max_token_len });
+        }
+        Ok(max_plaintext_len)
+    }
+}
+impl From<WrapError> for ChunkError {
+    fn from(value: WrapError) -> Self {
+        Self::Wrap(value)
+    }
+}
+impl std::error::Error for ChunkError {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        match self {
+            Self::Wrap(e) => Some(e),
+            Self::LimitTooSmall { .. } => None,
+        }
+    }
+}
+impl Display for ChunkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::LimitTooSmall { max_token_len } => {
+                write!(f, "no plaintext fits into a token of {max_token_len} bytes")
+            }
+            Self::Wrap(e) => e.fmt(f),
+        }
+    }
+}
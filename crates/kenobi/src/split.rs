@@ -0,0 +1,152 @@
+//! Independent halves of a finished context, for sending and receiving from different threads
+//!
+//! Both halves share the underlying context, and take turns on it for the duration of each call
+
+use std::marker::PhantomData;
+
+use kenobi_core::typestate::{Encryption, Unbound};
+
+use crate::sign_encrypt::{
+    Mic, Plaintext, Qop, Signature, UnwrapConfidentialError, UnwrapError, VerifyMicError, WrapError,
+};
+
+/// The halves of a split context, `B` tells whether it was bound to a channel
+#[must_use]
+pub struct Split<E, B = Unbound> {
+    pub sealer: Sealer<E, B>,
+    pub opener: Opener<E, B>,
+    /// The final token of the authentication the context had not sent yet, e.g. the mutual authentication reply
+    pub last_token: Option<Vec<u8>>,
+}
+
+/// The sending half of a split context
+pub struct Sealer<E, B = Unbound> {
+    #[cfg(windows)]
+    inner: kenobi_windows::split::Sealer<E>,
+    #[cfg(unix)]
+    inner: kenobi_unix::split::Sealer<E>,
+    _channel: PhantomData<B>,
+}
+#[cfg(windows)]
+impl<E, B> Sealer<E, B> {
+    pub(crate) fn from_inner(inner: kenobi_windows::split::Sealer<E>) -> Self {
+        Self {
+            inner,
+            _channel: PhantomData,
+        }
+    }
+}
+#[cfg(unix)]
+impl<E, B> Sealer<E, B> {
+    pub(crate) fn from_inner(inner: kenobi_unix::split::Sealer<E>) -> Self {
+        Self {
+            inner,
+            _channel: PhantomData,
+        }
+    }
+}
+impl<E, B> Sealer<E, B> {
+    pub fn sign(&mut self, message: &[u8]) -> Result<Signature, WrapError> {
+        Ok(Signature::from_inner(
+            self.inner.sign(message).map_err(WrapError::from_inner)?,
+        ))
+    }
+    /// Like `sign`, using a mechanism specific quality of protection
+    pub fn sign_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<Signature, WrapError> {
+        Ok(Signature::from_inner(
+            self.inner.sign_with_qop(message, qop).map_err(WrapError::from_inner)?,
+        ))
+    }
+    /// Creates a detached signature over `message`, to be sent alongside the message
+    pub fn get_mic(&mut self, message: &[u8]) -> Result<Mic, WrapError> {
+        Ok(Mic::from_inner(
+            self.inner.get_mic(message).map_err(WrapError::from_inner)?,
+        ))
+    }
+    /// Like `get_mic`, using a mechanism specific quality of protection
+    pub fn get_mic_with_qop(&mut self, message: &[u8], qop: Qop) -> Result<Mic, WrapError> {
+        Ok(Mic::from_inner(
+            self.inner
+                .get_mic_with_qop(message, qop)
+                .map_err(WrapError::from_inner)?,
+        ))
+    }
+    /// Largest message whose wrapped token is at most `max_token_len` bytes long
+    pub fn max_plaintext_len(&mut self, max_token_len: usize, encrypt: bool) -> Result<usize, WrapError> {
+        self.inner
+            .max_plaintext_len(max_token_len, encrypt)
+            .map_err(WrapError::from_inner)
+    }
+    /// Like `max_plaintext_len`, for messages wrapped with the given quality of protection
+    pub fn max_plaintext_len_with_qop(
+        &mut self,
+        max_token_len: usize,
+        encrypt: bool,
+        qop: Qop,
+    ) -> Result<usize, WrapError> {
+        self.inner
+            .max_plaintext_len_with_qop(max_token_len, encrypt, qop)
+            .map_err(WrapError::from_inner)
+    }
+}
+impl<B> Sealer<Encryption, B> {
+    pub fn encrypt(&mut self, message: &[u8]) -> Result<impl std::ops::Deref<Target = [u8]> + use<B>, WrapError> {
+        self.inner.encrypt(message).map_err(WrapError::from_inner)
+    }
+    /// Like `encrypt`, using a mechanism specific quality of protection
+    pub fn encrypt_with_qop(
+        &mut self,
+        message: &[u8],
+        qop: Qop,
+    ) -> Result<impl std::ops::Deref<Target = [u8]> + use<B>, WrapError> {
+        self.inner.encrypt_with_qop(message, qop).map_err(WrapError::from_inner)
+    }
+}
+
+/// The receiving half of a split context
+pub struct Opener<E, B = Unbound> {
+    #[cfg(windows)]
+    inner: kenobi_windows::split::Opener<E>,
+    #[cfg(unix)]
+    inner: kenobi_unix::split::Opener<E>,
+    _channel: PhantomData<B>,
+}
+#[cfg(windows)]
+impl<E, B> Opener<E, B> {
+    pub(crate) fn from_inner(inner: kenobi_windows::split::Opener<E>) -> Self {
+        Self {
+            inner,
+            _channel: PhantomData,
+        }
+    }
+}
+#[cfg(unix)]
+impl<E, B> Opener<E, B> {
+    pub(crate) fn from_inner(inner: kenobi_unix::split::Opener<E>) -> Self {
+        Self {
+            inner,
+            _channel: PhantomData,
+        }
+    }
+}
+impl<E, B> Opener<E, B> {
+    pub fn unwrap(&mut self, message: &[u8]) -> Result<Plaintext, UnwrapError> {
+        Ok(Plaintext::from_inner(
+            self.inner.unwrap(message).map_err(UnwrapError::from_inner)?,
+        ))
+    }
+    /// Returns the QOP the signature was created with
+    pub fn verify_mic(&mut self, message: &[u8], mic: &[u8]) -> Result<Qop, VerifyMicError> {
+        self.inner.verify_mic(message, mic).map_err(VerifyMicError::from_inner)
+    }
+}
+impl<B> Opener<Encryption, B> {
+    /// Like `unwrap`, but rejects messages that were only signed instead of encrypted
+    pub fn unwrap_confidential(&mut self, message: &[u8]) -> Result<Plaintext, UnwrapConfidentialError> {
+        let plaintext = self.unwrap(message)?;
+        if !plaintext.was_encrypted() {
+            return Err(UnwrapConfidentialError::NotEncrypted);
+        }
+        Ok(plaintext)
+    }
+}
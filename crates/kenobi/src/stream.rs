@@ -0,0 +1,351 @@
+//! Byte streams protected by a finished context
+//!
+//! Every write is wrapped into a single token and framed with its length as a 4 byte big endian prefix,
+//! like [`LengthPrefixed`](crate::handshake::LengthPrefixed) does. Contexts allowing encryption encrypt the data,
+//! contexts only allowing signing sign it.
+
+use std::io::{self, Read, Write};
+
+use kenobi_core::typestate::{Encryption, NoEncryption, Signing};
+
+use crate::{
+    client::{ClientContext, DelegationState},
+    handshake::Limits,
+    server::ServerContext,
+    sign_encrypt::{UnwrapConfidentialError, WrapError},
+};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// A finished context able to protect the data of a [`SecureStream`]
+///
+/// Implemented for client and server contexts that are known to allow signing
+pub trait StreamContext: sealed::Sealed {
+    /// Largest message whose wrapped token is at most `max_token_len` bytes long
+    fn max_plaintext_len(&mut self, max_token_len: usize) -> Result<usize, WrapError>;
+    fn wrap(&mut self, message: &[u8]) -> Result<Vec<u8>, WrapError>;
+    /// Contexts allowing encryption reject messages that were only signed
+    fn unwrap(&mut self, token: &[u8]) -> Result<Vec<u8>, UnwrapConfidentialError>;
+}
+
+macro_rules! impl_stream_context {
+    ($context:ident, $encryption:ty, $encrypt:literal, $wrap:ident, $unwrap:ident) => {
+        impl<Usage, D: DelegationState> sealed::Sealed for $context<Usage, Signing, $encryption, D> {}
+        impl<Usage, D: DelegationState> StreamContext for $context<Usage, Signing, $encryption, D> {
+            fn max_plaintext_len(&mut self, max_token_len: usize) -> Result<usize, WrapError> {
+                $context::max_plaintext_len(self, max_token_len, $encrypt)
+            }
+            fn wrap(&mut self, message: &[u8]) -> Result<Vec<u8>, WrapError> {
+                Ok(self.$wrap(message)?.to_vec())
+            }
+            fn unwrap(&mut self, token: &[u8]) -> Result<Vec<u8>, UnwrapConfidentialError> {
+                Ok($context::$unwrap(self, token)?.to_vec())
+            }
+        }
+    };
+}
+impl_stream_context!(ClientContext, NoEncryption, false, sign, unwrap);
+impl_stream_context!(ClientContext, Encryption, true, encrypt, unwrap_confidential);
+impl_stream_context!(ServerContext, NoEncryption, false, sign, unwrap);
+impl_stream_context!(ServerContext, Encryption, true, encrypt, unwrap_confidential);
+
+/// Shared framing state of [`SecureStream`] and [`AsyncSecureStream`]
+#[derive(Debug)]
+struct Framing {
+    max_frame_len: usize,
+    max_plaintext_len: Option<usize>,
+    plaintext: Vec<u8>,
+    plaintext_pos: usize,
+}
+impl Framing {
+    fn new(max_frame_len: usize) -> Self {
+        Self {
+            max_frame_len,
+            max_plaintext_len: None,
+            plaintext: Vec::new(),
+            plaintext_pos: 0,
+        }
+    }
+    /// Wraps as much of `buf` as fits into a single frame, returning the frame and the number of bytes consumed
+    fn wrap_frame<C: StreamContext>(&mut self, context: &mut C, buf: &[u8]) -> io::Result<(Vec<u8>, usize)> {
+        let max_plaintext_len = match self.max_plaintext_len {
+            Some(len) => len,
+            None => {
+                let len = context
+                    .max_plaintext_len(self.max_frame_len)
+                    .map_err(io::Error::other)?;
+                if len == 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("no plaintext fits into a frame of {} bytes", self.max_frame_len),
+                    ));
+                }
+                *self.max_plaintext_len.insert(len)
+            }
+        };
+        let consumed = buf.len().min(max_plaintext_len);
+        let token = context.wrap(&buf[..consumed]).map_err(io::Error::other)?;
+        let length = u32::try_from(token.len()).map_err(|_| io::ErrorKind::InvalidInput)?;
+        let mut frame = Vec::with_capacity(4 + token.len());
+        frame.extend_from_slice(&length.to_be_bytes());
+        frame.extend_from_slice(&token);
+        Ok((frame, consumed))
+    }
+    fn frame_len(&self, header: [u8; 4]) -> io::Result<usize> {
+        let length = u32::from_be_bytes(header) as usize;
+        if length > self.max_frame_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "frame of {length} bytes exceeds the limit of {} bytes",
+                    self.max_frame_len
+                ),
+            ));
+        }
+        Ok(length)
+    }
+    fn set_plaintext<C: StreamContext>(&mut self, context: &mut C, token: &[u8]) -> io::Result<()> {
+        self.plaintext = context
+            .unwrap(token)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.plaintext_pos = 0;
+        Ok(())
+    }
+    fn has_plaintext(&self) -> bool {
+        self.plaintext_pos < self.plaintext.len()
+    }
+    fn read_plaintext(&mut self, buf: &mut [u8]) -> usize {
+        let available = &self.plaintext[self.plaintext_pos..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.plaintext_pos += n;
+        n
+    }
+}
+
+/// A stream whose data is wrapped by a finished context
+#[derive(Debug)]
+pub struct SecureStream<C, T> {
+    context: C,
+    stream: T,
+    framing: Framing,
+}
+impl<C: StreamContext, T> SecureStream<C, T> {
+    /// Creates a stream with frames limited to the default [`Limits::max_token_len`]
+    pub fn new(context: C, stream: T) -> Self {
+        Self::with_max_frame_len(context, stream, Limits::default().max_token_len)
+    }
+    /// Creates a stream whose frames, both sent and received, are at most `max_frame_len` bytes long
+    pub fn with_max_frame_len(context: C, stream: T, max_frame_len: usize) -> Self {
+        Self {
+            context,
+            stream,
+            framing: Framing::new(max_frame_len),
+        }
+    }
+}
+impl<C, T> SecureStream<C, T> {
+    pub fn get_ref(&self) -> &T {
+        &self.stream
+    }
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.stream
+    }
+    pub fn context(&self) -> &C {
+        &self.context
+    }
+    /// Returns the context and the underlying stream, discarding any buffered plaintext
+    pub fn into_parts(self) -> (C, T) {
+        (self.context, self.stream)
+    }
+}
+impl<C: StreamContext, T: Read> SecureStream<C, T> {
+    /// Reads the next frame, returning `false` if the stream ended cleanly before it
+    fn read_frame(&mut self) -> io::Result<bool> {
+        let mut header = [0; 4];
+        let mut filled = 0;
+        while filled < header.len() {
+            match self.stream.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return Ok(false),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let mut token = vec![0; self.framing.frame_len(header)?];
+        self.stream.read_exact(&mut token)?;
+        self.framing.set_plaintext(&mut self.context, &token)?;
+        Ok(true)
+    }
+}
+impl<C: StreamContext, T: Read> Read for SecureStream<C, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while !self.framing.has_plaintext() {
+            if !self.read_frame()? {
+                return Ok(0);
+            }
+        }
+        Ok(self.framing.read_plaintext(buf))
+    }
+}
+impl<C: StreamContext, T: Write> Write for SecureStream<C, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let (frame, consumed) = self.framing.wrap_frame(&mut self.context, buf)?;
+        self.stream.write_all(&frame)?;
+        Ok(consumed)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub use async_stream::AsyncSecureStream;
+#[cfg(feature = "tokio")]
+mod async_stream {
+    use std::{
+        io,
+        pin::Pin,
+        task::{Context, Poll, ready},
+    };
+
+    use ::tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    use super::{Framing, StreamContext};
+    use crate::handshake::Limits;
+
+    /// The async twin of [`SecureStream`](super::SecureStream), using the same framing
+    ///
+    /// Written frames are buffered until the next write, flush or shutdown
+    #[derive(Debug)]
+    pub struct AsyncSecureStream<C, T> {
+        context: C,
+        stream: T,
+        framing: Framing,
+        incoming: Vec<u8>,
+        incoming_filled: usize,
+        outgoing: Vec<u8>,
+        outgoing_pos: usize,
+    }
+    impl<C: StreamContext, T> AsyncSecureStream<C, T> {
+        /// Creates a stream with frames limited to the default [`Limits::max_token_len`]
+        pub fn new(context: C, stream: T) -> Self {
+            Self::with_max_frame_len(context, stream, Limits::default().max_token_len)
+        }
+        /// Creates a stream whose frames, both sent and received, are at most `max_frame_len` bytes long
+        pub fn with_max_frame_len(context: C, stream: T, max_frame_len: usize) -> Self {
+            Self {
+                context,
+                stream,
+                framing: Framing::new(max_frame_len),
+                incoming: vec![0; 4],
+                incoming_filled: 0,
+                outgoing: Vec::new(),
+                outgoing_pos: 0,
+            }
+        }
+    }
+    impl<C, T> AsyncSecureStream<C, T> {
+        pub fn get_ref(&self) -> &T {
+            &self.stream
+        }
+        pub fn get_mut(&mut self) -> &mut T {
+            &mut self.stream
+        }
+        pub fn context(&self) -> &C {
+            &self.context
+        }
+        /// Returns the context and the underlying stream, discarding any buffered data
+        pub fn into_parts(self) -> (C, T) {
+            (self.context, self.stream)
+        }
+    }
+    impl<C: StreamContext, T: AsyncRead + Unpin> AsyncSecureStream<C, T> {
+        /// Reads the next frame, returning `false` if the stream ended cleanly before it
+        fn poll_frame(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<bool>> {
+            loop {
+                if self.incoming_filled == self.incoming.len() {
+                    if self.incoming.len() == 4 {
+                        let header = self.incoming[..4].try_into().expect("slice of length 4");
+                        let length = self.framing.frame_len(header)?;
+                        self.incoming.resize(4 + length, 0);
+                    }
+                    if self.incoming_filled == self.incoming.len() {
+                        let result = self.framing.set_plaintext(&mut self.context, &self.incoming[4..]);
+                        self.incoming.truncate(4);
+                        self.incoming_filled = 0;
+                        return Poll::Ready(result.map(|()| true));
+                    }
+                }
+                let mut buf = ReadBuf::new(&mut self.incoming[self.incoming_filled..]);
+                ready!(Pin::new(&mut self.stream).poll_read(cx, &mut buf))?;
+                match buf.filled().len() {
+                    0 if self.incoming_filled == 0 => return Poll::Ready(Ok(false)),
+                    0 => return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into())),
+                    n => self.incoming_filled += n,
+                }
+            }
+        }
+    }
+    impl<C: StreamContext, T: AsyncWrite + Unpin> AsyncSecureStream<C, T> {
+        fn poll_write_outgoing(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            while self.outgoing_pos < self.outgoing.len() {
+                let n = ready!(Pin::new(&mut self.stream).poll_write(cx, &self.outgoing[self.outgoing_pos..]))?;
+                if n == 0 {
+                    return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                }
+                self.outgoing_pos += n;
+            }
+            self.outgoing.clear();
+            self.outgoing_pos = 0;
+            Poll::Ready(Ok(()))
+        }
+    }
+    impl<C: StreamContext + Unpin, T: AsyncRead + Unpin> AsyncRead for AsyncSecureStream<C, T> {
+        fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+            if buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            while !this.framing.has_plaintext() {
+                if !ready!(this.poll_frame(cx))? {
+                    return Poll::Ready(Ok(()));
+                }
+            }
+            let n = this.framing.read_plaintext(buf.initialize_unfilled());
+            buf.advance(n);
+            Poll::Ready(Ok(()))
+        }
+    }
+    impl<C: StreamContext + Unpin, T: AsyncWrite + Unpin> AsyncWrite for AsyncSecureStream<C, T> {
+        fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            let this = self.get_mut();
+            ready!(this.poll_write_outgoing(cx))?;
+            if buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+            let (frame, consumed) = this.framing.wrap_frame(&mut this.context, buf)?;
+            this.outgoing = frame;
+            Poll::Ready(Ok(consumed))
+        }
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+            ready!(this.poll_write_outgoing(cx))?;
+            Pin::new(&mut this.stream).poll_flush(cx)
+        }
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+            ready!(this.poll_write_outgoing(cx))?;
+            Pin::new(&mut this.stream).poll_shutdown(cx)
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! Async authentication over tokio I/O
+//!
+//! Tokens are framed like [`TokenCodec`] does, which is compatible with [`LengthPrefixed`](crate::handshake::LengthPrefixed).
+//! The drivers never read past the last token, so the stream can be used for the application protocol afterwards.
+//!
+//! All calls into GSSAPI/SSPI that may block on the KDC are run on the blocking thread pool
+
+use std::{convert::Infallible, io};
+
+use ::tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use bytes::{Buf, BufMut, BytesMut};
+use kenobi_core::{
+    cred::usage::{InboundUsable, OutboundUsable},
+    typestate::{MaybeDelegation, MaybeEncryption, MaybeSigning},
+};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{
+    client::{ClientBuilder, ClientContext, StepOut as ClientStepOut},
+    handshake::{HandshakeError, Limits, check_round, check_token_len},
+    server::{ServerBuilder, ServerContext, StepOut as ServerStepOut},
+};
+
+/// Frames every token with its length as a 4 byte big endian prefix
+#[derive(Clone, Copy, Debug)]
+pub struct TokenCodec {
+    max_len: usize,
+}
+impl TokenCodec {
+    /// Creates a codec refusing incoming tokens longer than `max_len`
+    #[must_use]
+    pub fn new(max_len: usize) -> Self {
+        Self { max_len }
+    }
+}
+impl Default for TokenCodec {
+    fn default() -> Self {
+        Self::new(Limits::default().max_token_len)
+    }
+}
+impl Decoder for TokenCodec {
+    type Item = BytesMut;
+    type Error = io::Error;
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let Some(length) = src.get(..4) else {
+            return Ok(None);
+        };
+        let length = u32::from_be_bytes(length.try_into().expect("slice of length 4")) as usize;
+        check_token_len::<Infallible>(length, self.max_len)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        if src.len() < 4 + length {
+            src.reserve(4 + length - src.len());
+            return Ok(None);
+        }
+        src.advance(4);
+        Ok(Some(src.split_to(length)))
+    }
+}
+impl Encoder<&[u8]> for TokenCodec {
+    type Error = io::Error;
+    fn encode(&mut self, item: &[u8], dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let length = u32::try_from(item.len()).map_err(|_| io::ErrorKind::InvalidInput)?;
+        dst.reserve(4 + item.len());
+        dst.put_u32(length);
+        dst.extend_from_slice(item);
+        Ok(())
+    }
+}
+
+pub(crate) async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Result<T, io::Error> {
+    ::tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| match e.try_into_panic() {
+            Ok(panic) => std::panic::resume_unwind(panic),
+            Err(e) => io::Error::other(e),
+        })
+}
+
+async fn send<S: AsyncWrite + Unpin>(io: &mut S, token: &[u8]) -> Result<(), HandshakeError<io::Error>> {
+    let mut frame = BytesMut::new();
+    TokenCodec::default()
+        .encode(token, &mut frame)
+        .map_err(HandshakeError::Transport)?;
+    io.write_all(&frame).await.map_err(HandshakeError::Transport)?;
+    io.flush().await.map_err(HandshakeError::Transport)
+}
+
+async fn receive<S: AsyncRead + Unpin>(
+    io: &mut S,
+    rounds: &mut usize,
+    limits: Limits,
+) -> Result<Vec<u8>, HandshakeError<io::Error>> {
+    check_round(rounds, limits)?;
+    let length = io.read_u32().await.map_err(HandshakeError::Transport)? as usize;
+    check_token_len(length, limits.max_token_len)?;
+    let mut token = vec![0; length];
+    io.read_exact(&mut token).await.map_err(HandshakeError::Transport)?;
+    Ok(token)
+}
+
+/// Runs the client side of the authentication using the default [`Limits`]
+///
+/// # Errors
+/// Fails if the I/O fails, the context could not be established or the server violated the limits
+pub async fn authenticate_client<Usage, B, S>(
+    builder: ClientBuilder<Usage, B>,
+    io: &mut S,
+) -> Result<ClientContext<Usage, MaybeSigning, MaybeEncryption, MaybeDelegation, B>, HandshakeError<io::Error>>
+where
+    Usage: OutboundUsable + Send + 'static,
+    B: Send + 'static,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    authenticate_client_with_limits(builder, io, Limits::default()).await
+}
+
+/// Runs the client side of the authentication
+///
+/// # Errors
+/// Fails if the I/O fails, the context could not be established or the server violated the limits
+pub async fn authenticate_client_with_limits<Usage, B, S>(
+    builder: ClientBuilder<Usage, B>,
+    io: &mut S,
+    limits: Limits,
+) -> Result<ClientContext<Usage, MaybeSigning, MaybeEncryption, MaybeDelegation, B>, HandshakeError<io::Error>>
+where
+    Usage: OutboundUsable + Send + 'static,
+    B: Send + 'static,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut rounds = 0;
+    let mut step = blocking(move || builder.initialize())
+        .await
+        .map_err(HandshakeError::Transport)?
+        .map_err(HandshakeError::Initialize)?;
+    loop {
+        match step {
+            ClientStepOut::Pending(pending) => {
+                send(io, pending.next_token()).await?;
+                let token = receive(io, &mut rounds, limits).await?;
+                step = blocking(move || pending.step(&token))
+                    .await
+                    .map_err(HandshakeError::Transport)?
+                    .map_err(HandshakeError::Initialize)?;
+            }
+            ClientStepOut::Finished(context) => {
+                if let Some(token) = context.last_token() {
+                    send(io, token).await?;
+                }
+                return Ok(context);
+            }
+        }
+    }
+}
+
+/// Runs the server side of the authentication using the default [`Limits`]
+///
+/// # Errors
+/// Fails if the I/O fails, the context could not be established or the client violated the limits
+pub async fn authenticate_server<Usage, B, S>(
+    builder: ServerBuilder<Usage, B>,
+    io: &mut S,
+) -> Result<ServerContext<Usage, MaybeSigning, MaybeEncryption, MaybeDelegation, B>, HandshakeError<io::Error>>
+where
+    Usage: InboundUsable + Send + 'static,
+    B: Send + 'static,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    authenticate_server_with_limits(builder, io, Limits::default()).await
+}
+
+/// Runs the server side of the authentication
+///
+/// # Errors
+/// Fails if the I/O fails, the context could not be established or the client violated the limits
+pub async fn authenticate_server_with_limits<Usage, B, S>(
+    builder: ServerBuilder<Usage, B>,
+    io: &mut S,
+    limits: Limits,
+) -> Result<ServerContext<Usage, MaybeSigning, MaybeEncryption, MaybeDelegation, B>, HandshakeError<io::Error>>
+where
+    Usage: InboundUsable + Send + 'static,
+    B: Send + 'static,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut rounds = 0;
+    let token = receive(io, &mut rounds, limits).await?;
+    let mut step = blocking(move || builder.initialize(&token))
+        .await
+        .map_err(HandshakeError::Transport)?
+        .map_err(HandshakeError::Accept)?;
+    loop {
+        match step {
+            ServerStepOut::Pending(pending) => {
+                send(io, pending.next_token()).await?;
+                let token = receive(io, &mut rounds, limits).await?;
+                step = blocking(move || pending.step(&token))
+                    .await
+                    .map_err(HandshakeError::Transport)?
+                    .map_err(HandshakeError::Accept)?;
+            }
+            ServerStepOut::Finished(context) => {
+                if let Some(token) = context.last_token() {
+                    send(io, token).await?;
+                }
+                return Ok(context);
+            }
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! Tower middleware authenticating HTTP requests with Negotiate (RFC 4559)
+//!
+//! Requests without credentials are answered with `401 Negotiate`.
+//! Exchanges taking more than one round trip continue on the same connection, which the middleware recognizes
+//! by the [`ConnectionId`] extension the server inserts into every request of that connection.
+//! Without it, only exchanges finishing in one round trip (like plain Kerberos) succeed.
+//!
+//! Accepting a token does not contact the KDC, so the exchange runs directly inside `Service::call`
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll, ready},
+    time::{Duration, Instant},
+};
+
+use ::http::{
+    HeaderValue, Request, Response, StatusCode,
+    header::{AUTHORIZATION, WWW_AUTHENTICATE},
+};
+use kenobi_core::{
+    cred::usage::{InboundUsable, Outbound},
+    mech::Mechanism,
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{
+    cred::Credentials,
+    http::{NegotiateChallenge, Scheme, authorization_value, select_challenge},
+    server::{AcceptError, ClientNameError, PendingServerContext, ServerBuilder, ServerContext, StepOut},
+};
+
+/// Identifies the connection a request arrived on
+///
+/// Insert it into the extensions of every request to allow exchanges with more than one round trip
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ConnectionId(pub u64);
+
+/// The authenticated client, inserted into the extensions of every request passed on to the inner service
+#[derive(Clone, Debug)]
+pub struct AuthenticatedPrincipal {
+    pub name: String,
+    pub mechanism: Mechanism,
+    /// The credentials the client delegated, always `None` on Windows
+    pub delegated_credentials: Option<Credentials<Outbound>>,
+}
+impl AuthenticatedPrincipal {
+    fn from_context<Usage>(context: &mut ServerContext<Usage>) -> Result<Self, ClientNameError> {
+        let name = context.client_name()?.to_string();
+        Ok(Self {
+            name,
+            mechanism: context.mechanism(),
+            #[cfg(unix)]
+            delegated_credentials: context.take_delegated_credentials(),
+            #[cfg(windows)]
+            delegated_credentials: None,
+        })
+    }
+}
+
+struct Pending<Usage> {
+    context: PendingServerContext<Usage>,
+    started: Instant,
+}
+type PendingMap<Usage> = Arc<Mutex<HashMap<ConnectionId, Pending<Usage>>>>;
+
+/// Layer requiring Negotiate authentication for every request
+pub struct NegotiateLayer<Usage> {
+    credentials: Credentials<Usage>,
+    max_pending: usize,
+    pending_timeout: Duration,
+}
+impl<Usage> NegotiateLayer<Usage> {
+    #[must_use]
+    pub fn new(credentials: Credentials<Usage>) -> Self {
+        Self {
+            credentials,
+            max_pending: 1024,
+            pending_timeout: Duration::from_secs(60),
+        }
+    }
+    /// Limits the number of unfinished exchanges kept between round trips
+    #[must_use]
+    pub fn with_max_pending(self, max_pending: usize) -> Self {
+        Self { max_pending, ..self }
+    }
+    /// Drops unfinished exchanges whose client did not continue within `timeout`
+    #[must_use]
+    pub fn with_pending_timeout(self, pending_timeout: Duration) -> Self {
+        Self {
+            pending_timeout,
+            ..self
+        }
+    }
+}
+impl<Usage> Clone for NegotiateLayer<Usage> {
+    fn clone(&self) -> Self {
+        Self {
+            credentials: self.credentials.clone(),
+            max_pending: self.max_pending,
+            pending_timeout: self.pending_timeout,
+        }
+    }
+}
+impl<S, Usage> Layer<S> for NegotiateLayer<Usage> {
+    type Service = Negotiate<S, Usage>;
+    fn layer(&self, inner: S) -> Self::Service {
+        Negotiate {
+            inner,
+            layer: self.clone(),
+            pending: Arc::default(),
+        }
+    }
+}
+
+/// Service created by [`NegotiateLayer`]
+pub struct Negotiate<S, Usage> {
+    inner: S,
+    layer: NegotiateLayer<Usage>,
+    pending: PendingMap<Usage>,
+}
+impl<S: Clone, Usage> Clone for Negotiate<S, Usage> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+            pending: self.pending.clone(),
+        }
+    }
+}
+impl<S, Usage: InboundUsable> Negotiate<S, Usage> {
+    fn step(&self, connection: Option<ConnectionId>, token: &[u8]) -> Result<StepOut<Usage>, AcceptError> {
+        let resumed = connection
+            .and_then(|connection| self.lock().remove(&connection))
+            .filter(|pending| pending.started.elapsed() < self.layer.pending_timeout);
+        match resumed {
+            Some(pending) => pending.context.step(token),
+            None => ServerBuilder::new_from_credentials(self.layer.credentials.clone())
+                .with_mutual_auth()
+                .initialize(token),
+        }
+    }
+    fn store(&self, connection: ConnectionId, context: PendingServerContext<Usage>) {
+        let mut pending = self.lock();
+        pending.retain(|_, pending| pending.started.elapsed() < self.layer.pending_timeout);
+        if pending.len() < self.layer.max_pending {
+            pending.insert(
+                connection,
+                Pending {
+                    context,
+                    started: Instant::now(),
+                },
+            );
+        }
+    }
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<ConnectionId, Pending<Usage>>> {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn header_value(scheme: Scheme, token: Option<&[u8]>) -> HeaderValue {
+    match token {
+        Some(token) => {
+            HeaderValue::try_from(authorization_value(scheme, token)).expect("base64 is a valid header value")
+        }
+        None => HeaderValue::from_static(scheme.name()),
+    }
+}
+fn respond<B: Default>(status: StatusCode, challenge: Option<HeaderValue>) -> Response<B> {
+    let mut response = Response::new(B::default());
+    *response.status_mut() = status;
+    if let Some(challenge) = challenge {
+        response.headers_mut().insert(WWW_AUTHENTICATE, challenge);
+    }
+    response
+}
+fn unauthorized<B: Default>(challenge: HeaderValue) -> Response<B> {
+    respond(StatusCode::UNAUTHORIZED, Some(challenge))
+}
+
+impl<S, Usage, ReqB, ResB> Service<Request<ReqB>> for Negotiate<S, Usage>
+where
+    S: Service<Request<ReqB>, Response = Response<ResB>> + Clone,
+    Usage: InboundUsable,
+    ResB: Default,
+{
+    type Response = Response<ResB>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResB>;
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+    fn call(&mut self, mut request: Request<ReqB>) -> Self::Future {
+        let values = request
+            .headers()
+            .get_all(AUTHORIZATION)
+            .iter()
+            .filter_map(|value| value.to_str().ok());
+        let (scheme, token) = match select_challenge(values) {
+            Ok(Some(NegotiateChallenge {
+                scheme,
+                token: Some(token),
+            })) => (scheme, token),
+            Ok(_) => return ResponseFuture::respond(unauthorized(header_value(Scheme::Negotiate, None))),
+            Err(_) => return ResponseFuture::respond(respond(StatusCode::BAD_REQUEST, None)),
+        };
+        let connection = request.extensions().get::<ConnectionId>().copied();
+        match self.step(connection, &token) {
+            Ok(StepOut::Pending(pending)) => {
+                let challenge = header_value(scheme, Some(pending.next_token()));
+                if let Some(connection) = connection {
+                    self.store(connection, pending);
+                }
+                ResponseFuture::respond(unauthorized(challenge))
+            }
+            Ok(StepOut::Finished(mut context)) => {
+                let mutual_token = context.last_token().map(|token| header_value(scheme, Some(token)));
+                let Ok(principal) = AuthenticatedPrincipal::from_context(&mut context) else {
+                    return ResponseFuture::respond(respond(StatusCode::INTERNAL_SERVER_ERROR, None));
+                };
+                request.extensions_mut().insert(principal);
+                // The clone may not be ready, so the ready instance is taken
+                let clone = self.inner.clone();
+                let mut inner = std::mem::replace(&mut self.inner, clone);
+                ResponseFuture {
+                    kind: Kind::Inner {
+                        future: Box::pin(inner.call(request)),
+                        mutual_token,
+                    },
+                }
+            }
+            Err(_) => ResponseFuture::respond(unauthorized(header_value(scheme, None))),
+        }
+    }
+}
+
+/// Future returned by [`Negotiate`]
+pub struct ResponseFuture<F, B> {
+    kind: Kind<F, B>,
+}
+enum Kind<F, B> {
+    Respond(Option<Response<B>>),
+    Inner {
+        future: Pin<Box<F>>,
+        mutual_token: Option<HeaderValue>,
+    },
+}
+impl<F, B> ResponseFuture<F, B> {
+    fn respond(response: Response<B>) -> Self {
+        Self {
+            kind: Kind::Respond(Some(response)),
+        }
+    }
+}
+// The inner future is boxed and the body is never pinned
+impl<F, B> Unpin for ResponseFuture<F, B> {}
+impl<F, B, E> Future for ResponseFuture<F, B>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<B>, E>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match &mut self.get_mut().kind {
+            Kind::Respond(response) => Poll::Ready(Ok(response.take().expect("polled after completion"))),
+            Kind::Inner { future, mutual_token } => {
+                let mut response = ready!(future.as_mut().poll(cx))?;
+                if let Some(token) = mutual_token.take() {
+                    response.headers_mut().append(WWW_AUTHENTICATE, token);
+                }
+                Poll::Ready(Ok(response))
+            }
+        }
+    }
+}
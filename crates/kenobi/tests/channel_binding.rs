@@ -0,0 +1,103 @@
+//! Servers only count as bound once they require binding, checked with a cached ticket instead of a KDC
+#![cfg(unix)]
+
+use std::convert::Infallible;
+
+use kenobi::{
+    channel_bindings::{Channel, ChannelBindingPolicy},
+    client::{ClientBuilder, StepOut as ClientStep},
+    cred::{Credentials, Inbound, Mechanism, Outbound},
+    server::{AcceptError, ServerBuilder, ServerContext, StepOut as ServerStep},
+    split::Split,
+    typestate::{Bound, MaybeDelegation, MaybeEncryption, MaybeSigning, Unbound},
+};
+use kenobi_testkdc::{OfflineInitiator, offline_environment};
+
+struct TestChannel(&'static [u8]);
+impl Channel for TestChannel {
+    type Error = Infallible;
+    fn channel_bindings(&self) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(Some([b"tls-server-end-point:".as_slice(), self.0].concat()))
+    }
+}
+const TLS: TestChannel = TestChannel(b"certificate hash");
+
+type Server<B> = ServerContext<Inbound, MaybeSigning, MaybeEncryption, MaybeDelegation, B>;
+
+fn client_builder() -> ClientBuilder<Outbound> {
+    offline_environment();
+    let credentials = Credentials::outbound(None, Mechanism::KerberosV5).unwrap();
+    ClientBuilder::new_from_credentials(credentials, Some(OfflineInitiator::SERVICE)).request_signing()
+}
+
+fn server_builder() -> ServerBuilder<Inbound> {
+    ServerBuilder::new_from_credentials(Credentials::inbound(None, Mechanism::KerberosV5).unwrap())
+}
+
+fn accept<CB, SB>(
+    client: ClientBuilder<Outbound, CB>,
+    server: ServerBuilder<Inbound, SB>,
+) -> Result<Server<SB>, AcceptError> {
+    let ClientStep::Finished(client) = client.initialize().unwrap() else {
+        panic!("Kerberos without mutual authentication takes a single token");
+    };
+    let ServerStep::Finished(server) = server.initialize(client.last_token().unwrap())? else {
+        panic!("the server should finish after the first token");
+    };
+    Ok(server)
+}
+
+#[test]
+fn required_binding_makes_the_context_bound() {
+    let server: Server<Bound> = accept(
+        client_builder().bind_to_channel(&TLS).unwrap(),
+        server_builder().require_channel_binding(&TLS).unwrap(),
+    )
+    .unwrap();
+    assert!(server.channel_bound());
+
+    let Ok(server) = server.check_signing() else {
+        panic!("signing was requested");
+    };
+    let Split { mut sealer, .. }: Split<_, Bound> = server.split();
+    sealer.sign(b"bound halves").unwrap();
+}
+
+#[test]
+fn required_binding_rejects_unbound_and_mismatched_clients() {
+    let unbound = accept(
+        client_builder(),
+        server_builder().require_channel_binding(&TLS).unwrap(),
+    );
+    assert!(matches!(unbound, Err(AcceptError::BadChannelBindings)));
+
+    let mismatched = accept(
+        client_builder().bind_to_channel(&TestChannel(b"another hash")).unwrap(),
+        server_builder().require_channel_binding(&TLS).unwrap(),
+    );
+    assert!(matches!(mismatched, Err(AcceptError::BadChannelBindings)));
+}
+
+#[test]
+fn optional_binding_is_checked_at_runtime() {
+    let server: Server<Unbound> = accept(client_builder(), server_builder().bind_to_channel(&TLS).unwrap()).unwrap();
+    assert!(server.check_channel_bound().is_err());
+
+    let server: Server<Unbound> = accept(
+        client_builder().bind_to_channel(&TLS).unwrap(),
+        server_builder().bind_to_channel(&TLS).unwrap(),
+    )
+    .unwrap();
+    let _: Server<Bound> = server
+        .check_channel_bound()
+        .unwrap_or_else(|_| panic!("the client bound to the same channel"));
+}
+
+#[test]
+fn ignored_binding_accepts_any_client() {
+    accept(
+        client_builder().bind_to_channel(&TestChannel(b"another hash")).unwrap(),
+        server_builder().with_channel_binding_policy(ChannelBindingPolicy::Ignore),
+    )
+    .unwrap();
+}
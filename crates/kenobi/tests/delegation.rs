@@ -0,0 +1,60 @@
+//! Delegation against a throwaway MIT KDC, as only a KDC can issue the forwarded ticket
+//!
+//! Ignored by default, as the KDC programs are rarely installed. Run it with `cargo test -- --ignored`,
+//! which fails if the programs are missing
+#![cfg(unix)]
+
+use kenobi::{
+    client::{ClientBuilder, ClientContext, StepOut as ClientStep},
+    cred::{Credentials, Inbound, Mechanism, Outbound},
+    server::{ServerBuilder, ServerContext, StepOut as ServerStep},
+    typestate::{MaybeDelegation, MaybeEncryption, MaybeSigning},
+};
+use kenobi_testkdc::TestKdc;
+
+type Client = ClientContext<Outbound, MaybeSigning, MaybeEncryption, MaybeDelegation>;
+type Server = ServerContext<Inbound, MaybeSigning, MaybeEncryption, MaybeDelegation>;
+
+/// Kerberos with mutual authentication takes exactly one round trip
+fn establish(client: ClientBuilder<Outbound>) -> (Client, Server) {
+    let ClientStep::Pending(client) = client.request_mutual_auth().initialize().unwrap() else {
+        panic!("mutual authentication needs a reply from the server");
+    };
+    let server = ServerBuilder::new_from_credentials(Credentials::inbound(None, Mechanism::KerberosV5).unwrap());
+    let ServerStep::Finished(server) = server.initialize(client.next_token()).unwrap() else {
+        panic!("the server should finish after the first token");
+    };
+    let reply = server
+        .last_token()
+        .expect("the server should reply for mutual authentication");
+    let ClientStep::Finished(client) = client.step(reply).unwrap() else {
+        panic!("the client should finish after the reply");
+    };
+    (client, server)
+}
+
+#[test]
+#[ignore = "needs the MIT KDC programs"]
+fn delegation() {
+    let kdc = TestKdc::start().unwrap_or_else(|err| panic!("could not start the test KDC: {err}"));
+    let service = kdc.add_principal("HTTP/localhost").unwrap();
+    let user = kdc.add_principal("alice").unwrap();
+    kdc.kinit(&user).unwrap();
+    // SAFETY: this is the only test in this binary, no other thread touches the environment
+    unsafe { kdc.set_environment() };
+    let client_builder = || {
+        let cred = Credentials::outbound(None, Mechanism::KerberosV5).unwrap();
+        ClientBuilder::new_from_credentials(cred, Some(&service))
+    };
+
+    let (client, mut server) = establish(client_builder().request_delegation());
+    assert!(client.check_delegation().is_ok());
+    let delegated = server
+        .take_delegated_credentials()
+        .expect("the client delegated a forwardable ticket");
+    establish(ClientBuilder::new_from_credentials(delegated, Some(&service)));
+
+    let (client, mut server) = establish(client_builder());
+    assert!(client.check_delegation().is_err());
+    assert!(server.take_delegated_credentials().is_none());
+}
@@ -0,0 +1,200 @@
+//! Full exchanges between a client and a server, using a cached ticket for `HTTP/localhost` instead of a KDC
+#![cfg(unix)]
+
+use std::{sync::mpsc, thread};
+
+use kenobi::{
+    client::{ClientBuilder, ClientContext, StepOut as ClientStep},
+    cred::{Credentials, Inbound, Mechanism, Outbound},
+    key::PrfKey,
+    server::{ServerBuilder, ServerContext, StepOut as ServerStep},
+    sign_encrypt::{Qop, VerifyMicError},
+    split::Split,
+    typestate::{MaybeDelegation, MaybeEncryption, MaybeSigning},
+};
+use kenobi_testkdc::{OfflineInitiator, offline_environment};
+
+type Client = ClientContext<Outbound, MaybeSigning, MaybeEncryption, MaybeDelegation>;
+type Server = ServerContext<Inbound, MaybeSigning, MaybeEncryption, MaybeDelegation>;
+
+/// Kerberos with mutual authentication takes exactly one round trip
+fn establish(client: ClientBuilder<Outbound>) -> (Client, Server) {
+    let ClientStep::Pending(client) = client.request_mutual_auth().initialize().unwrap() else {
+        panic!("mutual authentication needs a reply from the server");
+    };
+    let ServerStep::Finished(server) = server_builder().initialize(client.next_token()).unwrap() else {
+        panic!("the server should finish after the first token");
+    };
+    let reply = server
+        .last_token()
+        .expect("the server should reply for mutual authentication");
+    let ClientStep::Finished(client) = client.step(reply).unwrap() else {
+        panic!("the client should finish after the reply");
+    };
+    (client, server)
+}
+
+fn client_builder() -> ClientBuilder<Outbound> {
+    offline_environment();
+    let cred = Credentials::outbound(None, Mechanism::KerberosV5).unwrap();
+    ClientBuilder::new_from_credentials(cred, Some(OfflineInitiator::SERVICE))
+}
+
+fn server_builder() -> ServerBuilder<Inbound> {
+    let cred = Credentials::inbound(None, Mechanism::KerberosV5).unwrap();
+    ServerBuilder::new_from_credentials(cred)
+}
+
+#[test]
+fn signing() {
+    let (client, mut server) = establish(client_builder().request_signing());
+    assert_eq!(server.client_name().unwrap().to_string(), OfflineInitiator::CLIENT);
+    let Ok(mut client) = client.check_signing() else {
+        panic!("signing was requested");
+    };
+    let Ok(mut server) = server.check_signing() else {
+        panic!("signing was requested");
+    };
+    let signed = client.sign(b"hello there").unwrap();
+    let plaintext = server.unwrap(&signed).unwrap();
+    assert_eq!(&*plaintext, b"hello there");
+    assert!(!plaintext.was_encrypted());
+}
+
+#[test]
+fn mic_and_qop() {
+    let (client, server) = establish(client_builder().request_signing());
+    let (Ok(mut client), Ok(mut server)) = (client.check_signing(), server.check_signing()) else {
+        panic!("signing was requested");
+    };
+    assert_eq!(
+        client.max_plaintext_len_with_qop(1024, false, Qop::DEFAULT).unwrap(),
+        client.max_plaintext_len(1024, false).unwrap()
+    );
+    assert_eq!(
+        server.wrap_overhead_with_qop(64, false, Qop::DEFAULT).unwrap(),
+        server.wrap_overhead(64, false).unwrap()
+    );
+
+    let mic = server.get_mic(b"general kenobi").unwrap();
+    client.verify_mic(b"general kenobi", &mic).unwrap();
+    assert!(client.verify_mic(b"general grievous", &mic).is_err());
+}
+
+#[test]
+fn rejects_tampered_and_replayed_mics() {
+    let (client, server) = establish(client_builder().request_signing().request_replay_detection());
+    let (Ok(mut client), Ok(mut server)) = (client.check_signing(), server.check_signing()) else {
+        panic!("signing was requested");
+    };
+    let mic = client.get_mic(b"hello there").unwrap();
+    let mut tampered = mic.to_vec();
+    *tampered.last_mut().unwrap() ^= 1;
+    assert!(matches!(
+        server.verify_mic(b"hello there", &tampered),
+        Err(VerifyMicError::BadSignature)
+    ));
+    assert!(matches!(
+        server.verify_mic(b"hello there!", &mic),
+        Err(VerifyMicError::BadSignature)
+    ));
+
+    server.verify_mic(b"hello there", &mic).unwrap();
+    assert!(matches!(
+        server.verify_mic(b"hello there", &mic),
+        Err(VerifyMicError::DuplicateToken)
+    ));
+}
+
+#[test]
+fn encryption() {
+    let (client, server) = establish(client_builder().request_encryption());
+    let Ok(Ok(mut client)) = client.check_signing().map(|client| client.check_encryption()) else {
+        panic!("encryption was requested");
+    };
+    let Ok(Ok(mut server)) = server.check_signing().map(|server| server.check_encryption()) else {
+        panic!("encryption was requested");
+    };
+    let encrypted = server.encrypt(b"you were the chosen one").unwrap();
+    assert!(!encrypted.windows(7).any(|window| window == b"chosen "));
+    let plaintext = client.unwrap_confidential(&encrypted).unwrap();
+    assert_eq!(&*plaintext, b"you were the chosen one");
+
+    let signed = server.sign(b"only signed").unwrap();
+    assert!(client.unwrap_confidential(&signed).is_err());
+}
+
+/// Both halves of each side run on their own thread, so sealing and opening contend for the same context
+#[test]
+fn split_halves() {
+    const MESSAGES: usize = 200;
+    let (client, server) = establish(client_builder().request_signing());
+    let (Ok(client), Ok(server)) = (client.check_signing(), server.check_signing()) else {
+        panic!("signing was requested");
+    };
+    let Split {
+        sealer: mut client_sealer,
+        opener: mut client_opener,
+        last_token: client_token,
+    } = client.split();
+    let Split {
+        sealer: mut server_sealer,
+        opener: mut server_opener,
+        last_token: server_token,
+    } = server.split();
+    assert!(client_token.is_none());
+    assert!(
+        server_token.is_some(),
+        "the mutual authentication reply survives the split"
+    );
+
+    let (to_server, from_client) = mpsc::channel();
+    let (to_client, from_server) = mpsc::channel();
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            for i in 0..MESSAGES {
+                let token = client_sealer.sign(format!("client {i}").as_bytes()).unwrap();
+                to_server.send(token).unwrap();
+            }
+        });
+        scope.spawn(move || {
+            for i in 0..MESSAGES {
+                let token = server_sealer.sign(format!("server {i}").as_bytes()).unwrap();
+                to_client.send(token).unwrap();
+            }
+        });
+        scope.spawn(move || {
+            for (i, token) in from_client.iter().enumerate() {
+                let plaintext = server_opener.unwrap(&token).unwrap();
+                assert_eq!(&*plaintext, format!("client {i}").as_bytes());
+            }
+        });
+        scope.spawn(move || {
+            for (i, token) in from_server.iter().enumerate() {
+                let plaintext = client_opener.unwrap(&token).unwrap();
+                assert_eq!(&*plaintext, format!("server {i}").as_bytes());
+            }
+        });
+    });
+}
+
+#[test]
+fn session_keys_match() {
+    let (client, server) = establish(client_builder());
+    let client_key = client.session_key().unwrap();
+    let server_key = server.session_key().unwrap();
+    assert!(!client_key.is_empty());
+    assert_eq!(*client_key, *server_key);
+}
+
+#[test]
+fn pseudo_random_outputs_match() {
+    let (client, server) = establish(client_builder());
+    for key in [PrfKey::Full, PrfKey::Partial] {
+        let client_output = client.prf(key, b"kenobi test", 64).unwrap();
+        assert_eq!(client_output.len(), 64);
+        assert_eq!(client_output, server.prf(key, b"kenobi test", 64).unwrap());
+        assert_ne!(client_output, client.prf(key, b"another input", 64).unwrap());
+    }
+    assert!(client.prf(PrfKey::Full, b"kenobi test", 0).unwrap().is_empty());
+}
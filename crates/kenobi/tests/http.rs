@@ -0,0 +1,35 @@
+use kenobi::http::{AuthTarget, Scheme, authorization_value, parse_challenges, select_challenge};
+
+#[test]
+fn parses_challenge_lists() {
+    let challenges = parse_challenges(r#"Basic realm="a, b", charset="UTF-8", Negotiate YWJj, Kerberos"#);
+    assert_eq!(challenges.len(), 3);
+    assert_eq!(challenges[0].scheme, "Basic");
+    assert_eq!(challenges[0].params, [("realm", "a, b"), ("charset", "UTF-8")]);
+    assert_eq!(challenges[1].token68, Some("YWJj"));
+    assert_eq!(challenges[2].scheme, "Kerberos");
+    assert_eq!(challenges[2].token68, None);
+}
+
+#[test]
+fn prefers_negotiate() {
+    let challenge = select_challenge(["Kerberos", "Basic realm=x, negotiate YWJjZA=="])
+        .unwrap()
+        .unwrap();
+    assert_eq!(challenge.scheme, Scheme::Negotiate);
+    assert_eq!(challenge.token.as_deref(), Some(&b"abcd"[..]));
+
+    let challenge = select_challenge(["Kerberos"]).unwrap().unwrap();
+    assert_eq!(challenge.scheme, Scheme::Kerberos);
+    assert_eq!(challenge.token, None);
+
+    assert!(select_challenge(["Basic realm=x"]).unwrap().is_none());
+    assert!(select_challenge(["Negotiate Y"]).is_err());
+}
+
+#[test]
+fn builds_authorization() {
+    assert_eq!(authorization_value(Scheme::Negotiate, b"abc"), "Negotiate YWJj");
+    assert_eq!(AuthTarget::from_status(407), Some(AuthTarget::Proxy));
+    assert_eq!(AuthTarget::Proxy.authorization_header(), "Proxy-Authorization");
+}
@@ -0,0 +1,24 @@
+use std::io::Cursor;
+
+use kenobi::handshake::{LengthPrefixed, Transport};
+
+#[test]
+fn round_trip() {
+    let mut transport = LengthPrefixed::new(Cursor::new(Vec::new()));
+    transport.send_token(b"first").unwrap();
+    transport.send_token(b"").unwrap();
+    transport.send_token(b"third").unwrap();
+    assert_eq!(&transport.get_ref().get_ref()[..9], b"\0\0\0\x05first");
+
+    let mut transport = LengthPrefixed::new(Cursor::new(transport.into_inner().into_inner()));
+    assert_eq!(transport.receive_token(16).unwrap(), b"first");
+    assert_eq!(transport.receive_token(16).unwrap(), b"");
+    assert_eq!(transport.receive_token(16).unwrap(), b"third");
+}
+
+#[test]
+fn refuses_oversized_token() {
+    let mut transport = LengthPrefixed::new(Cursor::new(b"\0\0\x01\0".to_vec()));
+    let error = transport.receive_token(255).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+}
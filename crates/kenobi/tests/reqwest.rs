@@ -0,0 +1,145 @@
+//! Exchanges with a scripted server, using a cached ticket for `HTTP/localhost` instead of a KDC
+#![cfg(all(unix, feature = "reqwest"))]
+
+use std::{
+    io::{Read, Write},
+    net::{Ipv4Addr, TcpListener},
+    thread::{self, JoinHandle},
+};
+
+use kenobi::{
+    cred::{Credentials, Mechanism},
+    http::{AuthTarget, HttpAuthError},
+    reqwest::{NegotiateClient, NegotiateError},
+};
+use kenobi_testkdc::offline_environment;
+use reqwest::{Body, Client, Method, Request, Response, StatusCode};
+
+/// Answers one connection per response and returns the head of every request it received
+struct Stub {
+    port: u16,
+    requests: JoinHandle<Vec<String>>,
+}
+impl Stub {
+    fn serve(responses: &'static [&'static str]) -> Self {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = thread::spawn(move || {
+            responses
+                .iter()
+                .map(|response| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let mut head = Vec::new();
+                    let mut byte = [0];
+                    while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                        head.push(byte[0]);
+                    }
+                    write!(stream, "{response}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").unwrap();
+                    String::from_utf8(head).unwrap().to_ascii_lowercase()
+                })
+                .collect()
+        });
+        Self { port, requests }
+    }
+    fn url(&self, host: &str) -> String {
+        format!("http://{host}:{}/", self.port)
+    }
+    fn requests(self) -> Vec<String> {
+        self.requests.join().unwrap()
+    }
+}
+
+fn negotiate_client() -> NegotiateClient {
+    offline_environment();
+    let credentials = Credentials::outbound(None, Mechanism::KerberosV5).unwrap();
+    NegotiateClient::new(Client::builder().no_proxy().build().unwrap(), credentials)
+}
+
+fn execute(client: &NegotiateClient, request: Request) -> Result<Response, NegotiateError> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(client.execute(request))
+}
+
+fn get(url: &str) -> Request {
+    Request::new(Method::GET, url.parse().unwrap())
+}
+
+#[test]
+fn derives_the_principal_from_the_url() {
+    let stub = Stub::serve(&[
+        "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Negotiate",
+        "HTTP/1.1 200 OK",
+    ]);
+    let result = execute(&negotiate_client(), get(&stub.url("localhost")));
+    assert!(
+        matches!(result, Err(NegotiateError::Http(HttpAuthError::MissingToken))),
+        "the stub never sends a mutual authentication token"
+    );
+    let requests = stub.requests();
+    assert!(!requests[0].contains("authorization:"));
+    assert!(requests[1].contains("\r\nauthorization: negotiate "));
+
+    let stub = Stub::serve(&["HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Negotiate"]);
+    let result = execute(&negotiate_client(), get(&stub.url("127.0.0.1")));
+    assert!(
+        matches!(result, Err(NegotiateError::Initialize(_))),
+        "only HTTP/localhost has a ticket"
+    );
+    assert_eq!(stub.requests().len(), 1);
+}
+
+#[test]
+fn derives_the_proxy_principal_from_the_proxy_host() {
+    let stub = Stub::serve(&[
+        "HTTP/1.1 407 Proxy Authentication Required\r\nProxy-Authenticate: Kerberos",
+        "HTTP/1.1 200 OK",
+    ]);
+    let client = negotiate_client().with_proxy_host("localhost");
+    let result = execute(&client, get(&stub.url("127.0.0.1")));
+    assert!(matches!(result, Err(NegotiateError::Http(HttpAuthError::MissingToken))));
+    let requests = stub.requests();
+    assert!(requests[1].contains("\r\nproxy-authorization: kerberos "));
+    assert!(!requests[1].contains("\r\nauthorization:"));
+}
+
+#[test]
+fn needs_the_proxy_host() {
+    let stub = Stub::serve(&["HTTP/1.1 407 Proxy Authentication Required\r\nProxy-Authenticate: Negotiate"]);
+    let result = execute(&negotiate_client(), get(&stub.url("localhost")));
+    assert!(matches!(result, Err(NegotiateError::NoHost(AuthTarget::Proxy))));
+    assert_eq!(stub.requests().len(), 1);
+}
+
+#[test]
+fn answers_every_target_once() {
+    let stub = Stub::serve(&[
+        "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Negotiate",
+        "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Negotiate",
+    ]);
+    let response = execute(&negotiate_client(), get(&stub.url("localhost"))).unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(stub.requests().len(), 2);
+
+    let stub = Stub::serve(&["HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Basic realm=\"kenobi\""]);
+    let response = execute(&negotiate_client(), get(&stub.url("localhost"))).unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(stub.requests().len(), 1);
+}
+
+#[test]
+fn refuses_streamed_bodies() {
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let file = runtime
+        .block_on(tokio::fs::File::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/Cargo.toml"
+        )))
+        .unwrap();
+    let mut request = Request::new(Method::POST, "http://localhost/".parse().unwrap());
+    *request.body_mut() = Some(Body::from(file));
+    let result = execute(&negotiate_client(), request);
+    assert!(matches!(result, Err(NegotiateError::BodyNotCloneable)));
+}
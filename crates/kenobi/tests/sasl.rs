@@ -0,0 +1,87 @@
+//! SASL exchanges between a client and a server, using a cached ticket for `HTTP/localhost` instead of a KDC
+#![cfg(unix)]
+
+use kenobi::{
+    client::ClientBuilder,
+    cred::{Credentials, Inbound, Mechanism, Outbound},
+    sasl::{
+        ClientStep, Negotiated, SaslClient, SaslClientConfig, SaslError, SaslMechanism, SaslServer, SaslServerConfig,
+        SecurityLayer, ServerStep,
+    },
+    server::ServerBuilder,
+};
+use kenobi_testkdc::{OfflineInitiator, offline_environment};
+
+fn client_builder(mechanism: Mechanism) -> ClientBuilder<Outbound> {
+    offline_environment();
+    let cred = Credentials::outbound(None, mechanism).unwrap();
+    ClientBuilder::new_from_credentials(cred, Some(OfflineInitiator::SERVICE))
+        .request_mutual_auth()
+        .request_encryption()
+}
+
+fn server_builder(mechanism: Mechanism) -> ServerBuilder<Inbound> {
+    ServerBuilder::new_from_credentials(Credentials::inbound(None, mechanism).unwrap())
+}
+
+/// Passes the messages back and forth until both sides finished
+fn exchange(mechanism: SaslMechanism) -> (Negotiated, Negotiated) {
+    let client = client_builder(mechanism.gss_mechanism());
+    let ClientStep::Continue(mut client) = SaslClient::start(client, mechanism, SaslClientConfig::default()).unwrap()
+    else {
+        panic!("mutual authentication needs a reply from the server");
+    };
+    let server = server_builder(mechanism.gss_mechanism());
+    let mut server_step = SaslServer::start(server, mechanism, SaslServerConfig::default(), client.response()).unwrap();
+    loop {
+        match server_step {
+            ServerStep::Continue(server) => match client.step(server.challenge()).unwrap() {
+                ClientStep::Continue(next) => {
+                    server_step = server.step(next.response()).unwrap();
+                    client = next;
+                }
+                ClientStep::Finished(client) => {
+                    let ServerStep::Finished(server) = server.step(client.response().unwrap_or_default()).unwrap()
+                    else {
+                        panic!("the server should finish after the last response");
+                    };
+                    return (client.negotiated(), server.negotiated());
+                }
+            },
+            ServerStep::Finished(server) => {
+                let ClientStep::Finished(client) = client.step(server.challenge().unwrap_or_default()).unwrap() else {
+                    panic!("the client should finish after the last challenge");
+                };
+                return (client.negotiated(), server.negotiated());
+            }
+        }
+    }
+}
+
+#[test]
+fn gssapi_negotiates_confidentiality() {
+    let (client, server) = exchange(SaslMechanism::Gssapi);
+    assert_eq!(client.layer, SecurityLayer::Confidentiality);
+    assert_eq!(client.layer, server.layer);
+}
+
+#[test]
+fn gss_spnego_negotiates_confidentiality() {
+    let (client, server) = exchange(SaslMechanism::GssSpnego);
+    assert_eq!(client.layer, SecurityLayer::Confidentiality);
+    assert_eq!(client.layer, server.layer);
+}
+
+#[test]
+fn rejects_credentials_of_another_mechanism() {
+    let client = client_builder(Mechanism::KerberosV5);
+    assert!(matches!(
+        SaslClient::start(client, SaslMechanism::GssSpnego, SaslClientConfig::default()),
+        Err(SaslError::WrongMechanism(Mechanism::KerberosV5))
+    ));
+    let server = server_builder(Mechanism::Spnego);
+    assert!(matches!(
+        SaslServer::start(server, SaslMechanism::Gssapi, SaslServerConfig::default(), b""),
+        Err(SaslError::WrongMechanism(Mechanism::Spnego))
+    ));
+}
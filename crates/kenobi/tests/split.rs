@@ -0,0 +1,16 @@
+use kenobi::{
+    split::{Opener, Sealer},
+    typestate::{Bound, Encryption, NoEncryption},
+};
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn halves_can_move_between_threads() {
+    assert_send_sync::<Sealer<Encryption>>();
+    assert_send_sync::<Sealer<NoEncryption>>();
+    assert_send_sync::<Opener<Encryption>>();
+    assert_send_sync::<Opener<NoEncryption>>();
+    assert_send_sync::<Sealer<Encryption, Bound>>();
+    assert_send_sync::<Opener<Encryption, Bound>>();
+}
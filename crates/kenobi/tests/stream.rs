@@ -0,0 +1,210 @@
+//! Streams between a client and a server, using a cached ticket for `HTTP/localhost` instead of a KDC
+#![cfg(unix)]
+
+use std::io::{self, Cursor, Read, Write};
+
+use kenobi::{
+    client::{ClientBuilder, ClientContext, StepOut as ClientStep},
+    cred::{Credentials, Inbound, Mechanism, Outbound},
+    server::{ServerBuilder, ServerContext, StepOut as ServerStep},
+    stream::SecureStream,
+    typestate::{Encryption, MaybeDelegation, Signing},
+};
+use kenobi_testkdc::{OfflineInitiator, offline_environment};
+
+type Client = ClientContext<Outbound, Signing, Encryption, MaybeDelegation>;
+type Server = ServerContext<Inbound, Signing, Encryption, MaybeDelegation>;
+
+const MAX_FRAME_LEN: usize = 256;
+
+fn establish() -> (Client, Server) {
+    offline_environment();
+    let credentials = Credentials::outbound(None, Mechanism::KerberosV5).unwrap();
+    let client = ClientBuilder::new_from_credentials(credentials, Some(OfflineInitiator::SERVICE)).request_encryption();
+    let ClientStep::Finished(client) = client.initialize().unwrap() else {
+        panic!("Kerberos without mutual authentication takes a single token");
+    };
+    let server = ServerBuilder::new_from_credentials(Credentials::inbound(None, Mechanism::KerberosV5).unwrap());
+    let ServerStep::Finished(server) = server.initialize(client.last_token().unwrap()).unwrap() else {
+        panic!("the server should finish after the first token");
+    };
+    let (Ok(Ok(client)), Ok(Ok(server))) = (
+        client.check_signing().map(ClientContext::check_encryption),
+        server.check_signing().map(ServerContext::check_encryption),
+    ) else {
+        panic!("encryption was requested");
+    };
+    (client, server)
+}
+
+/// Lengths of the frames in `framed`, checking that they fill it exactly
+fn frame_lengths(mut framed: &[u8]) -> Vec<usize> {
+    let mut lengths = Vec::new();
+    while !framed.is_empty() {
+        let length = u32::from_be_bytes(framed[..4].try_into().unwrap()) as usize;
+        lengths.push(length);
+        framed = &framed[4 + length..];
+    }
+    lengths
+}
+
+fn frame(token: &[u8]) -> Vec<u8> {
+    [&u32::try_from(token.len()).unwrap().to_be_bytes(), token].concat()
+}
+
+#[test]
+fn splits_large_writes_into_frames() {
+    let (client, server) = establish();
+    let message: Vec<u8> = (0..1000u16).map(|i| i as u8).collect();
+    let mut sender = SecureStream::with_max_frame_len(client, Vec::new(), MAX_FRAME_LEN);
+    sender.write_all(&message).unwrap();
+    let (_, framed) = sender.into_parts();
+    let lengths = frame_lengths(&framed);
+    assert!(lengths.len() > 1);
+    assert!(lengths.iter().all(|&length| length <= MAX_FRAME_LEN));
+
+    let mut receiver = SecureStream::with_max_frame_len(server, Cursor::new(framed), MAX_FRAME_LEN);
+    let mut received = Vec::new();
+    receiver.read_to_end(&mut received).unwrap();
+    assert_eq!(received, message);
+}
+
+#[test]
+fn rejects_frames_over_the_limit() {
+    let (_, server) = establish();
+    let header = u32::try_from(MAX_FRAME_LEN + 1).unwrap().to_be_bytes();
+    let mut receiver = SecureStream::with_max_frame_len(server, Cursor::new(header), MAX_FRAME_LEN);
+    let error = receiver.read(&mut [0; 16]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn ends_cleanly_only_between_frames() {
+    let (mut client, server) = establish();
+    let framed = frame(&client.encrypt(b"hello there").unwrap());
+    let mut receiver = SecureStream::with_max_frame_len(server, Cursor::new(framed.clone()), MAX_FRAME_LEN);
+    let mut received = Vec::new();
+    receiver.read_to_end(&mut received).unwrap();
+    assert_eq!(received, b"hello there");
+
+    let (server, _) = receiver.into_parts();
+    let mut truncated =
+        SecureStream::with_max_frame_len(server, Cursor::new(&framed[..framed.len() - 1]), MAX_FRAME_LEN);
+    let error = truncated.read(&mut [0; 16]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+    let (server, _) = truncated.into_parts();
+    let mut truncated = SecureStream::with_max_frame_len(server, Cursor::new(&framed[..2]), MAX_FRAME_LEN);
+    let error = truncated.read(&mut [0; 16]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn encrypting_streams_reject_signed_frames() {
+    let (mut client, server) = establish();
+    let framed = frame(&client.sign(b"only signed").unwrap());
+    let mut receiver = SecureStream::with_max_frame_len(server, Cursor::new(framed), MAX_FRAME_LEN);
+    let error = receiver.read(&mut [0; 16]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[cfg(feature = "tokio")]
+mod asynchronous {
+    use std::io;
+
+    use kenobi::stream::AsyncSecureStream;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, duplex};
+
+    use super::{MAX_FRAME_LEN, establish, frame, frame_lengths};
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn splits_large_writes_into_frames() {
+        let (client, server) = establish();
+        let message: Vec<u8> = (0..1000u16).map(|i| i as u8).collect();
+        block_on(async {
+            let (client_io, server_io) = duplex(4096);
+            let mut sender = AsyncSecureStream::with_max_frame_len(client, client_io, MAX_FRAME_LEN);
+            let mut receiver = AsyncSecureStream::with_max_frame_len(server, server_io, MAX_FRAME_LEN);
+            sender.write_all(&message).await.unwrap();
+            sender.shutdown().await.unwrap();
+            drop(sender);
+            let mut received = Vec::new();
+            receiver.read_to_end(&mut received).await.unwrap();
+            assert_eq!(received, message);
+        });
+    }
+
+    #[test]
+    fn writes_frames_within_the_limit() {
+        let (client, _) = establish();
+        block_on(async {
+            let (client_io, mut peer) = duplex(4096);
+            let mut sender = AsyncSecureStream::with_max_frame_len(client, client_io, MAX_FRAME_LEN);
+            sender.write_all(&[0x2a; 1000]).await.unwrap();
+            sender.shutdown().await.unwrap();
+            drop(sender);
+            let mut framed = Vec::new();
+            peer.read_to_end(&mut framed).await.unwrap();
+            let lengths = frame_lengths(&framed);
+            assert!(lengths.len() > 1);
+            assert!(lengths.iter().all(|&length| length <= MAX_FRAME_LEN));
+        });
+    }
+
+    #[test]
+    fn rejects_frames_over_the_limit() {
+        let (_, server) = establish();
+        block_on(async {
+            let (mut peer, server_io) = duplex(4096);
+            peer.write_all(&u32::try_from(MAX_FRAME_LEN + 1).unwrap().to_be_bytes())
+                .await
+                .unwrap();
+            let mut receiver = AsyncSecureStream::with_max_frame_len(server, server_io, MAX_FRAME_LEN);
+            let error = receiver.read(&mut [0; 16]).await.unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        });
+    }
+
+    #[test]
+    fn ends_cleanly_only_between_frames() {
+        let (mut client, server) = establish();
+        let framed = frame(&client.encrypt(b"hello there").unwrap());
+        block_on(async {
+            let (mut peer, server_io) = duplex(4096);
+            let mut receiver = AsyncSecureStream::with_max_frame_len(server, server_io, MAX_FRAME_LEN);
+            peer.write_all(&framed).await.unwrap();
+            drop(peer);
+            let mut received = Vec::new();
+            receiver.read_to_end(&mut received).await.unwrap();
+            assert_eq!(received, b"hello there");
+
+            let (server, _) = receiver.into_parts();
+            let (mut peer, server_io) = duplex(4096);
+            let mut truncated = AsyncSecureStream::with_max_frame_len(server, server_io, MAX_FRAME_LEN);
+            peer.write_all(&framed[..framed.len() - 1]).await.unwrap();
+            drop(peer);
+            let error = truncated.read(&mut [0; 16]).await.unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        });
+    }
+
+    #[test]
+    fn encrypting_streams_reject_signed_frames() {
+        let (mut client, server) = establish();
+        let framed = frame(&client.sign(b"only signed").unwrap());
+        block_on(async {
+            let (mut peer, server_io) = duplex(4096);
+            peer.write_all(&framed).await.unwrap();
+            let mut receiver = AsyncSecureStream::with_max_frame_len(server, server_io, MAX_FRAME_LEN);
+            let error = receiver.read(&mut [0; 16]).await.unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        });
+    }
+}
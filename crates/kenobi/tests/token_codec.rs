@@ -0,0 +1,26 @@
+#![cfg(feature = "tokio")]
+use bytes::BytesMut;
+use kenobi::tokio::TokenCodec;
+use tokio_util::codec::{Decoder, Encoder};
+
+#[test]
+fn decodes_split_frames() {
+    let mut codec = TokenCodec::default();
+    let mut buffer = BytesMut::new();
+    codec.encode(b"first".as_slice(), &mut buffer).unwrap();
+    codec.encode(b"second".as_slice(), &mut buffer).unwrap();
+
+    let mut input = BytesMut::from(&buffer[..7]);
+    assert_eq!(codec.decode(&mut input).unwrap(), None);
+    input.extend_from_slice(&buffer[7..]);
+    assert_eq!(codec.decode(&mut input).unwrap().as_deref(), Some(b"first".as_slice()));
+    assert_eq!(codec.decode(&mut input).unwrap().as_deref(), Some(b"second".as_slice()));
+    assert_eq!(codec.decode(&mut input).unwrap(), None);
+}
+
+#[test]
+fn refuses_oversized_frame() {
+    let mut codec = TokenCodec::new(4);
+    let mut input = BytesMut::from(b"\0\0\0\x05".as_slice());
+    assert!(codec.decode(&mut input).is_err());
+}
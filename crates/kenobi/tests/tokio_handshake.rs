@@ -0,0 +1,53 @@
+#![cfg(all(unix, feature = "tokio"))]
+use kenobi::{
+    cred::{Credentials, Inbound, Mechanism},
+    handshake::{HandshakeError, Limits},
+    server::{AcceptError, ServerBuilder},
+    tokio::authenticate_server_with_limits,
+};
+use kenobi_testkdc::offline_environment;
+use tokio::io::{AsyncWriteExt, duplex};
+
+fn server() -> ServerBuilder<Inbound> {
+    offline_environment();
+    ServerBuilder::new_from_credentials(Credentials::inbound(None, Mechanism::KerberosV5).unwrap())
+}
+
+/// Runs the server driver against a peer that sent `frames`
+fn accept(frames: &'static [u8], limits: Limits) -> HandshakeError<std::io::Error> {
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    runtime.block_on(async {
+        let (mut client, mut server_io) = duplex(1024);
+        client.write_all(frames).await.unwrap();
+        authenticate_server_with_limits(server(), &mut server_io, limits)
+            .await
+            .err()
+            .expect("the exchange cannot succeed")
+    })
+}
+
+#[test]
+fn refuses_oversized_token() {
+    let limits = Limits {
+        max_token_len: 16,
+        ..Limits::default()
+    };
+    let error = accept(b"\0\0\x01\0", limits);
+    assert!(matches!(error, HandshakeError::TokenTooLarge { len: 256, max: 16 }));
+}
+
+#[test]
+fn stops_after_round_limit() {
+    let limits = Limits {
+        max_rounds: 0,
+        ..Limits::default()
+    };
+    let error = accept(b"\0\0\0\x04abcd", limits);
+    assert!(matches!(error, HandshakeError::TooManyRounds { max: 0 }));
+}
+
+#[test]
+fn passes_whole_token_to_the_context() {
+    let error = accept(b"\0\0\0\x04abcd", Limits::default());
+    assert!(matches!(error, HandshakeError::Accept(AcceptError::DefectiveToken)));
+}
@@ -0,0 +1,103 @@
+#![cfg(feature = "tower")]
+use kenobi::{
+    cred::Inbound,
+    tower::{AuthenticatedPrincipal, Negotiate, NegotiateLayer},
+};
+
+fn assert_send_sync<T: Send + Sync + 'static>() {}
+
+#[test]
+fn usable_in_async_servers() {
+    assert_send_sync::<NegotiateLayer<Inbound>>();
+    assert_send_sync::<Negotiate<(), Inbound>>();
+    assert_send_sync::<AuthenticatedPrincipal>();
+}
+
+/// Exchanges that never reach the inner service, accepted with a keytab instead of a KDC
+#[cfg(unix)]
+mod rejected {
+    use std::{
+        convert::Infallible,
+        future::Ready,
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use http::{
+        Request, Response, StatusCode,
+        header::{AUTHORIZATION, WWW_AUTHENTICATE},
+    };
+    use kenobi::{
+        cred::{Credentials, Mechanism},
+        tower::NegotiateLayer,
+    };
+    use kenobi_testkdc::offline_environment;
+    use tower_layer::Layer;
+    use tower_service::Service;
+
+    #[derive(Clone)]
+    struct Unreachable;
+    impl Service<Request<()>> for Unreachable {
+        type Response = Response<()>;
+        type Error = Infallible;
+        type Future = Ready<Result<Response<()>, Infallible>>;
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, _: Request<()>) -> Self::Future {
+            panic!("unauthenticated requests must not reach the inner service")
+        }
+    }
+
+    fn call(authorization: Option<&str>) -> Response<()> {
+        offline_environment();
+        let credentials = Credentials::inbound(None, Mechanism::KerberosV5).unwrap();
+        let mut service = NegotiateLayer::new(credentials).layer(Unreachable);
+        let mut request = Request::new(());
+        if let Some(authorization) = authorization {
+            request
+                .headers_mut()
+                .insert(AUTHORIZATION, authorization.try_into().unwrap());
+        }
+        let future = pin!(service.call(request));
+        let Poll::Ready(Ok(response)) = future.poll(&mut Context::from_waker(Waker::noop())) else {
+            panic!("rejections are answered immediately");
+        };
+        response
+    }
+
+    fn challenge(response: &Response<()>) -> Option<&str> {
+        response
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .map(|value| value.to_str().unwrap())
+    }
+
+    #[test]
+    fn challenges_without_authorization() {
+        let response = call(None);
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(challenge(&response), Some("Negotiate"));
+    }
+
+    #[test]
+    fn rechallenges_other_schemes() {
+        let response = call(Some("Basic YWxpY2U6aHVudGVyMg=="));
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(challenge(&response), Some("Negotiate"));
+    }
+
+    #[test]
+    fn rejects_malformed_base64() {
+        let response = call(Some("Negotiate a2Vub2J"));
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(challenge(&response), None);
+    }
+
+    #[test]
+    fn rechallenges_rejected_tokens() {
+        let response = call(Some("Kerberos a2Vub2Jp"));
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(challenge(&response), Some("Kerberos"));
+    }
+}
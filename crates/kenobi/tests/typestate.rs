@@ -0,0 +1,31 @@
+use kenobi::{
+    client::{ClientBuilder, ClientContext},
+    cred::Outbound,
+    server::ServerContext,
+    typestate::{Bound, MaybeDelegation, MaybeEncryption, MaybeSigning, Signing, Unbound},
+};
+
+fn assert_send<T: Send>() {}
+
+// Contexts are unbound unless the builder was bound
+fn unbound_by_default(
+    context: ClientContext<Outbound, MaybeSigning, MaybeEncryption, MaybeDelegation>,
+) -> ClientContext<Outbound, MaybeSigning, MaybeEncryption, MaybeDelegation, Unbound> {
+    context
+}
+
+// API boundaries can demand a context bound to the channel
+fn send_over_tls<E, D>(_context: &mut ClientContext<Outbound, Signing, E, D, Bound>)
+where
+    E: kenobi::client::EncryptionState,
+    D: kenobi::client::DelegationState,
+{
+}
+
+#[test]
+fn channel_binding_is_part_of_the_type() {
+    let _ = unbound_by_default;
+    let _ = send_over_tls::<MaybeEncryption, MaybeDelegation>;
+    assert_send::<ClientBuilder<Outbound, Bound>>();
+    assert_send::<ServerContext<kenobi::cred::Inbound, MaybeSigning, MaybeEncryption, MaybeDelegation, Bound>>();
+}